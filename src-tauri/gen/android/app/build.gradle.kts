@@ -67,6 +67,11 @@ android {
     buildFeatures {
         buildConfig = true
     }
+    packaging {
+        // Extracts the native libraries, so that the pluggable transports
+        // shipped among them can be executed.
+        jniLibs.useLegacyPackaging = true
+    }
 }
 
 rust {
@@ -0,0 +1,67 @@
+#!/bin/sh
+# Fetches the pluggable transport binaries from a Tor expert bundle, for
+# bridges using obfs4, webtunnel (lyrebird) or snowflake (snowflake-client).
+#
+#   TOR_VERSION=<Tor Browser release> scripts/fetch-transports.sh <target>...
+#
+# Targets are Rust target triples. Desktop binaries go to `binaries/`, as
+# Tauri sidecars named after the target triple; Android ones go to the
+# native library directory of the app, as `lib<name>.so`, the only place
+# Android lets apps execute files from. Releases are listed on
+# https://dist.torproject.org/torbrowser/.
+set -eu
+
+: "${TOR_VERSION:?set TOR_VERSION to a Tor Browser release}"
+[ "$#" -gt 0 ] || { echo "usage: $0 <target-triple>..." >&2; exit 1; }
+
+cd "$(dirname "$0")/.."
+base_url="https://dist.torproject.org/torbrowser/$TOR_VERSION"
+work_dir="$(mktemp -d)"
+trap 'rm -rf "$work_dir"' EXIT
+
+curl --fail --silent --show-error --location \
+    --output "$work_dir/sha256sums.txt" \
+    "$base_url/sha256sums-signed-build.txt"
+
+for target in "$@"; do
+    suffix=""
+    case "$target" in
+        x86_64-unknown-linux-gnu) bundle=linux-x86_64 ;;
+        i686-unknown-linux-gnu) bundle=linux-i686 ;;
+        x86_64-apple-darwin) bundle=macos-x86_64 ;;
+        aarch64-apple-darwin) bundle=macos-aarch64 ;;
+        x86_64-pc-windows-msvc) bundle=windows-x86_64; suffix=.exe ;;
+        i686-pc-windows-msvc) bundle=windows-i686; suffix=.exe ;;
+        aarch64-linux-android) bundle=android-aarch64; abi=arm64-v8a ;;
+        armv7-linux-androideabi) bundle=android-armv7; abi=armeabi-v7a ;;
+        i686-linux-android) bundle=android-x86; abi=x86 ;;
+        x86_64-linux-android) bundle=android-x86_64; abi=x86_64 ;;
+        *) echo "No Tor expert bundle for $target" >&2; exit 1 ;;
+    esac
+
+    archive="tor-expert-bundle-$bundle-$TOR_VERSION.tar.gz"
+    curl --fail --silent --show-error --location \
+        --output "$work_dir/$archive" "$base_url/$archive"
+    (cd "$work_dir" && grep " $archive\$" sha256sums.txt | sha256sum -c -)
+
+    extract_dir="$work_dir/$target"
+    mkdir -p "$extract_dir"
+    tar -xzf "$work_dir/$archive" -C "$extract_dir"
+
+    for binary in lyrebird snowflake-client; do
+        source="$(find "$extract_dir" -type f -name "$binary$suffix" | head -n 1)"
+        [ -n "$source" ] || { echo "$binary is missing from $archive" >&2; exit 1; }
+
+        case "$target" in
+            *-android*)
+                destination="gen/android/app/src/main/jniLibs/$abi/lib$binary.so"
+                ;;
+            *)
+                destination="binaries/$binary-$target$suffix"
+                ;;
+        esac
+        mkdir -p "$(dirname "$destination")"
+        install -m 755 "$source" "$destination"
+        echo "$destination"
+    done
+done
@@ -121,6 +121,12 @@ impl From<std::io::Error> for TACommandError {
     }
 }
 
+impl From<crate::models::http::HttpError> for TACommandError {
+    fn from(error: crate::models::http::HttpError) -> Self {
+        Self(anyhow::anyhow!(error))
+    }
+}
+
 /// Use this as your command's return type.
 ///
 /// Example usage:
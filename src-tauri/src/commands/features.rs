@@ -0,0 +1,32 @@
+use crate::anyhow_tauri::TAResult;
+use crate::commands::map::get_exchange_file_path;
+use crate::models::features::{self, FeatureFormat, MapFeatures};
+use crate::models::AppState;
+use tauri::{AppHandle, State};
+
+/// Imports a file of the exchange directory, see `get_exchange_dir`.
+#[tauri::command]
+pub async fn import_features(app: AppHandle, file_name: String) -> TAResult<MapFeatures> {
+    Ok(features::import_features(&get_exchange_file_path(
+        &app, &file_name,
+    )?)?)
+}
+
+/// Exports the markers and tracks of a group to a file of the exchange
+/// directory.
+#[tauri::command]
+pub async fn export_features(
+    app: AppHandle,
+    group_id: String,
+    format: FeatureFormat,
+    file_name: String,
+    app_state: State<'_, AppState>,
+) -> TAResult<()> {
+    let group_features = MapFeatures {
+        markers: app_state.groups().markers(&group_id),
+        tracks: app_state.groups().tracks(&group_id),
+    };
+
+    let path = get_exchange_file_path(&app, &file_name)?;
+    Ok(features::export_features(&group_features, format, &path)?)
+}
@@ -0,0 +1,41 @@
+use crate::anyhow_tauri::TAResult;
+use crate::models::group::{Marker, Track};
+use crate::models::AppState;
+use tauri::State;
+
+#[tauri::command]
+pub async fn set_group_markers(
+    group_id: String,
+    markers: Vec<Marker>,
+    app_state: State<'_, AppState>,
+) -> TAResult<()> {
+    app_state.geofences().set_group_markers(&group_id, &markers);
+    app_state.groups().set_markers(&group_id, markers);
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn get_group_markers(
+    group_id: String,
+    app_state: State<'_, AppState>,
+) -> TAResult<Vec<Marker>> {
+    Ok(app_state.groups().markers(&group_id))
+}
+
+#[tauri::command]
+pub async fn set_group_tracks(
+    group_id: String,
+    tracks: Vec<Track>,
+    app_state: State<'_, AppState>,
+) -> TAResult<()> {
+    app_state.groups().set_tracks(&group_id, tracks);
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn get_group_tracks(
+    group_id: String,
+    app_state: State<'_, AppState>,
+) -> TAResult<Vec<Track>> {
+    Ok(app_state.groups().tracks(&group_id))
+}
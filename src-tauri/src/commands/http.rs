@@ -0,0 +1,87 @@
+use std::collections::BTreeMap;
+
+use crate::anyhow_tauri::TAResult;
+use crate::models::http::{HttpError, HttpRequest, HttpResponse};
+use crate::models::network::NetworkSettings;
+use crate::models::tls_pins::TlsPin;
+use crate::models::tor::StreamIsolation;
+use crate::models::AppState;
+use tauri::State;
+
+const DEFAULT_TIMEOUT_MS: u64 = 60_000;
+const DEFAULT_MAX_SIZE: u64 = 16 * 1024 * 1024;
+
+/// Sends a request to an onion service over Tor, so that the frontend can
+/// call localitysrv's API without going through the clearnet. Errors are
+/// returned as an `HttpError`, tagged by `kind`.
+#[tauri::command]
+pub async fn tor_fetch(
+    request: HttpRequest,
+    group_id: Option<String>,
+    app_state: State<'_, AppState>,
+) -> Result<HttpResponse, HttpError> {
+    let request = HttpRequest {
+        timeout_ms: request.timeout_ms.or(Some(DEFAULT_TIMEOUT_MS)),
+        max_size: request.max_size.or(Some(DEFAULT_MAX_SIZE)),
+        ..request
+    };
+
+    app_state
+        .http_client()
+        .send(
+            request,
+            app_state.tor_client(),
+            group_id.map(StreamIsolation::Group),
+            None,
+        )
+        .await
+}
+
+/// Checks that an onion service can be reached, returning how long opening a
+/// stream to it took in milliseconds. Failing circuits are retired.
+#[tauri::command]
+pub async fn probe_onion_service(
+    url: String,
+    group_id: Option<String>,
+    app_state: State<'_, AppState>,
+) -> Result<u64, HttpError> {
+    let elapsed = app_state
+        .http_client()
+        .probe(
+            &url,
+            app_state.tor_client(),
+            group_id.map(StreamIsolation::Group),
+        )
+        .await?;
+    Ok(elapsed.as_millis() as u64)
+}
+
+#[tauri::command]
+pub async fn get_onion_tls_pins(
+    app_state: State<'_, AppState>,
+) -> TAResult<BTreeMap<String, Vec<TlsPin>>> {
+    Ok(app_state.http_client().tls_pins().all())
+}
+
+/// Replaces the TLS pins of an onion service, an empty list unpinning it.
+#[tauri::command]
+pub async fn set_onion_tls_pins(
+    onion_address: String,
+    pins: Vec<TlsPin>,
+    app_state: State<'_, AppState>,
+) -> TAResult<()> {
+    Ok(app_state.http_client().set_tls_pins(&onion_address, pins)?)
+}
+
+#[tauri::command]
+pub async fn get_network_settings(app_state: State<'_, AppState>) -> TAResult<NetworkSettings> {
+    Ok(app_state.http_client().network().settings())
+}
+
+#[tauri::command]
+pub async fn set_network_settings(
+    settings: NetworkSettings,
+    app_state: State<'_, AppState>,
+) -> TAResult<()> {
+    Ok(app_state.http_client().network().set_settings(settings)?)
+}
@@ -0,0 +1,74 @@
+use crate::anyhow_tauri::TAResult;
+use crate::models::http::HttpError;
+use crate::models::localitysrv::{CountrySearchResponse, LocalitySearchResponse, SearchQuery};
+use crate::models::tor::StreamIsolation;
+use crate::models::AppState;
+use tauri::State;
+
+const DEFAULT_PAGE: u32 = 1;
+const DEFAULT_LIMIT: u32 = 10;
+
+#[tauri::command]
+pub async fn get_localitysrv_url(app_state: State<'_, AppState>) -> TAResult<Option<String>> {
+    Ok(app_state.localitysrv().url())
+}
+
+/// Sets the onion URL of localitysrv's HTTP API, `None` clearing it.
+#[tauri::command]
+pub async fn set_localitysrv_url(
+    url: Option<String>,
+    app_state: State<'_, AppState>,
+) -> TAResult<()> {
+    Ok(app_state.localitysrv().set_url(url)?)
+}
+
+/// Searches countries over Tor, for when Waku is unavailable.
+#[tauri::command]
+pub async fn search_countries(
+    query: String,
+    page: Option<u32>,
+    limit: Option<u32>,
+    group_id: Option<String>,
+    app_state: State<'_, AppState>,
+) -> Result<CountrySearchResponse, HttpError> {
+    app_state
+        .localitysrv()
+        .search_countries(
+            app_state.http_client(),
+            app_state.tor_client(),
+            group_id.map(StreamIsolation::Group),
+            &search_query(query, page, limit),
+        )
+        .await
+}
+
+/// Searches the localities of a country over Tor, for when Waku is
+/// unavailable.
+#[tauri::command]
+pub async fn search_localities(
+    country_code: String,
+    query: String,
+    page: Option<u32>,
+    limit: Option<u32>,
+    group_id: Option<String>,
+    app_state: State<'_, AppState>,
+) -> Result<LocalitySearchResponse, HttpError> {
+    app_state
+        .localitysrv()
+        .search_localities(
+            app_state.http_client(),
+            app_state.tor_client(),
+            group_id.map(StreamIsolation::Group),
+            &country_code,
+            &search_query(query, page, limit),
+        )
+        .await
+}
+
+fn search_query(query: String, page: Option<u32>, limit: Option<u32>) -> SearchQuery {
+    SearchQuery {
+        query,
+        page: page.unwrap_or(DEFAULT_PAGE),
+        limit: limit.unwrap_or(DEFAULT_LIMIT),
+    }
+}
@@ -0,0 +1,86 @@
+use crate::anyhow_tauri::TAResult;
+use crate::models::location::{
+    LocationFix, LocationProvider, LocationSource, ManualProvider, ReplayProvider,
+};
+use crate::models::AppState;
+use anyhow::Result;
+use serde::Deserialize;
+use std::path::PathBuf;
+#[cfg(any(target_os = "android", target_os = "ios"))]
+use tauri::AppHandle;
+use tauri::State;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case", tag = "source")]
+pub enum LocationSourceConfig {
+    Gps,
+    Manual {
+        latitude: f64,
+        longitude: f64,
+        accuracy: Option<f64>,
+    },
+    Replay {
+        path: PathBuf,
+        speed: Option<f64>,
+    },
+}
+
+#[cfg(any(target_os = "android", target_os = "ios"))]
+#[tauri::command]
+pub async fn start_location(
+    app: AppHandle,
+    config: LocationSourceConfig,
+    app_state: State<'_, AppState>,
+) -> TAResult<LocationSource> {
+    let provider: Box<dyn LocationProvider> = match config {
+        LocationSourceConfig::Gps => Box::new(crate::models::location::GpsProvider::new(app)),
+        config => new_provider(config)?,
+    };
+    Ok(start_provider(provider, &app_state)?)
+}
+
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+#[tauri::command]
+pub async fn start_location(
+    config: LocationSourceConfig,
+    app_state: State<'_, AppState>,
+) -> TAResult<LocationSource> {
+    Ok(start_provider(new_provider(config)?, &app_state)?)
+}
+
+/// Builds the providers available on every platform.
+fn new_provider(config: LocationSourceConfig) -> Result<Box<dyn LocationProvider>> {
+    Ok(match config {
+        LocationSourceConfig::Gps => {
+            return Err(anyhow::anyhow!("GPS is only available on mobile"));
+        }
+        LocationSourceConfig::Manual {
+            latitude,
+            longitude,
+            accuracy,
+        } => Box::new(ManualProvider::new(latitude, longitude, accuracy)),
+        LocationSourceConfig::Replay { path, speed } => {
+            Box::new(ReplayProvider::from_path(&path, speed.unwrap_or(1.0))?)
+        }
+    })
+}
+
+fn start_provider(
+    provider: Box<dyn LocationProvider>,
+    app_state: &AppState,
+) -> Result<LocationSource> {
+    let source = provider.source();
+    app_state.location().start(provider)?;
+    Ok(source)
+}
+
+#[tauri::command]
+pub async fn stop_location(app_state: State<'_, AppState>) -> TAResult<()> {
+    app_state.location().stop();
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn get_last_location(app_state: State<'_, AppState>) -> TAResult<Option<LocationFix>> {
+    Ok(app_state.location().last_fix())
+}
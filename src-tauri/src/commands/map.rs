@@ -1,65 +1,77 @@
 use pmtiles::{AsyncPmTilesReader, TileCoord};
-use std::io::Write;
-use std::path::PathBuf;
-use tauri::{ipc::Channel, AppHandle, Manager, State};
+use std::collections::BTreeMap;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, Manager, State};
 
 use crate::anyhow_tauri::TAResult;
+use crate::models::download_service::{MapDownload, MapDownloadRequest};
 use crate::models::map::PmtilesMetadata;
+use crate::models::mirrors::MirrorStats;
 use crate::models::AppState;
 
-fn get_pmtiles_dir(app: &AppHandle) -> TAResult<PathBuf> {
+pub fn get_pmtiles_dir(app: &AppHandle) -> TAResult<PathBuf> {
     Ok(app.path().app_data_dir()?.join("pmtiles"))
 }
 
-fn get_pmtiles_file_path(app: &AppHandle, locality_id: &str) -> TAResult<PathBuf> {
-    Ok(get_pmtiles_dir(app)?.join(format!("{}.pmtiles", locality_id)))
+/// Directory of the files imported into or exported from the app, which the
+/// webview moves in and out of it with the fs plugin.
+pub fn get_exchange_dir(app: &AppHandle) -> TAResult<PathBuf> {
+    Ok(app.path().app_data_dir()?.join("exchange"))
+}
+
+/// Path of a file of the exchange directory, refusing anything but a plain
+/// file name so that commands cannot reach outside of it.
+pub fn get_exchange_file_path(app: &AppHandle, file_name: &str) -> TAResult<PathBuf> {
+    let is_plain_name = Path::new(file_name).file_name() == Some(OsStr::new(file_name));
+    if !is_plain_name {
+        return Err(anyhow::anyhow!("Invalid file name: {}", file_name).into());
+    }
+
+    let dir = get_exchange_dir(app)?;
+    std::fs::create_dir_all(&dir)?;
+    Ok(dir.join(file_name))
 }
 
-#[derive(Clone, serde::Serialize)]
-#[serde(rename_all = "camelCase", tag = "event", content = "data")]
-pub enum DownloadEvent {
-    Progress { chunk_length: usize },
-    Finished {},
+fn get_pmtiles_file_path(app: &AppHandle, locality_id: &str) -> TAResult<PathBuf> {
+    Ok(get_pmtiles_dir(app)?.join(format!("{}.pmtiles", locality_id)))
 }
 
+/// Starts downloading the map of a locality in the background, or returns
+/// the download in progress. Its progress is sent as `map-download` events.
 #[tauri::command]
 pub async fn download_map(
     app: AppHandle,
-    onion_link: String,
-    locality_id: String,
-    on_event: Channel<DownloadEvent>,
+    request: MapDownloadRequest,
     app_state: State<'_, AppState>,
-) -> TAResult<()> {
-    let url = onion_link;
-
-    let pmtiles_dir = get_pmtiles_dir(&app)?;
-    std::fs::create_dir_all(&pmtiles_dir)?;
-    let file_path = get_pmtiles_file_path(&app, &locality_id)?;
-    let mut file = std::fs::File::create(&file_path)?;
-
-    let on_event_clone = on_event.clone();
-    let callback = std::sync::Arc::new(tokio::sync::Mutex::new(Box::new(
-        move |chunk_length: usize| {
-            if let Err(e) = on_event_clone.send(DownloadEvent::Progress { chunk_length }) {
-                eprintln!("Failed to send progress event: {}", e);
-            }
-        },
-    )
-        as Box<dyn Fn(usize) + Send + Sync>));
-
-    let (body_bytes, status) = app_state
-        .http_client()
-        .get(&url, app_state.tor_client(), Some(callback))
-        .await?;
+) -> TAResult<MapDownload> {
+    std::fs::create_dir_all(get_pmtiles_dir(&app)?)?;
+    let file_path = get_pmtiles_file_path(&app, &request.locality_id)?;
+    Ok(app_state.downloads().start(request, file_path)?)
+}
 
-    if !hyper::StatusCode::from_u16(status)?.is_success() {
-        return Err(anyhow::anyhow!("Request failed with status: {}", status).into());
-    }
+/// Lists the downloads in progress or finished, for the UI to catch up
+/// with them when it comes back.
+#[tauri::command]
+pub async fn get_map_downloads(app_state: State<'_, AppState>) -> TAResult<Vec<MapDownload>> {
+    Ok(app_state.downloads().downloads())
+}
 
-    file.write_all(&body_bytes)?;
-    on_event.send(DownloadEvent::Finished {})?;
+/// Stops a download and removes its partial file, or dismisses a finished
+/// one.
+#[tauri::command]
+pub async fn cancel_map_download(
+    locality_id: String,
+    app_state: State<'_, AppState>,
+) -> TAResult<bool> {
+    Ok(app_state.downloads().cancel(&locality_id).await)
+}
 
-    Ok(())
+#[tauri::command]
+pub async fn get_mirror_stats(
+    app_state: State<'_, AppState>,
+) -> TAResult<BTreeMap<String, MirrorStats>> {
+    Ok(app_state.mirrors().stats())
 }
 
 #[tauri::command]
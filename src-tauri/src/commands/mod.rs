@@ -1,5 +1,19 @@
+pub mod features;
+pub mod group;
+pub mod http;
+pub mod localitysrv;
+pub mod location;
 pub mod map;
+pub mod privacy;
+pub mod routing;
 pub mod tor;
 
+pub use features::*;
+pub use group::*;
+pub use http::*;
+pub use localitysrv::*;
+pub use location::*;
 pub use map::*;
+pub use privacy::*;
+pub use routing::*;
 pub use tor::*;
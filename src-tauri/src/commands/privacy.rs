@@ -0,0 +1,57 @@
+use crate::anyhow_tauri::TAResult;
+use crate::commands::map::get_pmtiles_dir;
+use crate::models::group::Marker;
+use crate::models::location::now_millis;
+use crate::models::privacy::{LocationPrivacySettings, Publication};
+use crate::models::AppState;
+use tauri::{AppHandle, Emitter, State};
+
+#[tauri::command]
+pub async fn get_location_privacy(
+    app_state: State<'_, AppState>,
+) -> TAResult<LocationPrivacySettings> {
+    Ok(app_state.privacy().settings())
+}
+
+#[tauri::command]
+pub async fn set_location_privacy(
+    settings: LocationPrivacySettings,
+    app_state: State<'_, AppState>,
+) -> TAResult<()> {
+    Ok(app_state.privacy().set_settings(settings)?)
+}
+
+/// Applies the location privacy settings to a marker and hands it back to
+/// the webview through a `location-publish` event once its delay is over.
+/// The messaging layer must only send markers received from that event.
+#[tauri::command]
+pub async fn publish_marker(
+    app: AppHandle,
+    group_id: String,
+    mut marker: Marker,
+    app_state: State<'_, AppState>,
+) -> TAResult<Publication> {
+    let privacy = app_state.privacy();
+    let coordinate = privacy
+        .apply(marker.coordinate(), &get_pmtiles_dir(&app)?)
+        .await?;
+    marker.latitude = coordinate.latitude;
+    marker.longitude = coordinate.longitude;
+
+    let delay = privacy.settings().delay();
+    let publication = Publication {
+        group_id,
+        marker,
+        publish_at: now_millis() + delay.as_millis() as u64,
+    };
+
+    let pending = publication.clone();
+    tauri::async_runtime::spawn(async move {
+        tokio::time::sleep(delay).await;
+        if let Err(e) = app.emit("location-publish", pending) {
+            eprintln!("Failed to emit location publication: {}", e);
+        }
+    });
+
+    Ok(publication)
+}
@@ -0,0 +1,57 @@
+use crate::anyhow_tauri::TAResult;
+use crate::commands::map::get_pmtiles_dir;
+use crate::models::geo::{Coordinate, LocalProjection};
+use crate::models::road_graph::RoadGraph;
+use crate::models::routing::{AvoidanceZone, RouteResult, Router};
+use crate::models::AppState;
+use tauri::{AppHandle, State};
+
+// Roads are read this far around the start and destination, so that
+// detours around the zones stay on the map.
+const MIN_ROAD_MARGIN_METERS: f64 = 1000.0;
+
+#[tauri::command]
+pub async fn compute_route(
+    app: AppHandle,
+    group_id: Option<String>,
+    start: Coordinate,
+    end: Coordinate,
+    avoid: Option<Vec<AvoidanceZone>>,
+    app_state: State<'_, AppState>,
+) -> TAResult<RouteResult> {
+    let mut zones = avoid.unwrap_or_default();
+    if let Some(group_id) = group_id {
+        zones.extend(
+            app_state
+                .groups()
+                .markers(&group_id)
+                .iter()
+                .filter_map(AvoidanceZone::from_marker),
+        );
+    }
+
+    let margin = (start.distance_to(&end) * 0.25).max(MIN_ROAD_MARGIN_METERS);
+    let south_west = LocalProjection::new(Coordinate::new(
+        start.latitude.min(end.latitude),
+        start.longitude.min(end.longitude),
+    ))
+    .unproject(-margin, -margin);
+    let north_east = LocalProjection::new(Coordinate::new(
+        start.latitude.max(end.latitude),
+        start.longitude.max(end.longitude),
+    ))
+    .unproject(margin, margin);
+    let roads = RoadGraph::load(&get_pmtiles_dir(&app)?, &south_west, &north_east)
+        .await
+        .unwrap_or_else(|e| {
+            eprintln!("Failed to read roads for routing: {}", e);
+            RoadGraph::new()
+        });
+
+    let router = Router::new(zones).with_roads(roads);
+    Ok(
+        tokio::task::spawn_blocking(move || router.compute_route(start, end))
+            .await
+            .map_err(|e| anyhow::anyhow!(e))?,
+    )
+}
@@ -1,21 +1,228 @@
 use crate::anyhow_tauri::TAResult;
+use crate::commands::map::{get_exchange_file_path, get_pmtiles_dir};
+use crate::models::bridges::BridgeSettings;
+use crate::models::key_vault::{AuthorizedClient, ClientAuthKey};
+use crate::models::tor_bundle::TorStateBundle;
+use crate::models::tor_status::TorStatus;
 use crate::models::AppState;
 use tauri::{AppHandle, State};
 
 #[tauri::command]
-pub async fn bootstrap_tor(app: AppHandle, app_state: State<'_, AppState>) -> TAResult<bool> {
+pub async fn bootstrap_tor(app_state: State<'_, AppState>) -> TAResult<bool> {
     let client = app_state.tor_client();
 
     if client.is_ready() {
         return Ok(true);
     }
 
-    client.listen_bootstrap_events(app.clone()).await?;
     client.bootstrap().await?;
     Ok(false)
 }
 
+#[tauri::command]
+pub async fn cancel_tor_bootstrap(app_state: State<'_, AppState>) -> TAResult<()> {
+    app_state.tor_client().cancel_bootstrap();
+    Ok(())
+}
+
 #[tauri::command]
 pub async fn is_tor_ready(app_state: State<'_, AppState>) -> TAResult<bool> {
     Ok(app_state.tor_client().is_ready())
 }
+
+#[tauri::command]
+pub async fn get_tor_status(app_state: State<'_, AppState>) -> TAResult<TorStatus> {
+    Ok(app_state.tor_client().status())
+}
+
+#[tauri::command]
+pub async fn get_tor_bridges(app_state: State<'_, AppState>) -> TAResult<BridgeSettings> {
+    Ok(app_state.tor_client().bridge_settings())
+}
+
+#[tauri::command]
+pub async fn set_tor_bridges(
+    settings: BridgeSettings,
+    app_state: State<'_, AppState>,
+) -> TAResult<BridgeSettings> {
+    Ok(app_state.tor_client().set_bridges(settings).await?)
+}
+
+#[tauri::command]
+pub async fn reconfigure_tor(app_state: State<'_, AppState>) -> TAResult<()> {
+    Ok(app_state.tor_client().reconfigure().await?)
+}
+
+#[tauri::command]
+pub async fn restart_tor(app_state: State<'_, AppState>) -> TAResult<()> {
+    Ok(app_state.tor_client().restart().await?)
+}
+
+#[tauri::command]
+pub async fn shutdown_tor(app_state: State<'_, AppState>) -> TAResult<()> {
+    app_state.tor_client().shutdown();
+    Ok(())
+}
+
+/// Exports the Tor directory cache to a file of the exchange directory, as a
+/// bundle other devices can import to bootstrap faster. Guards are left out
+/// unless asked for, as devices sharing them can be linked to each other.
+#[tauri::command]
+pub async fn export_tor_state(
+    app: AppHandle,
+    file_name: String,
+    include_guards: Option<bool>,
+    app_state: State<'_, AppState>,
+) -> TAResult<TorStateBundle> {
+    let path = get_exchange_file_path(&app, &file_name)?;
+    Ok(app_state
+        .tor_client()
+        .export_state(&path, include_guards.unwrap_or(false))?)
+}
+
+/// Imports a bundle from a file of the exchange directory, see
+/// `get_exchange_dir`.
+#[tauri::command]
+pub async fn import_tor_state(
+    app: AppHandle,
+    file_name: String,
+    app_state: State<'_, AppState>,
+) -> TAResult<TorStateBundle> {
+    let path = get_exchange_file_path(&app, &file_name)?;
+    Ok(app_state.tor_client().import_state(&path).await?)
+}
+
+/// Starts the loopback SOCKS5 proxy and returns its `host:port` address.
+#[tauri::command]
+pub async fn start_socks_proxy(
+    port: Option<u16>,
+    app_state: State<'_, AppState>,
+) -> TAResult<String> {
+    let address = app_state
+        .socks_proxy()
+        .start(app_state.tor_client().clone(), port.unwrap_or(0))
+        .await?;
+    Ok(address.to_string())
+}
+
+#[tauri::command]
+pub async fn stop_socks_proxy(app_state: State<'_, AppState>) -> TAResult<()> {
+    app_state.socks_proxy().stop();
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn get_socks_proxy(app_state: State<'_, AppState>) -> TAResult<Option<String>> {
+    Ok(app_state
+        .socks_proxy()
+        .address()
+        .map(|address| address.to_string()))
+}
+
+/// Starts hosting the onion service and returns its `.onion` address.
+#[tauri::command]
+pub async fn start_onion_service(
+    app: AppHandle,
+    app_state: State<'_, AppState>,
+) -> TAResult<String> {
+    Ok(app_state
+        .onion_service()
+        .start(app_state.tor_client(), get_pmtiles_dir(&app)?)?)
+}
+
+#[tauri::command]
+pub async fn stop_onion_service(app_state: State<'_, AppState>) -> TAResult<()> {
+    app_state.onion_service().stop();
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn get_onion_service_address(app_state: State<'_, AppState>) -> TAResult<Option<String>> {
+    Ok(app_state.onion_service().address())
+}
+
+#[tauri::command]
+pub async fn get_onion_authorized_clients(
+    app_state: State<'_, AppState>,
+) -> TAResult<Vec<AuthorizedClient>> {
+    Ok(app_state
+        .tor_client()
+        .authorized_clients()?
+        .into_iter()
+        .map(|(nickname, public_key)| AuthorizedClient {
+            nickname,
+            public_key: public_key.to_string(),
+        })
+        .collect())
+}
+
+/// Allows a client to discover the hosted onion service. The public key is
+/// the one generated by the client with `generate_onion_client_key`.
+#[tauri::command]
+pub async fn authorize_onion_client(
+    nickname: String,
+    public_key: String,
+    app_state: State<'_, AppState>,
+) -> TAResult<AuthorizedClient> {
+    let client = app_state
+        .tor_client()
+        .authorize_client(&nickname, &public_key)?;
+    app_state
+        .onion_service()
+        .reload_clients(app_state.tor_client())?;
+    Ok(client)
+}
+
+/// Returns whether the client was authorized. The service is taken down
+/// when no client is left.
+#[tauri::command]
+pub async fn revoke_onion_client(
+    nickname: String,
+    app_state: State<'_, AppState>,
+) -> TAResult<bool> {
+    let revoked = app_state.tor_client().revoke_client(&nickname)?;
+    app_state
+        .onion_service()
+        .reload_clients(app_state.tor_client())?;
+    Ok(revoked)
+}
+
+#[tauri::command]
+pub async fn get_onion_client_keys(app_state: State<'_, AppState>) -> TAResult<Vec<ClientAuthKey>> {
+    Ok(app_state.tor_client().client_auth_keys()?)
+}
+
+/// Generates a client authorization key for a restricted discovery service.
+/// The returned public key is to be added to the service by its operator.
+#[tauri::command]
+pub async fn generate_onion_client_key(
+    onion_address: String,
+    app_state: State<'_, AppState>,
+) -> TAResult<ClientAuthKey> {
+    Ok(app_state
+        .tor_client()
+        .generate_client_auth_key(&onion_address)?)
+}
+
+/// Imports a client authorization key, such as a line of a C Tor
+/// `.auth_private` file.
+#[tauri::command]
+pub async fn import_onion_client_key(
+    onion_address: Option<String>,
+    key: String,
+    app_state: State<'_, AppState>,
+) -> TAResult<ClientAuthKey> {
+    Ok(app_state
+        .tor_client()
+        .import_client_auth_key(onion_address.as_deref(), &key)?)
+}
+
+#[tauri::command]
+pub async fn remove_onion_client_key(
+    onion_address: String,
+    app_state: State<'_, AppState>,
+) -> TAResult<bool> {
+    Ok(app_state
+        .tor_client()
+        .remove_client_auth_key(&onion_address)?)
+}
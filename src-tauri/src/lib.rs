@@ -55,6 +55,7 @@ fn try_run() -> Result<()> {
         .plugin(tauri_plugin_store::Builder::new().build())
         .plugin(tauri_plugin_http::init())
         .plugin(tauri_plugin_opener::init())
+        .plugin(tauri_plugin_notification::init())
         .setup(|app| {
             let app_state = AppState::new(app.handle().clone())?;
             app.manage(app_state);
@@ -62,15 +63,65 @@ fn try_run() -> Result<()> {
         })
         .invoke_handler(tauri::generate_handler![
             commands::download_map,
+            commands::get_map_downloads,
+            commands::cancel_map_download,
             commands::get_pmtiles_header,
             commands::get_pmtiles_tile,
+            commands::get_mirror_stats,
+            commands::tor_fetch,
+            commands::get_network_settings,
+            commands::set_network_settings,
+            commands::get_localitysrv_url,
+            commands::set_localitysrv_url,
+            commands::search_countries,
+            commands::search_localities,
+            commands::probe_onion_service,
+            commands::get_onion_tls_pins,
+            commands::set_onion_tls_pins,
             commands::bootstrap_tor,
             commands::is_tor_ready,
+            commands::cancel_tor_bootstrap,
+            commands::get_tor_status,
+            commands::get_tor_bridges,
+            commands::set_tor_bridges,
+            commands::reconfigure_tor,
+            commands::restart_tor,
+            commands::shutdown_tor,
+            commands::export_tor_state,
+            commands::import_tor_state,
+            commands::start_socks_proxy,
+            commands::stop_socks_proxy,
+            commands::get_socks_proxy,
+            commands::start_onion_service,
+            commands::stop_onion_service,
+            commands::get_onion_service_address,
+            commands::get_onion_authorized_clients,
+            commands::authorize_onion_client,
+            commands::revoke_onion_client,
+            commands::get_onion_client_keys,
+            commands::generate_onion_client_key,
+            commands::import_onion_client_key,
+            commands::remove_onion_client_key,
+            commands::set_group_markers,
+            commands::get_group_markers,
+            commands::set_group_tracks,
+            commands::get_group_tracks,
+            commands::compute_route,
+            commands::start_location,
+            commands::stop_location,
+            commands::get_last_location,
+            commands::get_location_privacy,
+            commands::set_location_privacy,
+            commands::publish_marker,
+            commands::import_features,
+            commands::export_features,
         ]);
 
     #[cfg(any(target_os = "android", target_os = "ios"))]
     {
-        builder = builder.plugin(tauri_plugin_barcode_scanner::init());
+        builder = builder
+            .plugin(tauri_plugin_barcode_scanner::init())
+            .plugin(tauri_plugin_geolocation::init());
     }
 
     builder.run(tauri::generate_context!())?;
@@ -1,18 +1,55 @@
+use crate::commands::map::get_pmtiles_dir;
+use crate::models::download_service::DownloadService;
+use crate::models::geofence::GeofenceEngine;
+use crate::models::group::GroupRegistry;
 use crate::models::http::HttpClient;
+use crate::models::localitysrv::LocalitysrvClient;
+use crate::models::location::LocationService;
+use crate::models::mirrors::MirrorRegistry;
+use crate::models::onion_service::OnionServiceHost;
+use crate::models::privacy::LocationPrivacy;
+use crate::models::socks::SocksProxy;
 use crate::models::tor::TorClientWrapper;
 use anyhow::Result;
+use std::sync::Arc;
 use tauri::AppHandle;
 
 pub struct AppState {
     tor_client: TorClientWrapper,
     http_client: HttpClient,
+    mirrors: MirrorRegistry,
+    localitysrv: LocalitysrvClient,
+    downloads: DownloadService,
+    groups: GroupRegistry,
+    location: LocationService,
+    privacy: Arc<LocationPrivacy>,
+    geofences: GeofenceEngine,
+    socks_proxy: SocksProxy,
+    onion_service: OnionServiceHost,
 }
 
 impl AppState {
     pub fn new(app_handle: AppHandle) -> Result<Self> {
+        let privacy = Arc::new(LocationPrivacy::new(app_handle.clone()));
+        let location = LocationService::new(
+            app_handle.clone(),
+            privacy.clone(),
+            get_pmtiles_dir(&app_handle).unwrap_or_default(),
+        );
+        let geofences = GeofenceEngine::new(app_handle.clone(), location.subscribe());
+
         Ok(Self {
-            tor_client: TorClientWrapper::new(app_handle),
-            http_client: HttpClient::new(),
+            tor_client: TorClientWrapper::new(app_handle.clone()),
+            http_client: HttpClient::new(app_handle.clone()),
+            mirrors: MirrorRegistry::new(app_handle.clone()),
+            localitysrv: LocalitysrvClient::new(app_handle.clone()),
+            downloads: DownloadService::new(app_handle.clone()),
+            groups: GroupRegistry::new(),
+            location,
+            privacy,
+            geofences,
+            socks_proxy: SocksProxy::new(),
+            onion_service: OnionServiceHost::new(),
         })
     }
 
@@ -23,4 +60,40 @@ impl AppState {
     pub fn http_client(&self) -> &HttpClient {
         &self.http_client
     }
+
+    pub fn mirrors(&self) -> &MirrorRegistry {
+        &self.mirrors
+    }
+
+    pub fn localitysrv(&self) -> &LocalitysrvClient {
+        &self.localitysrv
+    }
+
+    pub fn downloads(&self) -> &DownloadService {
+        &self.downloads
+    }
+
+    pub fn groups(&self) -> &GroupRegistry {
+        &self.groups
+    }
+
+    pub fn location(&self) -> &LocationService {
+        &self.location
+    }
+
+    pub fn privacy(&self) -> &LocationPrivacy {
+        &self.privacy
+    }
+
+    pub fn geofences(&self) -> &GeofenceEngine {
+        &self.geofences
+    }
+
+    pub fn socks_proxy(&self) -> &SocksProxy {
+        &self.socks_proxy
+    }
+
+    pub fn onion_service(&self) -> &OnionServiceHost {
+        &self.onion_service
+    }
 }
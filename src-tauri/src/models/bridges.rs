@@ -0,0 +1,220 @@
+use anyhow::Result;
+use arti_client::config::pt::TransportConfigBuilder;
+use arti_client::config::{BridgeConfigBuilder, CfgPath, TorClientConfigBuilder};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use tauri::AppHandle;
+
+use crate::models::settings::{load_setting, save_setting};
+
+const SETTINGS_KEY: &str = "tor_bridges";
+
+/// Pluggable transport binaries bundled with the app, see
+/// `scripts/fetch-transports.sh`, and the transports each of them provides.
+const PLUGGABLE_TRANSPORTS: [(&str, &[&str]); 2] = [
+    ("lyrebird", &["obfs4", "webtunnel"]),
+    ("snowflake-client", &["snowflake"]),
+];
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BridgeSettings {
+    pub enabled: bool,
+    /// Bridge lines, with or without the leading `Bridge` keyword.
+    #[serde(default)]
+    pub bridges: Vec<String>,
+}
+
+impl BridgeSettings {
+    pub fn load(app_handle: &AppHandle) -> Self {
+        load_setting(app_handle, SETTINGS_KEY)
+            .unwrap_or_else(|e| {
+                eprintln!("Failed to load Tor bridge settings: {}", e);
+                None
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, app_handle: &AppHandle) -> Result<()> {
+        save_setting(app_handle, SETTINGS_KEY, self)
+    }
+
+    /// Checks every bridge line and returns the settings with blank lines
+    /// and comments removed.
+    pub fn validate(self) -> Result<Self> {
+        let mut bridges = Vec::new();
+        for (index, line) in self.bridges.iter().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            parse_bridge_line(line).map_err(|e| anyhow::anyhow!("Bridge {}: {}", index + 1, e))?;
+            bridges.push(line.to_string());
+        }
+
+        if self.enabled && bridges.is_empty() {
+            return Err(anyhow::anyhow!(
+                "Bridges are enabled but none are configured"
+            ));
+        }
+
+        Ok(Self {
+            enabled: self.enabled,
+            bridges,
+        })
+    }
+
+    /// Adds the bridges, and the transports they need, to a client config.
+    pub fn apply(&self, builder: &mut TorClientConfigBuilder) -> Result<()> {
+        if !self.enabled {
+            return Ok(());
+        }
+
+        let mut binaries = Vec::new();
+        for line in &self.bridges {
+            let bridge = parse_bridge_line(line)?;
+            if let Some(binary) = bridge.get_transport().and_then(transport_binary) {
+                if !binaries.contains(&binary) {
+                    binaries.push(binary);
+                }
+            }
+            builder.bridges().bridges().push(bridge);
+        }
+
+        for (binary, protocols) in PLUGGABLE_TRANSPORTS {
+            if !binaries.contains(&binary) {
+                continue;
+            }
+
+            let mut transport = TransportConfigBuilder::default();
+            transport
+                .protocols(
+                    protocols
+                        .iter()
+                        .map(|protocol| protocol.parse())
+                        .collect::<Result<_, _>>()?,
+                )
+                .path(CfgPath::new_literal(transport_binary_path(binary)?))
+                // Started when a bridge needs it, not with the client.
+                .run_on_startup(false);
+            builder.bridges().transports().push(transport);
+        }
+
+        Ok(())
+    }
+}
+
+/// Parses a bridge line, rejecting transports the app cannot run.
+pub fn parse_bridge_line(line: &str) -> Result<BridgeConfigBuilder> {
+    let bridge: BridgeConfigBuilder = line.trim().parse()?;
+    bridge.build()?;
+
+    match bridge.get_transport() {
+        None | Some("") | Some("-") | Some("bridge") => {}
+        Some(transport) => {
+            let binary = transport_binary(transport)
+                .ok_or_else(|| anyhow::anyhow!("Unsupported transport {}", transport))?;
+            let path = transport_binary_path(binary)?;
+            if !path.is_file() {
+                return Err(anyhow::anyhow!(
+                    "Transport {} is not available, {} is missing",
+                    transport,
+                    path.display()
+                ));
+            }
+        }
+    }
+
+    Ok(bridge)
+}
+
+fn transport_binary(transport: &str) -> Option<&'static str> {
+    PLUGGABLE_TRANSPORTS
+        .iter()
+        .find(|(_, protocols)| protocols.contains(&transport))
+        .map(|(binary, _)| *binary)
+}
+
+/// Desktop bundles ship the transports as Tauri sidecars, next to the
+/// executable.
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+fn transport_binary_path(binary: &str) -> Result<PathBuf> {
+    let executable = std::env::current_exe()?;
+    let directory = executable
+        .parent()
+        .ok_or_else(|| anyhow::anyhow!("Failed to get the executable directory"))?;
+    Ok(directory.join(format!("{}{}", binary, std::env::consts::EXE_SUFFIX)))
+}
+
+/// Android only lets apps execute the files of their native library
+/// directory, so the transports ship there as `lib<name>.so`, next to the
+/// library of the app itself.
+#[cfg(target_os = "android")]
+fn transport_binary_path(binary: &str) -> Result<PathBuf> {
+    let library = format!("lib{}.so", env!("CARGO_CRATE_NAME"));
+    let maps = std::fs::read_to_string("/proc/self/maps")?;
+    let directory = maps
+        .lines()
+        .filter_map(|line| line.split_whitespace().nth(5))
+        .map(std::path::Path::new)
+        .find(|path| path.file_name().is_some_and(|name| *name == *library))
+        .and_then(|path| path.parent())
+        .ok_or_else(|| anyhow::anyhow!("Failed to find the native library directory"))?;
+    Ok(directory.join(format!("lib{}.so", binary)))
+}
+
+/// iOS does not let apps start other processes.
+#[cfg(target_os = "ios")]
+fn transport_binary_path(binary: &str) -> Result<PathBuf> {
+    Err(anyhow::anyhow!(
+        "{} cannot run on iOS, which does not allow starting other processes",
+        binary
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PLAIN_BRIDGE: &str = "192.0.2.1:443 0123456789ABCDEF0123456789ABCDEF01234567";
+
+    #[test]
+    fn keeps_bridge_lines_without_comments() {
+        let settings = BridgeSettings {
+            enabled: true,
+            bridges: vec![
+                "# from the bridge distributor".to_string(),
+                String::new(),
+                format!("Bridge {}", PLAIN_BRIDGE),
+            ],
+        }
+        .validate()
+        .unwrap();
+        assert_eq!(settings.bridges, vec![format!("Bridge {}", PLAIN_BRIDGE)]);
+    }
+
+    #[test]
+    fn maps_transports_to_their_binary() {
+        assert_eq!(transport_binary("obfs4"), Some("lyrebird"));
+        assert_eq!(transport_binary("webtunnel"), Some("lyrebird"));
+        assert_eq!(transport_binary("snowflake"), Some("snowflake-client"));
+        assert_eq!(transport_binary("meek"), None);
+
+        // No transport binary sits next to the test executable.
+        let obfs4 = format!("obfs4 {} cert=AAAA iat-mode=0", PLAIN_BRIDGE);
+        let error = parse_bridge_line(&obfs4).unwrap_err().to_string();
+        assert!(error.contains("lyrebird"), "{}", error);
+        let meek = format!("meek {} url=https://example.com/", PLAIN_BRIDGE);
+        assert!(parse_bridge_line(&meek).is_err());
+    }
+
+    #[test]
+    fn requires_bridges_when_enabled() {
+        let settings = BridgeSettings {
+            enabled: true,
+            bridges: vec!["# none yet".to_string()],
+        };
+        assert!(settings.validate().is_err());
+        assert!(BridgeSettings::default().validate().is_ok());
+    }
+}
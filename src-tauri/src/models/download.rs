@@ -0,0 +1,764 @@
+//! Download of large files over several circuits at once. A single circuit
+//! caps the throughput, so files served with range support are fetched as
+//! segments spread over isolated circuits, and written in place.
+
+use bytes::Bytes;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, Mutex as TokioMutex};
+
+use crate::models::http::{
+    BodyProgress, HttpClient, HttpError, HttpRequest, HttpResponse, ProgressCallback,
+};
+use crate::models::tor::{StreamIsolation, TorClientWrapper};
+
+const SEGMENT_SIZE: u64 = 4 * 1024 * 1024;
+// Largest file accepted when its size is not known beforehand, well above
+// the map of any locality.
+const MAX_FILE_SIZE: u64 = 16 * 1024 * 1024 * 1024;
+// Circuits used at once, the first one being the circuit of the caller.
+const MAX_CIRCUITS: usize = 4;
+// Attempts of a segment, each over the next circuit of its worker.
+const SEGMENT_ATTEMPTS: usize = 3;
+// Least time between two progress reports of the same phase.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+// Time over which the throughput is averaged.
+const RATE_WINDOW: Duration = Duration::from_secs(5);
+// Frames of the first response waiting to be written, and bytes written at
+// once.
+const BUFFERED_FRAMES: usize = 64;
+const WRITE_SIZE: usize = 1024 * 1024;
+
+pub type DownloadProgress = Arc<ProgressTracker>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DownloadPhase {
+    Connecting,
+    Downloading,
+    Verifying,
+    Finalizing,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DownloadStatus {
+    pub phase: DownloadPhase,
+    pub received: u64,
+    /// Size of the file, once the service told it.
+    pub total: Option<u64>,
+    pub bytes_per_second: f64,
+    pub eta_seconds: Option<f64>,
+}
+
+struct TrackerState {
+    phase: DownloadPhase,
+    received: u64,
+    total: Option<u64>,
+    /// Bytes received by the given times, within the rate window.
+    samples: VecDeque<(Instant, u64)>,
+    reported_at: Option<Instant>,
+}
+
+impl TrackerState {
+    fn status(&self) -> DownloadStatus {
+        let bytes_per_second = match (self.samples.front(), self.samples.back()) {
+            (Some((first_at, first)), Some((last_at, last))) if last_at > first_at => {
+                (last - first) as f64 / last_at.duration_since(*first_at).as_secs_f64()
+            }
+            _ => 0.0,
+        };
+        let eta_seconds = self
+            .total
+            .filter(|_| bytes_per_second > 0.0)
+            .map(|total| total.saturating_sub(self.received) as f64 / bytes_per_second);
+
+        DownloadStatus {
+            phase: self.phase,
+            received: self.received,
+            total: self.total,
+            bytes_per_second,
+            eta_seconds,
+        }
+    }
+}
+
+/// Sums up the progress of every stream of a download, and reports it at
+/// most every `PROGRESS_INTERVAL`, phase changes aside.
+pub struct ProgressTracker {
+    state: Mutex<TrackerState>,
+    report: Box<dyn Fn(DownloadStatus) + Send + Sync>,
+}
+
+impl ProgressTracker {
+    pub fn new(report: impl Fn(DownloadStatus) + Send + Sync + 'static) -> Self {
+        Self {
+            state: Mutex::new(TrackerState {
+                phase: DownloadPhase::Connecting,
+                received: 0,
+                total: None,
+                samples: VecDeque::new(),
+                reported_at: None,
+            }),
+            report: Box::new(report),
+        }
+    }
+
+    pub fn status(&self) -> Option<DownloadStatus> {
+        self.state.lock().ok().map(|state| state.status())
+    }
+
+    /// Starts over, for another attempt at the download.
+    pub fn restart(&self) {
+        self.update(|state| {
+            state.phase = DownloadPhase::Connecting;
+            state.received = 0;
+            state.total = None;
+            state.samples.clear();
+            true
+        });
+    }
+
+    pub fn set_phase(&self, phase: DownloadPhase) {
+        self.update(|state| {
+            state.phase = phase;
+            true
+        });
+    }
+
+    fn set_total(&self, total: u64) {
+        self.update(|state| {
+            state.total = Some(total);
+            false
+        });
+    }
+
+    fn add(&self, bytes: u64) {
+        self.update(|state| {
+            let now = Instant::now();
+            let phase_changed = state.phase == DownloadPhase::Connecting;
+            state.phase = DownloadPhase::Downloading;
+            state.received += bytes;
+            state.samples.push_back((now, state.received));
+            while state
+                .samples
+                .front()
+                .is_some_and(|(at, _)| now.duration_since(*at) > RATE_WINDOW)
+            {
+                state.samples.pop_front();
+            }
+            phase_changed
+        });
+    }
+
+    /// Applies `update`, which tells whether the change must be reported
+    /// right away.
+    fn update(&self, update: impl FnOnce(&mut TrackerState) -> bool) {
+        let status = {
+            let Ok(mut state) = self.state.lock() else {
+                return;
+            };
+            let force = update(&mut state);
+
+            let due = state
+                .reported_at
+                .is_none_or(|reported_at| reported_at.elapsed() >= PROGRESS_INTERVAL);
+            if !force && !due {
+                return;
+            }
+            state.reported_at = Some(Instant::now());
+            state.status()
+        };
+        (self.report)(status);
+    }
+}
+
+struct Segment {
+    start: u64,
+    end: u64,
+    attempts: usize,
+    /// Bytes of the segment already reported, which retries do not report
+    /// again.
+    reported: Arc<AtomicU64>,
+}
+
+impl Segment {
+    fn new(start: u64, end: u64) -> Self {
+        Self {
+            start,
+            end,
+            attempts: 0,
+            reported: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    fn len(&self) -> u64 {
+        self.end - self.start
+    }
+}
+
+/// Segments left to download, cut from the rest of the file as workers ask
+/// for them. Failed segments are handed out again first.
+struct SegmentQueue {
+    next: u64,
+    total: u64,
+    retries: VecDeque<Segment>,
+}
+
+impl SegmentQueue {
+    fn new(start: u64, total: u64) -> Self {
+        Self {
+            next: start.min(total),
+            total,
+            retries: VecDeque::new(),
+        }
+    }
+
+    /// Segments not handed out yet, retries aside.
+    fn remaining(&self) -> u64 {
+        (self.total - self.next).div_ceil(SEGMENT_SIZE)
+    }
+
+    fn pop(&mut self) -> Option<Segment> {
+        if let Some(segment) = self.retries.pop_front() {
+            return Some(segment);
+        }
+        if self.next >= self.total {
+            return None;
+        }
+
+        let start = self.next;
+        self.next = start.saturating_add(SEGMENT_SIZE).min(self.total);
+        Some(Segment::new(start, self.next))
+    }
+
+    fn retry(&mut self, segment: Segment) {
+        self.retries.push_back(segment);
+    }
+}
+
+/// Downloads `url` into `path`, returning the size of the file. The first
+/// segment is requested over the circuit of the scope, isolated per onion
+/// host by default, and tells whether the service supports ranges; if it
+/// does, the rest is fetched in parallel over circuits split from it.
+/// Files larger than `size_limit` fail with `HttpError::TooLarge`, as soon
+/// as their size is known, and so do files larger than `expected_size` or,
+/// without it, than `MAX_FILE_SIZE`. Segments are only accepted from the
+/// version of the file the first one came from. The first response is
+/// written as it arrives, so that a service sending the whole file is not
+/// held in memory.
+#[allow(clippy::too_many_arguments)]
+pub async fn download_file(
+    http_client: &HttpClient,
+    tor_client: &TorClientWrapper,
+    url: &str,
+    isolation: Option<StreamIsolation>,
+    path: &Path,
+    size_limit: Option<u64>,
+    expected_size: Option<u64>,
+    progress: DownloadProgress,
+) -> Result<u64, HttpError> {
+    let isolation = match isolation {
+        Some(isolation) => isolation,
+        None => {
+            let uri = url
+                .parse::<hyper::Uri>()
+                .map_err(|e| HttpError::InvalidUrl {
+                    message: e.to_string(),
+                })?;
+            StreamIsolation::Server(uri.host().unwrap_or_default().to_string())
+        }
+    };
+
+    // Bounds the body of a service ignoring the range.
+    let max_size = expected_size.unwrap_or(MAX_FILE_SIZE);
+    let first = Segment::new(0, SEGMENT_SIZE);
+    let file = create_file(path, 0).await?;
+    let (body_sink, frames) = mpsc::channel(BUFFERED_FRAMES);
+    let (response, written) = futures::future::try_join(
+        request_range(
+            http_client,
+            tor_client,
+            url,
+            &isolation,
+            &first,
+            None,
+            Some(size_limit.map_or(max_size, |limit| limit.min(max_size))),
+            Some(body_sink),
+            &progress,
+        ),
+        write_frames(&file, frames),
+    )
+    .await?;
+
+    let total = match response.status {
+        // The service ignored the range and sent the whole file, within
+        // the size limits.
+        200 => return Ok(written),
+        206 => {
+            let range = parse_content_range(&response)?;
+            if range.start != 0 || written > first.len() {
+                return Err(HttpError::Truncated {
+                    message: "Unexpected range in the response".to_string(),
+                });
+            }
+            check_size(range.total, expected_size)?;
+            if let Some(limit) = size_limit.filter(|limit| range.total > *limit) {
+                return Err(HttpError::TooLarge { limit });
+            }
+            range.total
+        }
+        status => {
+            return Err(HttpError::Status {
+                status,
+                message: format!("Request failed with status: {}", status),
+            })
+        }
+    };
+
+    progress.set_total(total);
+    let validator = range_validator(&response);
+    let queue = SegmentQueue::new(written, total);
+    let circuits = queue.remaining().min(MAX_CIRCUITS as u64) as usize;
+    set_file_len(&file, total).await?;
+
+    let queue = Mutex::new(queue);
+    let workers = (0..circuits).map(|worker| {
+        download_segments(
+            http_client,
+            tor_client,
+            url,
+            &isolation,
+            worker,
+            &queue,
+            &file,
+            validator.as_deref(),
+            &progress,
+        )
+    });
+    futures::future::try_join_all(workers).await?;
+
+    Ok(total)
+}
+
+/// Refuses files larger than expected, before any space is taken for them.
+fn check_size(size: u64, expected_size: Option<u64>) -> Result<(), HttpError> {
+    let limit = expected_size.unwrap_or(MAX_FILE_SIZE);
+    if size > limit {
+        return Err(HttpError::Truncated {
+            message: format!(
+                "The file is {} bytes, larger than the {} expected",
+                size, limit
+            ),
+        });
+    }
+    Ok(())
+}
+
+/// Value for the `If-Range` of the following segments: the strong ETag of
+/// the first response, or else its Last-Modified date.
+fn range_validator(response: &HttpResponse) -> Option<String> {
+    response
+        .header("etag")
+        .filter(|etag| !etag.trim_start().starts_with("W/"))
+        .or_else(|| response.header("last-modified"))
+        .map(|validator| validator.trim().to_string())
+}
+
+/// Takes segments off the queue until it is empty. A failed segment goes
+/// back to the queue, for this worker or another to retry it over a fresh
+/// circuit.
+#[allow(clippy::too_many_arguments)]
+async fn download_segments(
+    http_client: &HttpClient,
+    tor_client: &TorClientWrapper,
+    url: &str,
+    isolation: &StreamIsolation,
+    worker: usize,
+    queue: &Mutex<SegmentQueue>,
+    file: &Arc<Mutex<File>>,
+    validator: Option<&str>,
+    progress: &DownloadProgress,
+) -> Result<(), HttpError> {
+    let mut circuit = worker;
+    loop {
+        let Some(mut segment) = queue.lock().ok().and_then(|mut queue| queue.pop()) else {
+            end_split_isolation(tor_client, isolation, circuit);
+            return Ok(());
+        };
+
+        let circuit_isolation = isolation.split(circuit);
+        let result = request_range(
+            http_client,
+            tor_client,
+            url,
+            &circuit_isolation,
+            &segment,
+            validator,
+            Some(segment.len()),
+            None,
+            progress,
+        )
+        .await
+        .and_then(|response| check_segment(&response, &segment).map(|_| response.body));
+
+        match result {
+            Ok(body) => write_at(file, segment.start, body).await?,
+            // The file changed since the first segment and the whole of it
+            // is sent instead, which retrying cannot fix.
+            Err(HttpError::Status { status: 200, .. } | HttpError::TooLarge { .. }) => {
+                return Err(file_changed())
+            }
+            Err(e) => {
+                segment.attempts += 1;
+                end_split_isolation(tor_client, isolation, circuit);
+                if segment.attempts >= SEGMENT_ATTEMPTS {
+                    return Err(e);
+                }
+                eprintln!(
+                    "Segment {}-{} of {} failed, retrying: {}",
+                    segment.start, segment.end, url, e
+                );
+                if let Ok(mut queue) = queue.lock() {
+                    queue.retry(segment);
+                }
+                circuit += MAX_CIRCUITS;
+            }
+        }
+    }
+}
+
+/// Forgets the scope split off for a circuit once the worker is done with
+/// it. The first split is the download's own scope, left to its owner.
+fn end_split_isolation(tor_client: &TorClientWrapper, isolation: &StreamIsolation, circuit: usize) {
+    if circuit != 0 {
+        tor_client.end_isolation(&isolation.split(circuit));
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn request_range(
+    http_client: &HttpClient,
+    tor_client: &TorClientWrapper,
+    url: &str,
+    isolation: &StreamIsolation,
+    segment: &Segment,
+    validator: Option<&str>,
+    max_size: Option<u64>,
+    body_sink: Option<mpsc::Sender<Bytes>>,
+    progress: &DownloadProgress,
+) -> Result<HttpResponse, HttpError> {
+    let mut headers = vec![
+        (
+            "Range".to_string(),
+            format!("bytes={}-{}", segment.start, segment.end - 1),
+        ),
+        // Ranges of a compressed body cannot be decoded on their own.
+        ("Accept-Encoding".to_string(), "identity".to_string()),
+    ];
+    if let Some(validator) = validator {
+        headers.push(("If-Range".to_string(), validator.to_string()));
+    }
+    let request = HttpRequest {
+        headers,
+        max_size,
+        body_sink,
+        ..HttpRequest::get(url)
+    };
+
+    http_client
+        .send(
+            request,
+            tor_client,
+            Some(isolation.clone()),
+            Some(segment_progress(progress, &segment.reported)),
+        )
+        .await
+}
+
+/// Forwards to `progress` only the bytes of the segment beyond those
+/// reported by its previous attempts. A service ignoring the range tells
+/// the size of the whole file in its Content-Length.
+fn segment_progress(progress: &DownloadProgress, reported: &Arc<AtomicU64>) -> ProgressCallback {
+    let progress = progress.clone();
+    let reported = reported.clone();
+    let received = AtomicU64::new(0);
+
+    Arc::new(TokioMutex::new(Box::new(
+        move |body_progress| match body_progress {
+            BodyProgress::Started {
+                status: 200,
+                content_length: Some(content_length),
+            } => progress.set_total(content_length),
+            BodyProgress::Started { .. } => {}
+            BodyProgress::Chunk(chunk_length) => {
+                let received = received.fetch_add(chunk_length as u64, Ordering::Relaxed)
+                    + chunk_length as u64;
+                let previous = reported.fetch_max(received, Ordering::Relaxed);
+                if received > previous {
+                    progress.add(received - previous);
+                }
+            }
+        },
+    )))
+}
+
+fn check_segment(response: &HttpResponse, segment: &Segment) -> Result<(), HttpError> {
+    match response.status {
+        206 => {}
+        200 => return Err(file_changed()),
+        status => {
+            return Err(HttpError::Status {
+                status,
+                message: format!("Range request failed with status: {}", status),
+            })
+        }
+    }
+
+    let start = parse_content_range(response)?.start;
+    if start != segment.start || response.body.len() as u64 != segment.len() {
+        return Err(HttpError::Truncated {
+            message: format!(
+                "Expected bytes {}-{}, got {} bytes from {}",
+                segment.start,
+                segment.end - 1,
+                response.body.len(),
+                start
+            ),
+        });
+    }
+    Ok(())
+}
+
+/// Range of a partial response, out of the `total` bytes of the file.
+#[derive(Debug, PartialEq, Eq)]
+struct ContentRange {
+    start: u64,
+    end: u64,
+    total: u64,
+}
+
+/// Parses `Content-Range: bytes <start>-<end>/<total>`, refusing ranges
+/// outside of the file and files of unknown size.
+fn parse_content_range(response: &HttpResponse) -> Result<ContentRange, HttpError> {
+    let invalid = || HttpError::Truncated {
+        message: "Missing or invalid Content-Range".to_string(),
+    };
+    let range = response
+        .header("content-range")
+        .and_then(|range| range.trim().strip_prefix("bytes "))
+        .ok_or_else(invalid)?;
+    let (range, total) = range.split_once('/').ok_or_else(invalid)?;
+    let (start, end) = range.split_once('-').ok_or_else(invalid)?;
+
+    let range = ContentRange {
+        start: start.trim().parse().map_err(|_| invalid())?,
+        end: end.trim().parse().map_err(|_| invalid())?,
+        total: total.trim().parse().map_err(|_| invalid())?,
+    };
+    if range.start > range.end || range.end >= range.total {
+        return Err(invalid());
+    }
+    Ok(range)
+}
+
+/// Creates the file and gives it its final length, off the async runtime.
+async fn create_file(path: &Path, length: u64) -> Result<Arc<Mutex<File>>, HttpError> {
+    let path = path.to_path_buf();
+    let file = tokio::task::spawn_blocking(move || {
+        let file = File::create(path)?;
+        file.set_len(length)?;
+        Ok(file)
+    })
+    .await
+    .map_err(HttpError::other)?
+    .map_err(io_error)?;
+    Ok(Arc::new(Mutex::new(file)))
+}
+
+async fn set_file_len(file: &Arc<Mutex<File>>, length: u64) -> Result<(), HttpError> {
+    let file = file.clone();
+    tokio::task::spawn_blocking(move || {
+        file.lock()
+            .map_err(|_| HttpError::other("Download file lock poisoned"))?
+            .set_len(length)
+            .map_err(io_error)
+    })
+    .await
+    .map_err(HttpError::other)?
+}
+
+/// Writes the frames of a body one after the other from the start of the
+/// file, until the sender is dropped, and returns their length. Frames
+/// waiting in the channel are written together.
+async fn write_frames(
+    file: &Arc<Mutex<File>>,
+    mut frames: mpsc::Receiver<Bytes>,
+) -> Result<u64, HttpError> {
+    let mut written = 0;
+    while let Some(frame) = frames.recv().await {
+        let mut data = frame.to_vec();
+        while data.len() < WRITE_SIZE {
+            match frames.try_recv() {
+                Ok(frame) => data.extend_from_slice(&frame),
+                Err(_) => break,
+            }
+        }
+        let length = data.len() as u64;
+        write_at(file, written, data).await?;
+        written += length;
+    }
+    Ok(written)
+}
+
+/// Writes `data` at `offset`, off the async runtime.
+async fn write_at(file: &Arc<Mutex<File>>, offset: u64, data: Vec<u8>) -> Result<(), HttpError> {
+    let file = file.clone();
+    tokio::task::spawn_blocking(move || {
+        let mut file = file
+            .lock()
+            .map_err(|_| HttpError::other("Download file lock poisoned"))?;
+        file.seek(SeekFrom::Start(offset))
+            .and_then(|_| file.write_all(&data))
+            .map_err(io_error)
+    })
+    .await
+    .map_err(HttpError::other)?
+}
+
+fn file_changed() -> HttpError {
+    HttpError::Status {
+        status: 200,
+        message: "The file changed on the service during the download".to_string(),
+    }
+}
+
+fn io_error(error: std::io::Error) -> HttpError {
+    HttpError::Other {
+        message: format!("Failed to write the download: {}", error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(status: u16, headers: &[(&str, &str)]) -> HttpResponse {
+        HttpResponse {
+            status,
+            headers: headers
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            body: Vec::new(),
+        }
+    }
+
+    fn content_range(value: &str) -> Result<ContentRange, HttpError> {
+        parse_content_range(&response(206, &[("Content-Range", value)]))
+    }
+
+    #[test]
+    fn parses_content_ranges() {
+        assert_eq!(
+            content_range("bytes 0-99/1000").unwrap(),
+            ContentRange {
+                start: 0,
+                end: 99,
+                total: 1000
+            }
+        );
+        assert_eq!(content_range(" bytes 900-999/1000 ").unwrap().start, 900);
+        assert!(content_range("bytes 0-99/*").is_err());
+        assert!(content_range("bytes */1000").is_err());
+        assert!(content_range("bytes 100-99/1000").is_err());
+        assert!(content_range("bytes 0-1000/1000").is_err());
+        assert!(content_range("bytes 0-99/18446744073709551616").is_err());
+        assert!(content_range("items 0-99/1000").is_err());
+        assert!(parse_content_range(&response(206, &[])).is_err());
+    }
+
+    #[test]
+    fn refuses_files_larger_than_expected() {
+        assert!(check_size(1000, Some(1000)).is_ok());
+        assert!(check_size(1001, Some(1000)).is_err());
+        assert!(check_size(MAX_FILE_SIZE, None).is_ok());
+        assert!(check_size(u64::MAX, None).is_err());
+    }
+
+    #[test]
+    fn cuts_segments_as_they_are_needed() {
+        let total = 2 * SEGMENT_SIZE + 200;
+        let mut queue = SegmentQueue::new(100, total);
+        assert_eq!(queue.remaining(), 3);
+
+        let first = queue.pop().unwrap();
+        assert_eq!((first.start, first.end), (100, 100 + SEGMENT_SIZE));
+        queue.retry(first);
+        assert_eq!(queue.pop().unwrap().start, 100);
+
+        let second = queue.pop().unwrap();
+        assert_eq!(second.start, 100 + SEGMENT_SIZE);
+        let last = queue.pop().unwrap();
+        assert_eq!((last.start, last.end), (100 + 2 * SEGMENT_SIZE, total));
+        assert!(queue.pop().is_none());
+        assert_eq!(queue.remaining(), 0);
+    }
+
+    #[test]
+    fn does_not_overflow_at_the_end_of_the_range() {
+        let mut queue = SegmentQueue::new(u64::MAX - 10, u64::MAX);
+        let segment = queue.pop().unwrap();
+        assert_eq!((segment.start, segment.end), (u64::MAX - 10, u64::MAX));
+        assert!(queue.pop().is_none());
+    }
+
+    #[test]
+    fn validates_ranges_with_strong_etags_or_dates() {
+        let date = "Wed, 21 Oct 2015 07:28:00 GMT";
+        assert_eq!(
+            range_validator(&response(
+                206,
+                &[("ETag", "\"abc\""), ("Last-Modified", date)]
+            ))
+            .as_deref(),
+            Some("\"abc\"")
+        );
+        assert_eq!(
+            range_validator(&response(
+                206,
+                &[("ETag", "W/\"abc\""), ("Last-Modified", date)]
+            ))
+            .as_deref(),
+            Some(date)
+        );
+        assert_eq!(
+            range_validator(&response(206, &[("ETag", "W/\"abc\"")])),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn writes_frames_in_order_from_the_start() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("map.pmtiles.part");
+        let file = create_file(&path, 0).await.unwrap();
+        let (body_sink, frames) = mpsc::channel(BUFFERED_FRAMES);
+
+        let sender = async move {
+            for frame in ["abc", "", "defg", "h"] {
+                body_sink.send(Bytes::from(frame)).await.unwrap();
+            }
+        };
+        let (_, written) = tokio::join!(sender, write_frames(&file, frames));
+
+        assert_eq!(written.unwrap(), 8);
+        assert_eq!(std::fs::read(&path).unwrap(), b"abcdefgh");
+    }
+}
@@ -0,0 +1,476 @@
+//! Map downloads running in the Rust runtime, independently of the command
+//! that started them. On mobile the webview may be suspended while the app
+//! is in the background, so progress goes out as `map-download` events the
+//! UI can pick up again when it returns, and the end of a download is also
+//! told through an OS notification. Nothing keeps the process itself alive
+//! though: without a foreground service on Android or a background task on
+//! iOS, the OS may suspend or kill the app, and the download then has to be
+//! started again.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tauri::async_runtime::JoinHandle;
+use tauri::{AppHandle, Emitter, Manager};
+use tauri_plugin_notification::{NotificationExt, PermissionState};
+
+use crate::models::download::{download_file, DownloadPhase, DownloadStatus, ProgressTracker};
+use crate::models::http::HttpError;
+use crate::models::mirrors::verify_sha256;
+use crate::models::tor::StreamIsolation;
+use crate::models::AppState;
+
+const EVENT_NAME: &str = "map-download";
+
+/// Map to download. `onion_link` and `mirrors` must serve the same file,
+/// whose hash is checked against `sha256`. The hash is required to try
+/// mirrors, since nothing else tells that they served the expected file.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MapDownloadRequest {
+    pub locality_id: String,
+    /// Name of the locality, for the notifications.
+    pub name: Option<String>,
+    pub onion_link: String,
+    #[serde(default)]
+    pub mirrors: Vec<String>,
+    pub sha256: Option<String>,
+    /// Size of the map in bytes, which larger files are refused above.
+    pub file_size: Option<u64>,
+    pub group_id: Option<String>,
+    /// Allows the download above the limit of a metered connection.
+    #[serde(default)]
+    pub confirm_metered: bool,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase", tag = "event", content = "data")]
+pub enum DownloadEvent {
+    Progress(DownloadStatus),
+    /// The download from `url` failed and starts over from the next mirror.
+    MirrorFailed {
+        url: String,
+        error: String,
+    },
+    /// The map is larger than the limit of the metered connection; the
+    /// download has to be started again with `confirm_metered`.
+    ConfirmationRequired {
+        limit: u64,
+    },
+    Failed {
+        error: String,
+    },
+    Finished {},
+}
+
+/// Payload of the `map-download` event.
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MapDownloadEvent {
+    pub locality_id: String,
+    #[serde(flatten)]
+    pub event: DownloadEvent,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "snake_case", tag = "state")]
+pub enum DownloadState {
+    Running,
+    Finished,
+    Failed { error: String },
+    ConfirmationRequired { limit: u64 },
+}
+
+/// Snapshot of a download, for the UI to catch up with it.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MapDownload {
+    pub locality_id: String,
+    #[serde(flatten)]
+    pub state: DownloadState,
+    pub status: Option<DownloadStatus>,
+}
+
+struct Job {
+    state: DownloadState,
+    progress: Arc<ProgressTracker>,
+    task: Option<JoinHandle<()>>,
+    part_path: PathBuf,
+}
+
+impl Job {
+    fn snapshot(&self, locality_id: &str) -> MapDownload {
+        MapDownload {
+            locality_id: locality_id.to_string(),
+            state: self.state.clone(),
+            status: self.progress.status(),
+        }
+    }
+}
+
+/// Downloads by locality, the finished ones being kept until they are
+/// started again or dismissed.
+pub struct DownloadService {
+    app_handle: AppHandle,
+    jobs: Arc<Mutex<HashMap<String, Job>>>,
+}
+
+impl DownloadService {
+    pub fn new(app_handle: AppHandle) -> Self {
+        Self {
+            app_handle,
+            jobs: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub fn downloads(&self) -> Vec<MapDownload> {
+        self.jobs
+            .lock()
+            .map(|jobs| {
+                jobs.iter()
+                    .map(|(locality_id, job)| job.snapshot(locality_id))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Starts downloading a map into `file_path`, unless it is already being
+    /// downloaded, and returns the state of the download.
+    pub fn start(&self, request: MapDownloadRequest, file_path: PathBuf) -> Result<MapDownload> {
+        let mut jobs = self
+            .jobs
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Downloads lock poisoned"))?;
+        request.urls()?;
+        let locality_id = request.locality_id.clone();
+        if let Some(job) = jobs.get(&locality_id) {
+            if matches!(job.state, DownloadState::Running) {
+                return Ok(job.snapshot(&locality_id));
+            }
+        }
+
+        let app_handle = self.app_handle.clone();
+        let event_locality_id = locality_id.clone();
+        let progress = Arc::new(ProgressTracker::new(move |status| {
+            emit(
+                &app_handle,
+                &event_locality_id,
+                DownloadEvent::Progress(status),
+            );
+        }));
+
+        let app_handle = self.app_handle.clone();
+        let jobs_clone = self.jobs.clone();
+        let job_progress = progress.clone();
+        let part_path = part_path(&file_path);
+        let task = tauri::async_runtime::spawn(async move {
+            request_notification_permission(&app_handle).await;
+            let app_state = app_handle.state::<AppState>();
+            let locality_id = request.locality_id.clone();
+            let state = run(&app_handle, &app_state, &request, &file_path, &job_progress).await;
+
+            let event = match &state {
+                DownloadState::Running => return,
+                DownloadState::Finished => DownloadEvent::Finished {},
+                DownloadState::Failed { error } => DownloadEvent::Failed {
+                    error: error.clone(),
+                },
+                DownloadState::ConfirmationRequired { limit } => {
+                    DownloadEvent::ConfirmationRequired { limit: *limit }
+                }
+            };
+            notify(&app_handle, &request, &state);
+            if let Ok(mut jobs) = jobs_clone.lock() {
+                if let Some(job) = jobs.get_mut(&locality_id) {
+                    job.state = state;
+                    job.task = None;
+                }
+            }
+            emit(&app_handle, &locality_id, event);
+        });
+
+        let job = Job {
+            state: DownloadState::Running,
+            progress,
+            task: Some(task),
+            part_path,
+        };
+        let snapshot = job.snapshot(&locality_id);
+        jobs.insert(locality_id, job);
+        Ok(snapshot)
+    }
+
+    /// Stops a download and removes what it wrote, or forgets a finished
+    /// one. Returns whether there was one.
+    pub async fn cancel(&self, locality_id: &str) -> bool {
+        let job = self
+            .jobs
+            .lock()
+            .ok()
+            .and_then(|mut jobs| jobs.remove(locality_id));
+        match job {
+            Some(job) => {
+                if let Some(task) = job.task {
+                    task.abort();
+                    // The part file is only removed once the task no longer
+                    // writes to it.
+                    let _ = task.await;
+                    remove_part_file(&job.part_path);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Downloads the map from the first mirror that serves it, starting with
+/// the mirrors that proved the most reliable.
+async fn run(
+    app_handle: &AppHandle,
+    app_state: &AppState,
+    request: &MapDownloadRequest,
+    file_path: &Path,
+    progress: &Arc<ProgressTracker>,
+) -> DownloadState {
+    // Failures while Tor is down say nothing about the mirrors.
+    if !app_state.tor_client().is_ready() {
+        return DownloadState::Failed {
+            error: "Tor client is not enabled".to_string(),
+        };
+    }
+
+    let urls = match request.urls() {
+        Ok(urls) => app_state.mirrors().rank(&urls),
+        Err(e) => {
+            return DownloadState::Failed {
+                error: e.to_string(),
+            }
+        }
+    };
+    let size_limit = if request.confirm_metered {
+        None
+    } else {
+        app_state.http_client().network().download_limit()
+    };
+
+    let part_path = part_path(file_path);
+
+    let mut errors = Vec::new();
+    for url in urls {
+        let started = Instant::now();
+        progress.restart();
+        let result = download_file(
+            app_state.http_client(),
+            app_state.tor_client(),
+            &url,
+            request.group_id.clone().map(StreamIsolation::Group),
+            &part_path,
+            size_limit,
+            request.file_size,
+            progress.clone(),
+        )
+        .await;
+
+        if let Err(HttpError::TooLarge { limit }) = result {
+            if size_limit == Some(limit) {
+                remove_part_file(&part_path);
+                return DownloadState::ConfirmationRequired { limit };
+            }
+        }
+
+        let result = match (result, &request.sha256) {
+            (Ok(size), Some(sha256)) => {
+                progress.set_phase(DownloadPhase::Verifying);
+                verify_sha256(sha256, &part_path).await.map(|_| size)
+            }
+            (result, _) => result.map_err(anyhow::Error::from),
+        };
+
+        match result {
+            Ok(size) => {
+                app_state
+                    .mirrors()
+                    .record_success(&url, size, started.elapsed());
+                progress.set_phase(DownloadPhase::Finalizing);
+                return match std::fs::rename(&part_path, file_path) {
+                    Ok(()) => DownloadState::Finished,
+                    Err(e) => DownloadState::Failed {
+                        error: format!("Failed to save the map: {}", e),
+                    },
+                };
+            }
+            Err(e) => {
+                let error = e.to_string();
+                app_state.mirrors().record_failure(&url, &error);
+                emit(
+                    app_handle,
+                    &request.locality_id,
+                    DownloadEvent::MirrorFailed {
+                        url: url.clone(),
+                        error: error.clone(),
+                    },
+                );
+                errors.push(format!("{}: {}", url, error));
+            }
+        }
+    }
+
+    remove_part_file(&part_path);
+    DownloadState::Failed {
+        error: format!("Every mirror failed: {}", errors.join("; ")),
+    }
+}
+
+impl MapDownloadRequest {
+    /// URLs serving the map, the main link first, refusing to try several
+    /// without the hash of the map.
+    fn urls(&self) -> Result<Vec<String>> {
+        let mut urls = vec![self.onion_link.clone()];
+        for mirror in &self.mirrors {
+            if !urls.contains(mirror) {
+                urls.push(mirror.clone());
+            }
+        }
+
+        match &self.sha256 {
+            Some(sha256) => {
+                let is_sha256 = sha256.trim().len() == 64
+                    && sha256.trim().chars().all(|c| c.is_ascii_hexdigit());
+                if !is_sha256 {
+                    return Err(anyhow::anyhow!("Invalid SHA-256 for the map: {}", sha256));
+                }
+            }
+            None if urls.len() > 1 => {
+                return Err(anyhow::anyhow!(
+                    "Mirrors can only be tried with the SHA-256 of the map"
+                ))
+            }
+            None => {}
+        }
+        Ok(urls)
+    }
+}
+
+/// File the map is written to first, so that a failure keeps the previous
+/// map.
+fn part_path(file_path: &Path) -> PathBuf {
+    file_path.with_extension("pmtiles.part")
+}
+
+fn emit(app_handle: &AppHandle, locality_id: &str, event: DownloadEvent) {
+    let event = MapDownloadEvent {
+        locality_id: locality_id.to_string(),
+        event,
+    };
+    if let Err(e) = app_handle.emit(EVENT_NAME, event) {
+        eprintln!("Failed to emit map download event: {}", e);
+    }
+}
+
+/// Asks for the permission to notify the end of downloads, unless the user
+/// already answered.
+async fn request_notification_permission(app_handle: &AppHandle) {
+    let app_handle = app_handle.clone();
+    let result = tokio::task::spawn_blocking(move || {
+        let notification = app_handle.notification();
+        match notification.permission_state()? {
+            PermissionState::Prompt | PermissionState::PromptWithRationale => {
+                notification.request_permission()
+            }
+            state => Ok(state),
+        }
+    })
+    .await;
+
+    match result {
+        Ok(Ok(_)) => {}
+        Ok(Err(e)) => eprintln!("Failed to request the notification permission: {}", e),
+        Err(e) => eprintln!("Failed to request the notification permission: {}", e),
+    }
+}
+
+fn notify(app_handle: &AppHandle, request: &MapDownloadRequest, state: &DownloadState) {
+    if !matches!(
+        app_handle.notification().permission_state(),
+        Ok(PermissionState::Granted)
+    ) {
+        return;
+    }
+
+    let name = request.name.as_deref().unwrap_or(&request.locality_id);
+    let (title, body) = match state {
+        DownloadState::Running => return,
+        DownloadState::Finished => ("Map downloaded", format!("{} is ready", name)),
+        DownloadState::Failed { .. } => (
+            "Map download failed",
+            format!("{} could not be downloaded", name),
+        ),
+        DownloadState::ConfirmationRequired { .. } => (
+            "Map download paused",
+            format!("{} is large, confirm to download it", name),
+        ),
+    };
+
+    if let Err(e) = app_handle
+        .notification()
+        .builder()
+        .title(title)
+        .body(body)
+        .show()
+    {
+        eprintln!("Failed to show the download notification: {}", e);
+    }
+}
+
+fn remove_part_file(part_path: &Path) {
+    if let Err(e) = std::fs::remove_file(part_path) {
+        if e.kind() != std::io::ErrorKind::NotFound {
+            eprintln!("Failed to remove {}: {}", part_path.display(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHA256: &str = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08";
+
+    fn request(mirrors: &[&str], sha256: Option<&str>) -> MapDownloadRequest {
+        MapDownloadRequest {
+            locality_id: "1".to_string(),
+            name: None,
+            onion_link: "http://a.onion/1.pmtiles".to_string(),
+            mirrors: mirrors.iter().map(|mirror| mirror.to_string()).collect(),
+            sha256: sha256.map(str::to_string),
+            file_size: None,
+            group_id: None,
+            confirm_metered: false,
+        }
+    }
+
+    #[test]
+    fn requires_a_hash_to_try_mirrors() {
+        assert_eq!(request(&[], None).urls().unwrap().len(), 1);
+        // The main link given again as a mirror adds nothing to try.
+        assert_eq!(
+            request(&["http://a.onion/1.pmtiles"], None)
+                .urls()
+                .unwrap()
+                .len(),
+            1
+        );
+        assert!(request(&["http://b.onion/1.pmtiles"], None).urls().is_err());
+        assert_eq!(
+            request(&["http://b.onion/1.pmtiles"], Some(SHA256))
+                .urls()
+                .unwrap(),
+            vec!["http://a.onion/1.pmtiles", "http://b.onion/1.pmtiles"]
+        );
+        assert!(request(&[], Some("abc")).urls().is_err());
+    }
+}
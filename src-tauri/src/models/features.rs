@@ -0,0 +1,829 @@
+use anyhow::Result;
+use quick_xml::escape::{escape, resolve_predefined_entity};
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::fmt::Write;
+use std::path::Path;
+
+use crate::models::geo::Coordinate;
+use crate::models::group::{Marker, MarkerKind, Track};
+
+const DEFAULT_MARKER_NAME: &str = "Imported marker";
+const DEFAULT_TRACK_NAME: &str = "Imported track";
+// Namespace of the GPX extensions keeping what waypoints cannot express.
+const GPX_EXTENSIONS_NAMESPACE: &str = "urn:ash:gpx:1";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FeatureFormat {
+    Gpx,
+    Kml,
+    GeoJson,
+}
+
+impl FeatureFormat {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "gpx" => Some(Self::Gpx),
+            "kml" => Some(Self::Kml),
+            "geojson" | "json" => Some(Self::GeoJson),
+            _ => None,
+        }
+    }
+
+    fn sniff(content: &str) -> Option<Self> {
+        let content = content.trim_start();
+        if content.starts_with('{') {
+            Some(Self::GeoJson)
+        } else if content.contains("<gpx") {
+            Some(Self::Gpx)
+        } else if content.contains("<kml") {
+            Some(Self::Kml)
+        } else {
+            None
+        }
+    }
+}
+
+/// Waypoints map to markers and tracks/routes/lines to tracks.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MapFeatures {
+    pub markers: Vec<Marker>,
+    pub tracks: Vec<Track>,
+}
+
+pub fn import_features(path: &Path) -> Result<MapFeatures> {
+    let content = std::fs::read_to_string(path)?;
+    let format = FeatureFormat::from_path(path)
+        .or_else(|| FeatureFormat::sniff(&content))
+        .ok_or_else(|| anyhow::anyhow!("Unsupported file format: {}", path.display()))?;
+
+    parse_features(&content, format)
+}
+
+pub fn export_features(features: &MapFeatures, format: FeatureFormat, path: &Path) -> Result<()> {
+    std::fs::write(path, serialize_features(features, format)?)?;
+    Ok(())
+}
+
+pub fn parse_features(content: &str, format: FeatureFormat) -> Result<MapFeatures> {
+    match format {
+        FeatureFormat::Gpx => parse_gpx(&parse_xml(content)?),
+        FeatureFormat::Kml => parse_kml(&parse_xml(content)?),
+        FeatureFormat::GeoJson => parse_geojson(&serde_json::from_str(content)?),
+    }
+}
+
+pub fn serialize_features(features: &MapFeatures, format: FeatureFormat) -> Result<String> {
+    match format {
+        FeatureFormat::Gpx => serialize_gpx(features),
+        FeatureFormat::Kml => serialize_kml(features),
+        FeatureFormat::GeoJson => Ok(serde_json::to_string_pretty(&serialize_geojson(features))?),
+    }
+}
+
+fn new_marker(name: Option<&str>, coordinate: Coordinate) -> Marker {
+    Marker {
+        latitude: coordinate.latitude,
+        longitude: coordinate.longitude,
+        name: name
+            .filter(|name| !name.is_empty())
+            .unwrap_or(DEFAULT_MARKER_NAME)
+            .to_string(),
+        kind: MarkerKind::Point,
+        radius: None,
+        penalty: None,
+        outline: None,
+    }
+}
+
+fn parse_kind(value: &str) -> MarkerKind {
+    serde_json::from_value(Value::String(value.trim().to_string())).unwrap_or_default()
+}
+
+fn kind_name(kind: MarkerKind) -> String {
+    match serde_json::to_value(kind) {
+        Ok(Value::String(name)) => name,
+        _ => String::new(),
+    }
+}
+
+/// Drops the closing position repeated at the end of polygon rings.
+fn open_ring(mut ring: Vec<Coordinate>) -> Vec<Coordinate> {
+    if ring.len() > 3 && ring.first() == ring.last() {
+        ring.pop();
+    }
+    ring
+}
+
+fn centroid(points: &[Coordinate]) -> Coordinate {
+    let count = points.len().max(1) as f64;
+    Coordinate::new(
+        points.iter().map(|p| p.latitude).sum::<f64>() / count,
+        points.iter().map(|p| p.longitude).sum::<f64>() / count,
+    )
+}
+
+#[derive(Debug, Default)]
+struct XmlElement {
+    name: String,
+    attributes: Vec<(String, String)>,
+    children: Vec<XmlElement>,
+    text: String,
+}
+
+impl XmlElement {
+    fn from_start(start: &BytesStart) -> Result<Self> {
+        let mut attributes = Vec::new();
+        for attribute in start.attributes() {
+            let attribute = attribute?;
+            attributes.push((
+                String::from_utf8_lossy(attribute.key.local_name().as_ref()).into_owned(),
+                attribute.unescape_value()?.into_owned(),
+            ));
+        }
+
+        Ok(Self {
+            name: String::from_utf8_lossy(start.local_name().as_ref()).into_owned(),
+            attributes,
+            ..Default::default()
+        })
+    }
+
+    fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn children<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a XmlElement> {
+        self.children.iter().filter(move |child| child.name == name)
+    }
+
+    fn child_text(&self, name: &str) -> Option<&str> {
+        self.children
+            .iter()
+            .find(|child| child.name == name)
+            .map(|child| child.text.trim())
+    }
+
+    fn descendants<'a>(&'a self, name: &str, found: &mut Vec<&'a XmlElement>) {
+        for child in &self.children {
+            if child.name == name {
+                found.push(child);
+            }
+            child.descendants(name, found);
+        }
+    }
+
+    fn find_all(&self, name: &str) -> Vec<&XmlElement> {
+        let mut found = Vec::new();
+        self.descendants(name, &mut found);
+        found
+    }
+}
+
+fn parse_xml(content: &str) -> Result<XmlElement> {
+    let mut reader = Reader::from_str(content);
+    let mut stack = vec![XmlElement::default()];
+
+    loop {
+        match reader.read_event()? {
+            Event::Start(start) => stack.push(XmlElement::from_start(&start)?),
+            Event::Empty(start) => {
+                let element = XmlElement::from_start(&start)?;
+                if let Some(parent) = stack.last_mut() {
+                    parent.children.push(element);
+                }
+            }
+            Event::End(_) if stack.len() > 1 => {
+                if let (Some(element), Some(parent)) = (stack.pop(), stack.last_mut()) {
+                    parent.children.push(element);
+                }
+            }
+            Event::Text(text) => {
+                if let Some(element) = stack.last_mut() {
+                    element.text.push_str(&text.decode()?);
+                }
+            }
+            Event::CData(data) => {
+                if let Some(element) = stack.last_mut() {
+                    element.text.push_str(&data.decode()?);
+                }
+            }
+            Event::GeneralRef(reference) => {
+                if let Some(element) = stack.last_mut() {
+                    if let Some(character) = reference.resolve_char_ref()? {
+                        element.text.push(character);
+                    } else if let Some(entity) = resolve_predefined_entity(&reference.decode()?) {
+                        element.text.push_str(entity);
+                    }
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    while stack.len() > 1 {
+        if let (Some(element), Some(parent)) = (stack.pop(), stack.last_mut()) {
+            parent.children.push(element);
+        }
+    }
+
+    stack
+        .pop()
+        .ok_or_else(|| anyhow::anyhow!("Empty XML document"))
+}
+
+fn gpx_coordinate(point: &XmlElement) -> Option<Coordinate> {
+    Some(Coordinate::new(
+        point.attribute("lat")?.trim().parse().ok()?,
+        point.attribute("lon")?.trim().parse().ok()?,
+    ))
+}
+
+fn parse_gpx(root: &XmlElement) -> Result<MapFeatures> {
+    let mut features = MapFeatures::default();
+
+    for waypoint in root.find_all("wpt") {
+        let Some(coordinate) = gpx_coordinate(waypoint) else {
+            continue;
+        };
+        let mut marker = new_marker(waypoint.child_text("name"), coordinate);
+        if let Some(kind) = waypoint.child_text("type") {
+            marker.kind = parse_kind(kind);
+        }
+        if let Some(extensions) = waypoint.children("extensions").next() {
+            let number = |name| {
+                extensions
+                    .find_all(name)
+                    .first()
+                    .and_then(|element| element.text.trim().parse().ok())
+            };
+            marker.radius = number("radius");
+            marker.penalty = number("penalty");
+            marker.outline = extensions
+                .find_all("outline")
+                .first()
+                .map(|outline| {
+                    outline
+                        .children("point")
+                        .filter_map(gpx_coordinate)
+                        .collect::<Vec<_>>()
+                })
+                .filter(|outline| outline.len() >= 3);
+        }
+        features.markers.push(marker);
+    }
+
+    for track in root.find_all("trk") {
+        let name = track.child_text("name").unwrap_or(DEFAULT_TRACK_NAME);
+        for segment in track.children("trkseg") {
+            let points: Vec<Coordinate> = segment
+                .children("trkpt")
+                .filter_map(gpx_coordinate)
+                .collect();
+            if points.len() >= 2 {
+                features.tracks.push(Track {
+                    name: name.to_string(),
+                    points,
+                });
+            }
+        }
+    }
+
+    for route in root.find_all("rte") {
+        let points: Vec<Coordinate> = route.children("rtept").filter_map(gpx_coordinate).collect();
+        if points.len() >= 2 {
+            features.tracks.push(Track {
+                name: route
+                    .child_text("name")
+                    .unwrap_or(DEFAULT_TRACK_NAME)
+                    .to_string(),
+                points,
+            });
+        }
+    }
+
+    Ok(features)
+}
+
+/// A track point, route point or waypoint with its recorded elevation and
+/// time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GpxPoint {
+    pub coordinate: Coordinate,
+    pub elevation: Option<f64>,
+    /// Milliseconds since the Unix epoch.
+    pub time: Option<u64>,
+}
+
+/// Reads the track points of a GPX document, or its route points then its
+/// waypoints when it has no track.
+pub fn parse_gpx_points(content: &str) -> Result<Vec<GpxPoint>> {
+    let root = parse_xml(content)?;
+    for name in ["trkpt", "rtept", "wpt"] {
+        let points: Vec<GpxPoint> = root
+            .find_all(name)
+            .into_iter()
+            .filter_map(gpx_point)
+            .collect();
+        if !points.is_empty() {
+            return Ok(points);
+        }
+    }
+    Ok(Vec::new())
+}
+
+fn gpx_point(point: &XmlElement) -> Option<GpxPoint> {
+    Some(GpxPoint {
+        coordinate: gpx_coordinate(point)?,
+        elevation: point
+            .child_text("ele")
+            .and_then(|elevation| elevation.parse().ok()),
+        time: point
+            .child_text("time")
+            .and_then(|time| chrono::DateTime::parse_from_rfc3339(time).ok())
+            .map(|time| time.timestamp_millis().max(0) as u64),
+    })
+}
+
+fn serialize_gpx(features: &MapFeatures) -> Result<String> {
+    let mut gpx = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <gpx version=\"1.1\" creator=\"Ash\" xmlns=\"http://www.topografix.com/GPX/1/1\"",
+    );
+    writeln!(gpx, " xmlns:ash=\"{}\">", GPX_EXTENSIONS_NAMESPACE)?;
+
+    for marker in &features.markers {
+        writeln!(
+            gpx,
+            "  <wpt lat=\"{}\" lon=\"{}\">\n    <name>{}</name>\n    <type>{}</type>",
+            marker.latitude,
+            marker.longitude,
+            escape(marker.name.as_str()),
+            kind_name(marker.kind)
+        )?;
+
+        let outline = marker.outline.as_ref().filter(|outline| outline.len() >= 3);
+        if marker.radius.is_some() || marker.penalty.is_some() || outline.is_some() {
+            gpx.push_str("    <extensions>\n");
+            if let Some(radius) = marker.radius {
+                writeln!(gpx, "      <ash:radius>{}</ash:radius>", radius)?;
+            }
+            if let Some(penalty) = marker.penalty {
+                writeln!(gpx, "      <ash:penalty>{}</ash:penalty>", penalty)?;
+            }
+            if let Some(outline) = outline {
+                gpx.push_str("      <ash:outline>\n");
+                for point in outline {
+                    writeln!(
+                        gpx,
+                        "        <ash:point lat=\"{}\" lon=\"{}\"/>",
+                        point.latitude, point.longitude
+                    )?;
+                }
+                gpx.push_str("      </ash:outline>\n");
+            }
+            gpx.push_str("    </extensions>\n");
+        }
+        gpx.push_str("  </wpt>\n");
+    }
+
+    for track in &features.tracks {
+        writeln!(
+            gpx,
+            "  <trk>\n    <name>{}</name>\n    <trkseg>",
+            escape(track.name.as_str())
+        )?;
+        for point in &track.points {
+            writeln!(
+                gpx,
+                "      <trkpt lat=\"{}\" lon=\"{}\"/>",
+                point.latitude, point.longitude
+            )?;
+        }
+        writeln!(gpx, "    </trkseg>\n  </trk>")?;
+    }
+
+    gpx.push_str("</gpx>\n");
+    Ok(gpx)
+}
+
+fn kml_coordinates(element: &XmlElement) -> Vec<Coordinate> {
+    element
+        .child_text("coordinates")
+        .unwrap_or_default()
+        .split_whitespace()
+        .filter_map(|tuple| {
+            let mut values = tuple.split(',').map(|value| value.trim().parse::<f64>());
+            match (values.next(), values.next()) {
+                (Some(Ok(longitude)), Some(Ok(latitude))) => {
+                    Some(Coordinate::new(latitude, longitude))
+                }
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+fn parse_kml(root: &XmlElement) -> Result<MapFeatures> {
+    let mut features = MapFeatures::default();
+
+    for placemark in root.find_all("Placemark") {
+        let name = placemark.child_text("name");
+
+        let mut kind = MarkerKind::Point;
+        let mut radius = None;
+        let mut penalty = None;
+        for data in placemark.find_all("Data") {
+            let value = data.child_text("value").unwrap_or_default();
+            match data.attribute("name") {
+                Some("kind") => kind = parse_kind(value),
+                Some("radius") => radius = value.parse().ok(),
+                Some("penalty") => penalty = value.parse().ok(),
+                _ => {}
+            }
+        }
+
+        let outlines: Vec<Vec<Coordinate>> = placemark
+            .find_all("Polygon")
+            .into_iter()
+            .filter_map(|polygon| {
+                polygon
+                    .find_all("outerBoundaryIs")
+                    .first()
+                    .and_then(|boundary| boundary.find_all("LinearRing").first().copied())
+                    .map(|ring| open_ring(kml_coordinates(ring)))
+            })
+            .filter(|ring| ring.len() >= 3)
+            .collect();
+        let points: Vec<Coordinate> = placemark
+            .find_all("Point")
+            .into_iter()
+            .flat_map(kml_coordinates)
+            .collect();
+
+        // A point with a single polygon is a marker covering that area.
+        let single_outline =
+            (points.len() == 1 && outlines.len() == 1).then(|| outlines[0].clone());
+        let positions: Vec<(Coordinate, Option<Vec<Coordinate>>)> = if points.is_empty() {
+            outlines
+                .into_iter()
+                .map(|ring| (centroid(&ring), Some(ring)))
+                .collect()
+        } else {
+            points
+                .into_iter()
+                .map(|point| (point, single_outline.clone()))
+                .collect()
+        };
+
+        for (coordinate, outline) in positions {
+            let mut marker = new_marker(name, coordinate);
+            marker.kind = kind;
+            marker.radius = radius;
+            marker.penalty = penalty;
+            marker.outline = outline;
+            features.markers.push(marker);
+        }
+
+        for line in placemark.find_all("LineString") {
+            let points = kml_coordinates(line);
+            if points.len() >= 2 {
+                features.tracks.push(Track {
+                    name: name.unwrap_or(DEFAULT_TRACK_NAME).to_string(),
+                    points,
+                });
+            }
+        }
+    }
+
+    Ok(features)
+}
+
+fn format_kml_coordinates(points: &[Coordinate]) -> String {
+    points
+        .iter()
+        .map(|point| format!("{},{}", point.longitude, point.latitude))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn serialize_kml(features: &MapFeatures) -> Result<String> {
+    let mut kml = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <kml xmlns=\"http://www.opengis.net/kml/2.2\">\n  <Document>\n",
+    );
+
+    for marker in &features.markers {
+        writeln!(
+            kml,
+            "    <Placemark>\n      <name>{}</name>\n      <ExtendedData>\n        \
+             <Data name=\"kind\"><value>{}</value></Data>",
+            escape(marker.name.as_str()),
+            kind_name(marker.kind)
+        )?;
+        if let Some(radius) = marker.radius {
+            writeln!(
+                kml,
+                "        <Data name=\"radius\"><value>{}</value></Data>",
+                radius
+            )?;
+        }
+        if let Some(penalty) = marker.penalty {
+            writeln!(
+                kml,
+                "        <Data name=\"penalty\"><value>{}</value></Data>",
+                penalty
+            )?;
+        }
+        kml.push_str("      </ExtendedData>\n");
+
+        let point = format!(
+            "<Point><coordinates>{},{}</coordinates></Point>",
+            marker.longitude, marker.latitude
+        );
+        match marker.outline.as_ref().filter(|outline| outline.len() >= 3) {
+            Some(outline) => {
+                let mut ring = outline.clone();
+                ring.push(outline[0]);
+                writeln!(
+                    kml,
+                    "      <MultiGeometry>{}<Polygon><outerBoundaryIs><LinearRing>\
+                     <coordinates>{}</coordinates></LinearRing></outerBoundaryIs></Polygon>\
+                     </MultiGeometry>",
+                    point,
+                    format_kml_coordinates(&ring)
+                )?;
+            }
+            None => writeln!(kml, "      {}", point)?,
+        }
+        kml.push_str("    </Placemark>\n");
+    }
+
+    for track in &features.tracks {
+        writeln!(
+            kml,
+            "    <Placemark>\n      <name>{}</name>\n      \
+             <LineString><coordinates>{}</coordinates></LineString>\n    </Placemark>",
+            escape(track.name.as_str()),
+            format_kml_coordinates(&track.points)
+        )?;
+    }
+
+    kml.push_str("  </Document>\n</kml>\n");
+    Ok(kml)
+}
+
+fn geojson_position(value: &Value) -> Option<Coordinate> {
+    let position = value.as_array()?;
+    Some(Coordinate::new(
+        position.get(1)?.as_f64()?,
+        position.first()?.as_f64()?,
+    ))
+}
+
+fn geojson_positions(value: &Value) -> Vec<Coordinate> {
+    value
+        .as_array()
+        .map(|positions| positions.iter().filter_map(geojson_position).collect())
+        .unwrap_or_default()
+}
+
+fn parse_geojson_geometry(geometry: &Value, properties: &Value, features: &mut MapFeatures) {
+    let name = properties
+        .get("name")
+        .or_else(|| properties.get("title"))
+        .and_then(Value::as_str);
+    let coordinates = &geometry["coordinates"];
+
+    let push_marker = |features: &mut MapFeatures, coordinate, outline: Option<Vec<Coordinate>>| {
+        let mut marker = new_marker(name, coordinate);
+        if let Some(kind) = properties.get("kind").and_then(Value::as_str) {
+            marker.kind = parse_kind(kind);
+        }
+        marker.radius = properties.get("radius").and_then(Value::as_f64);
+        marker.penalty = properties.get("penalty").and_then(Value::as_f64);
+        marker.outline = outline;
+        features.markers.push(marker);
+    };
+    let push_track = |features: &mut MapFeatures, points: Vec<Coordinate>| {
+        if points.len() >= 2 {
+            features.tracks.push(Track {
+                name: name.unwrap_or(DEFAULT_TRACK_NAME).to_string(),
+                points,
+            });
+        }
+    };
+    let outer_ring = |polygon: &Value| open_ring(geojson_positions(&polygon[0]));
+
+    match geometry["type"].as_str() {
+        Some("Point") => {
+            if let Some(coordinate) = geojson_position(coordinates) {
+                push_marker(features, coordinate, None);
+            }
+        }
+        Some("MultiPoint") => {
+            for coordinate in geojson_positions(coordinates) {
+                push_marker(features, coordinate, None);
+            }
+        }
+        Some("LineString") => push_track(features, geojson_positions(coordinates)),
+        Some("MultiLineString") => {
+            for line in coordinates.as_array().into_iter().flatten() {
+                push_track(features, geojson_positions(line));
+            }
+        }
+        Some("Polygon") => {
+            let ring = outer_ring(coordinates);
+            if ring.len() >= 3 {
+                push_marker(features, centroid(&ring), Some(ring));
+            }
+        }
+        Some("MultiPolygon") => {
+            for polygon in coordinates.as_array().into_iter().flatten() {
+                let ring = outer_ring(polygon);
+                if ring.len() >= 3 {
+                    push_marker(features, centroid(&ring), Some(ring));
+                }
+            }
+        }
+        Some("GeometryCollection") => {
+            let geometries = geometry["geometries"]
+                .as_array()
+                .cloned()
+                .unwrap_or_default();
+            let point = geometries
+                .iter()
+                .find(|g| g["type"] == "Point")
+                .and_then(|g| geojson_position(&g["coordinates"]));
+            let polygon = geometries
+                .iter()
+                .find(|g| g["type"] == "Polygon")
+                .map(|g| outer_ring(&g["coordinates"]))
+                .filter(|ring| ring.len() >= 3);
+
+            // Written by Ash for markers covering an area.
+            if let (Some(point), Some(ring), 2) = (point, &polygon, geometries.len()) {
+                push_marker(features, point, Some(ring.clone()));
+            } else {
+                for geometry in &geometries {
+                    parse_geojson_geometry(geometry, properties, features);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn parse_geojson(value: &Value) -> Result<MapFeatures> {
+    let mut features = MapFeatures::default();
+    let empty = Value::Null;
+
+    match value["type"].as_str() {
+        Some("FeatureCollection") => {
+            for feature in value["features"].as_array().into_iter().flatten() {
+                parse_geojson_geometry(&feature["geometry"], &feature["properties"], &mut features);
+            }
+        }
+        Some("Feature") => {
+            parse_geojson_geometry(&value["geometry"], &value["properties"], &mut features)
+        }
+        Some(_) => parse_geojson_geometry(value, &empty, &mut features),
+        None => return Err(anyhow::anyhow!("Invalid GeoJSON document")),
+    }
+
+    Ok(features)
+}
+
+fn serialize_geojson(features: &MapFeatures) -> Value {
+    let position = |point: &Coordinate| json!([point.longitude, point.latitude]);
+
+    let markers = features.markers.iter().map(|marker| {
+        let point = json!({
+            "type": "Point",
+            "coordinates": [marker.longitude, marker.latitude],
+        });
+        let geometry = match marker.outline.as_ref().filter(|outline| outline.len() >= 3) {
+            Some(outline) => {
+                let mut ring: Vec<Value> = outline.iter().map(position).collect();
+                ring.push(position(&outline[0]));
+                json!({
+                    "type": "GeometryCollection",
+                    "geometries": [point, { "type": "Polygon", "coordinates": [ring] }],
+                })
+            }
+            None => point,
+        };
+
+        json!({
+            "type": "Feature",
+            "geometry": geometry,
+            "properties": {
+                "name": marker.name,
+                "kind": marker.kind,
+                "radius": marker.radius,
+                "penalty": marker.penalty,
+            },
+        })
+    });
+
+    let tracks = features.tracks.iter().map(|track| {
+        json!({
+            "type": "Feature",
+            "geometry": {
+                "type": "LineString",
+                "coordinates": track.points.iter().map(position).collect::<Vec<_>>(),
+            },
+            "properties": { "name": track.name },
+        })
+    });
+
+    json!({
+        "type": "FeatureCollection",
+        "features": markers.chain(tracks).collect::<Vec<_>>(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_features() -> MapFeatures {
+        let mut meeting = new_marker(Some("Fountain & steps"), Coordinate::new(48.8566, 2.3522));
+        meeting.kind = MarkerKind::MeetingPoint;
+        meeting.radius = Some(25.0);
+
+        let mut closure = new_marker(Some("Bridge"), Coordinate::new(48.857, 2.353));
+        closure.kind = MarkerKind::Closure;
+        closure.penalty = Some(4.5);
+        closure.outline = Some(vec![
+            Coordinate::new(48.8569, 2.3529),
+            Coordinate::new(48.8571, 2.3529),
+            Coordinate::new(48.8571, 2.3531),
+            Coordinate::new(48.8569, 2.3531),
+        ]);
+
+        MapFeatures {
+            markers: vec![
+                new_marker(Some("Plain"), Coordinate::new(-33.8688, 151.2093)),
+                meeting,
+                closure,
+            ],
+            tracks: vec![Track {
+                name: "Route <A>".to_string(),
+                points: vec![Coordinate::new(48.85, 2.35), Coordinate::new(48.86, 2.36)],
+            }],
+        }
+    }
+
+    fn assert_round_trip(format: FeatureFormat) {
+        let features = sample_features();
+        let exported = serialize_features(&features, format).unwrap();
+        let imported = parse_features(&exported, format).unwrap();
+        assert_eq!(imported.markers, features.markers, "{}", exported);
+        assert_eq!(imported.tracks, features.tracks, "{}", exported);
+
+        let reexported = serialize_features(&imported, format).unwrap();
+        assert_eq!(reexported, exported);
+    }
+
+    #[test]
+    fn round_trips_gpx() {
+        assert_round_trip(FeatureFormat::Gpx);
+    }
+
+    #[test]
+    fn round_trips_kml() {
+        assert_round_trip(FeatureFormat::Kml);
+    }
+
+    #[test]
+    fn round_trips_geojson() {
+        assert_round_trip(FeatureFormat::GeoJson);
+    }
+
+    #[test]
+    fn reads_gpx_points_with_their_time() {
+        let points = parse_gpx_points(
+            r#"<gpx><wpt lat="1" lon="2"/><trk><trkseg>
+                <trkpt lat="48.5" lon="2.25"><ele>35.5</ele><time>2024-05-01T10:00:00Z</time></trkpt>
+                <trkpt lat="48.6" lon="2.35"/>
+            </trkseg></trk></gpx>"#,
+        )
+        .unwrap();
+
+        assert_eq!(points.len(), 2);
+        assert_eq!(points[0].coordinate, Coordinate::new(48.5, 2.25));
+        assert_eq!(points[0].elevation, Some(35.5));
+        assert_eq!(points[0].time, Some(1_714_557_600_000));
+        assert_eq!(points[1].time, None);
+    }
+}
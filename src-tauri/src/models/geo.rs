@@ -0,0 +1,166 @@
+use serde::{Deserialize, Serialize};
+
+pub const EARTH_RADIUS_METERS: f64 = 6_371_008.8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Coordinate {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+impl Coordinate {
+    pub fn new(latitude: f64, longitude: f64) -> Self {
+        Self {
+            latitude,
+            longitude,
+        }
+    }
+
+    /// Great-circle distance in meters (haversine).
+    pub fn distance_to(&self, other: &Coordinate) -> f64 {
+        let lat1 = self.latitude.to_radians();
+        let lat2 = other.latitude.to_radians();
+        let d_lat = lat2 - lat1;
+        let d_lon = (other.longitude - self.longitude).to_radians();
+
+        let a = (d_lat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (d_lon / 2.0).sin().powi(2);
+        2.0 * EARTH_RADIUS_METERS * a.sqrt().asin()
+    }
+}
+
+/// Equirectangular projection around an origin, accurate enough for the few
+/// kilometers covered by a locality.
+#[derive(Debug, Clone, Copy)]
+pub struct LocalProjection {
+    origin: Coordinate,
+    meters_per_deg_lat: f64,
+    meters_per_deg_lon: f64,
+}
+
+impl LocalProjection {
+    pub fn new(origin: Coordinate) -> Self {
+        let meters_per_deg_lat = EARTH_RADIUS_METERS.to_radians();
+        Self {
+            origin,
+            meters_per_deg_lat,
+            meters_per_deg_lon: meters_per_deg_lat * origin.latitude.to_radians().cos(),
+        }
+    }
+
+    pub fn project(&self, coordinate: &Coordinate) -> (f64, f64) {
+        (
+            (coordinate.longitude - self.origin.longitude) * self.meters_per_deg_lon,
+            (coordinate.latitude - self.origin.latitude) * self.meters_per_deg_lat,
+        )
+    }
+
+    pub fn unproject(&self, x: f64, y: f64) -> Coordinate {
+        Coordinate::new(
+            self.origin.latitude + y / self.meters_per_deg_lat,
+            self.origin.longitude + x / self.meters_per_deg_lon,
+        )
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase",
+    tag = "type"
+)]
+pub enum Area {
+    Circle {
+        center: Coordinate,
+        radius_meters: f64,
+    },
+    Polygon {
+        points: Vec<Coordinate>,
+    },
+}
+
+impl Area {
+    pub fn contains(&self, coordinate: &Coordinate) -> bool {
+        self.signed_distance(coordinate) <= 0.0
+    }
+
+    /// Distance in meters to the edge of the area, negative when inside.
+    pub fn signed_distance(&self, coordinate: &Coordinate) -> f64 {
+        match self {
+            Area::Circle {
+                center,
+                radius_meters,
+            } => center.distance_to(coordinate) - radius_meters,
+            Area::Polygon { points } => {
+                if points.len() < 3 {
+                    return f64::INFINITY;
+                }
+
+                let projection = LocalProjection::new(*coordinate);
+                let ring: Vec<(f64, f64)> = points.iter().map(|p| projection.project(p)).collect();
+                let edge_distance = ring
+                    .iter()
+                    .zip(ring.iter().cycle().skip(1))
+                    .map(|(a, b)| distance_to_segment((0.0, 0.0), *a, *b))
+                    .fold(f64::INFINITY, f64::min);
+
+                if point_in_ring((0.0, 0.0), &ring) {
+                    -edge_distance
+                } else {
+                    edge_distance
+                }
+            }
+        }
+    }
+
+    /// Bounding circle of the area, used to size search grids.
+    pub fn bounding_circle(&self) -> (Coordinate, f64) {
+        match self {
+            Area::Circle {
+                center,
+                radius_meters,
+            } => (*center, *radius_meters),
+            Area::Polygon { points } => {
+                let count = points.len().max(1) as f64;
+                let center = Coordinate::new(
+                    points.iter().map(|p| p.latitude).sum::<f64>() / count,
+                    points.iter().map(|p| p.longitude).sum::<f64>() / count,
+                );
+                let radius = points
+                    .iter()
+                    .map(|p| center.distance_to(p))
+                    .fold(0.0, f64::max);
+                (center, radius)
+            }
+        }
+    }
+}
+
+/// Even-odd point in polygon test on projected coordinates.
+pub fn point_in_ring(point: (f64, f64), ring: &[(f64, f64)]) -> bool {
+    let (x, y) = point;
+    let mut inside = false;
+    let mut j = ring.len().wrapping_sub(1);
+
+    for i in 0..ring.len() {
+        let (xi, yi) = ring[i];
+        let (xj, yj) = ring[j];
+        if (yi > y) != (yj > y) && x < (xj - xi) * (y - yi) / (yj - yi) + xi {
+            inside = !inside;
+        }
+        j = i;
+    }
+
+    inside
+}
+
+pub fn distance_to_segment(point: (f64, f64), a: (f64, f64), b: (f64, f64)) -> f64 {
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let length_sq = dx * dx + dy * dy;
+    let t = if length_sq == 0.0 {
+        0.0
+    } else {
+        (((point.0 - a.0) * dx + (point.1 - a.1) * dy) / length_sq).clamp(0.0, 1.0)
+    };
+    let (px, py) = (a.0 + t * dx, a.1 + t * dy);
+    ((point.0 - px).powi(2) + (point.1 - py).powi(2)).sqrt()
+}
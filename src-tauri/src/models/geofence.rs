@@ -0,0 +1,270 @@
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use tauri::{AppHandle, Emitter};
+use tokio::sync::broadcast;
+
+use crate::models::geo::Area;
+use crate::models::group::{Marker, MarkerKind};
+use crate::models::location::{now_millis, LocationFix};
+
+const DEFAULT_MEETING_POINT_RADIUS_METERS: f64 = 30.0;
+const DEFAULT_ZONE_RADIUS_METERS: f64 = 100.0;
+// Hazards trigger before the zone itself is reached.
+const HAZARD_WARNING_DISTANCE_METERS: f64 = 150.0;
+// Distance past the boundary required before leaving a fence, widened by the
+// accuracy of the fix, so that GPS jitter does not flap enter/exit events.
+const MIN_EXIT_MARGIN_METERS: f64 = 20.0;
+const MAX_EXIT_MARGIN_METERS: f64 = 100.0;
+
+#[derive(Debug, Clone)]
+pub struct Geofence {
+    pub id: String,
+    pub group_id: String,
+    pub name: String,
+    pub kind: MarkerKind,
+    pub area: Area,
+    /// Signed distance to the area at which the fence is entered.
+    pub trigger_distance: f64,
+}
+
+impl Geofence {
+    pub fn from_marker(group_id: &str, marker: &Marker) -> Option<Self> {
+        let (default_radius, trigger_distance) = match marker.kind {
+            MarkerKind::Point => return None,
+            MarkerKind::MeetingPoint => (DEFAULT_MEETING_POINT_RADIUS_METERS, 0.0),
+            MarkerKind::SafeZone => (DEFAULT_ZONE_RADIUS_METERS, 0.0),
+            MarkerKind::PoliceLine | MarkerKind::Closure | MarkerKind::Threat => {
+                (DEFAULT_ZONE_RADIUS_METERS, HAZARD_WARNING_DISTANCE_METERS)
+            }
+        };
+
+        Some(Self {
+            id: format!(
+                "{}:{}:{:.6}:{:.6}",
+                group_id, marker.name, marker.latitude, marker.longitude
+            ),
+            group_id: group_id.to_string(),
+            name: marker.name.clone(),
+            kind: marker.kind,
+            area: marker.area(default_radius),
+            trigger_distance,
+        })
+    }
+
+    fn event(&self, distance_meters: f64, timestamp: u64, removed: bool) -> GeofenceEvent {
+        GeofenceEvent {
+            fence_id: self.id.clone(),
+            group_id: self.group_id.clone(),
+            name: self.name.clone(),
+            kind: self.kind,
+            distance_meters,
+            timestamp,
+            removed,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GeofenceEvent {
+    pub fence_id: String,
+    pub group_id: String,
+    pub name: String,
+    pub kind: MarkerKind,
+    /// Distance in meters to the edge of the area, negative when inside.
+    pub distance_meters: f64,
+    pub timestamp: u64,
+    /// Exit caused by the fence being removed while inside it.
+    pub removed: bool,
+}
+
+type GeofenceEvents = Vec<(&'static str, GeofenceEvent)>;
+
+#[derive(Default)]
+struct GeofenceState {
+    fences: HashMap<String, Vec<Geofence>>,
+    inside: HashSet<String>,
+    last_fix: Option<LocationFix>,
+}
+
+/// Watches the location stream and emits `geofence-enter` and
+/// `geofence-exit` events for the fences built from group markers.
+pub struct GeofenceEngine {
+    app_handle: AppHandle,
+    state: Arc<Mutex<GeofenceState>>,
+}
+
+impl GeofenceEngine {
+    pub fn new(app_handle: AppHandle, mut fixes: broadcast::Receiver<LocationFix>) -> Self {
+        let state = Arc::new(Mutex::new(GeofenceState::default()));
+
+        let state_clone = state.clone();
+        let app_handle_clone = app_handle.clone();
+        tauri::async_runtime::spawn(async move {
+            loop {
+                let fix = match fixes.recv().await {
+                    Ok(fix) => fix,
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
+
+                let events = match state_clone.lock() {
+                    Ok(mut state) => state.evaluate(&fix),
+                    Err(_) => break,
+                };
+
+                emit_events(&app_handle_clone, events);
+            }
+        });
+
+        Self { app_handle, state }
+    }
+
+    pub fn set_group_markers(&self, group_id: &str, markers: &[Marker]) {
+        let fences: Vec<Geofence> = markers
+            .iter()
+            .filter_map(|marker| Geofence::from_marker(group_id, marker))
+            .collect();
+
+        let events = match self.state.lock() {
+            Ok(mut state) => state.replace_group(group_id, fences),
+            Err(_) => return,
+        };
+        emit_events(&self.app_handle, events);
+    }
+}
+
+fn emit_events(app_handle: &AppHandle, events: GeofenceEvents) {
+    for (event_name, event) in events {
+        if let Err(e) = app_handle.emit(event_name, event) {
+            eprintln!("Failed to emit geofence event: {}", e);
+        }
+    }
+}
+
+impl GeofenceState {
+    /// Replaces the fences of a group, exiting the removed fences the
+    /// position was inside of.
+    fn replace_group(&mut self, group_id: &str, fences: Vec<Geofence>) -> GeofenceEvents {
+        let mut events = Vec::new();
+        for fence in self.fences.remove(group_id).unwrap_or_default() {
+            if fences.iter().any(|f| f.id == fence.id) || !self.inside.remove(&fence.id) {
+                continue;
+            }
+
+            let distance = self
+                .last_fix
+                .as_ref()
+                .map(|fix| fence.area.signed_distance(&fix.coordinate()))
+                .unwrap_or_default();
+            events.push(("geofence-exit", fence.event(distance, now_millis(), true)));
+        }
+
+        self.fences.insert(group_id.to_string(), fences);
+        events
+    }
+
+    fn evaluate(&mut self, fix: &LocationFix) -> GeofenceEvents {
+        self.last_fix = Some(fix.clone());
+        let coordinate = fix.coordinate();
+        let exit_margin = fix
+            .accuracy
+            .unwrap_or_default()
+            .clamp(MIN_EXIT_MARGIN_METERS, MAX_EXIT_MARGIN_METERS);
+
+        let mut events = Vec::new();
+        for fence in self.fences.values().flatten() {
+            let distance = fence.area.signed_distance(&coordinate);
+            let was_inside = self.inside.contains(&fence.id);
+
+            let event_name = if !was_inside && distance <= fence.trigger_distance {
+                self.inside.insert(fence.id.clone());
+                "geofence-enter"
+            } else if was_inside && distance > fence.trigger_distance + exit_margin {
+                self.inside.remove(&fence.id);
+                "geofence-exit"
+            } else {
+                continue;
+            };
+
+            events.push((event_name, fence.event(distance, fix.timestamp, false)));
+        }
+
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::location::LocationSource;
+
+    fn marker(name: &str, kind: MarkerKind) -> Marker {
+        Marker {
+            latitude: 48.8566,
+            longitude: 2.3522,
+            name: name.to_string(),
+            kind,
+            radius: Some(50.0),
+            penalty: None,
+            outline: None,
+        }
+    }
+
+    fn fix(latitude: f64, longitude: f64) -> LocationFix {
+        LocationFix {
+            latitude,
+            longitude,
+            accuracy: None,
+            altitude: None,
+            speed: None,
+            heading: None,
+            timestamp: 1,
+            source: LocationSource::Manual,
+        }
+    }
+
+    fn fences(markers: &[Marker]) -> Vec<Geofence> {
+        markers
+            .iter()
+            .filter_map(|marker| Geofence::from_marker("group", marker))
+            .collect()
+    }
+
+    #[test]
+    fn enters_and_exits_with_a_margin() {
+        let mut state = GeofenceState::default();
+        state.replace_group("group", fences(&[marker("camp", MarkerKind::SafeZone)]));
+
+        let events = state.evaluate(&fix(48.8566, 2.3522));
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].0, "geofence-enter");
+
+        // Just past the edge, within the exit margin.
+        assert!(state.evaluate(&fix(48.8571, 2.3522)).is_empty());
+
+        let events = state.evaluate(&fix(48.86, 2.3522));
+        assert_eq!(events[0].0, "geofence-exit");
+        assert!(!events[0].1.removed);
+    }
+
+    #[test]
+    fn exits_fences_removed_while_inside() {
+        let mut state = GeofenceState::default();
+        let camp = marker("camp", MarkerKind::SafeZone);
+        let meeting = marker("meeting", MarkerKind::MeetingPoint);
+        state.replace_group("group", fences(&[camp.clone(), meeting]));
+        assert_eq!(state.evaluate(&fix(48.8566, 2.3522)).len(), 2);
+
+        let events = state.replace_group("group", fences(&[camp]));
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].0, "geofence-exit");
+        assert_eq!(events[0].1.name, "meeting");
+        assert!(events[0].1.removed);
+        assert!(events[0].1.distance_meters < 0.0);
+
+        assert_eq!(state.replace_group("group", Vec::new()).len(), 1);
+        assert!(state.replace_group("group", Vec::new()).is_empty());
+    }
+}
@@ -0,0 +1,111 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use crate::models::geo::{Area, Coordinate};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MarkerKind {
+    #[default]
+    Point,
+    MeetingPoint,
+    SafeZone,
+    PoliceLine,
+    Closure,
+    Threat,
+}
+
+impl MarkerKind {
+    pub fn is_hazard(&self) -> bool {
+        matches!(
+            self,
+            MarkerKind::PoliceLine | MarkerKind::Closure | MarkerKind::Threat
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Marker {
+    pub latitude: f64,
+    pub longitude: f64,
+    pub name: String,
+    #[serde(default)]
+    pub kind: MarkerKind,
+    /// Radius in meters of the area covered by the marker.
+    #[serde(default)]
+    pub radius: Option<f64>,
+    /// Routing penalty weight, `None` lets the marker kind decide.
+    #[serde(default)]
+    pub penalty: Option<f64>,
+    /// Outline of the area covered by the marker, used instead of the radius.
+    #[serde(default)]
+    pub outline: Option<Vec<Coordinate>>,
+}
+
+impl Marker {
+    pub fn coordinate(&self) -> Coordinate {
+        Coordinate::new(self.latitude, self.longitude)
+    }
+
+    pub fn area(&self, default_radius: f64) -> Area {
+        match &self.outline {
+            Some(points) if points.len() >= 3 => Area::Polygon {
+                points: points.clone(),
+            },
+            _ => Area::Circle {
+                center: self.coordinate(),
+                radius_meters: self.radius.unwrap_or(default_radius),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Track {
+    pub name: String,
+    pub points: Vec<Coordinate>,
+}
+
+/// Markers and tracks shared in each group, as last pushed by the webview.
+#[derive(Default)]
+pub struct GroupRegistry {
+    markers: RwLock<HashMap<String, Vec<Marker>>>,
+    tracks: RwLock<HashMap<String, Vec<Track>>>,
+}
+
+impl GroupRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_markers(&self, group_id: &str, markers: Vec<Marker>) {
+        if let Ok(mut groups) = self.markers.write() {
+            groups.insert(group_id.to_string(), markers);
+        }
+    }
+
+    pub fn markers(&self, group_id: &str) -> Vec<Marker> {
+        self.markers
+            .read()
+            .ok()
+            .and_then(|groups| groups.get(group_id).cloned())
+            .unwrap_or_default()
+    }
+
+    pub fn set_tracks(&self, group_id: &str, tracks: Vec<Track>) {
+        if let Ok(mut groups) = self.tracks.write() {
+            groups.insert(group_id.to_string(), tracks);
+        }
+    }
+
+    pub fn tracks(&self, group_id: &str) -> Vec<Track> {
+        self.tracks
+            .read()
+            .ok()
+            .and_then(|groups| groups.get(group_id).cloned())
+            .unwrap_or_default()
+    }
+}
@@ -1,37 +1,323 @@
-use anyhow::Result;
+use arti_client::IsolationToken;
 use bytes::Bytes;
+use flate2::read::{GzDecoder, ZlibDecoder};
 use http_body_util::BodyExt;
-use http_body_util::Empty;
+use http_body_util::Full;
 use hyper::client::conn::http1;
+use hyper::http::uri::Scheme;
 use hyper_util::rt::TokioIo;
-use std::sync::Arc;
-use tokio::sync::Mutex as TokioMutex;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::Read;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tauri::AppHandle;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::{mpsc, Mutex as TokioMutex};
+use tokio_native_tls::TlsStream;
 
-use crate::models::tor::TorClientWrapper;
+use crate::models::network::NetworkPolicy;
+use crate::models::tls_pins::{verify_certificate, TlsPin, TlsPins};
+use crate::models::tor::{self, CircuitFailureKind, StreamIsolation, TorClientWrapper};
 
-pub type ProgressCallback = Arc<TokioMutex<Box<dyn Fn(usize) + Send + Sync>>>;
+// Time allowed for the response headers once the request is sent, and
+// between two frames of the body, before the stream is deemed stalled.
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+// Attempts of idempotent requests, which are retried on fresh circuits.
+const MAX_ATTEMPTS: usize = 3;
+// Wait before the first retry, doubled for each further one.
+const RETRY_DELAY: Duration = Duration::from_millis(500);
+// Onion services usually drop idle connections after a minute or two.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+const MAX_IDLE_PER_KEY: usize = 4;
+const MAX_IDLE: usize = 32;
+// Time for a pooled connection to finish its previous exchange.
+const READY_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_REDIRECTS: usize = 5;
+const ACCEPT_ENCODING: &str = "gzip, deflate, br";
+// Credentials meant for one service are not forwarded to another.
+const CROSS_HOST_STRIPPED_HEADERS: [&str; 3] = ["authorization", "cookie", "proxy-authorization"];
 
-pub struct HttpClient;
+pub type ProgressCallback = Arc<TokioMutex<Box<dyn Fn(BodyProgress) + Send + Sync>>>;
+
+/// Progress of the body of a response, given to the progress callback.
+#[derive(Debug, Clone, Copy)]
+pub enum BodyProgress {
+    /// The headers of a response arrived.
+    Started {
+        status: u16,
+        content_length: Option<u64>,
+    },
+    /// Length of a chunk of the body, before decoding.
+    Chunk(usize),
+}
+
+/// Request to an onion service. Headers are `[name, value]` pairs.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HttpRequest {
+    pub url: String,
+    #[serde(default = "default_method")]
+    pub method: String,
+    #[serde(default)]
+    pub headers: Vec<(String, String)>,
+    #[serde(default)]
+    pub body: Option<Vec<u8>>,
+    /// Limit for the whole exchange, retries included.
+    pub timeout_ms: Option<u64>,
+    /// Largest response body accepted, in bytes.
+    pub max_size: Option<u64>,
+    /// Receives the body of a successful response frame by frame, instead
+    /// of `HttpResponse::body`, which is then left empty. The body must be
+    /// sent without content encoding.
+    #[serde(skip)]
+    pub body_sink: Option<mpsc::Sender<Bytes>>,
+}
+
+impl HttpRequest {
+    pub fn get(url: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            method: default_method(),
+            headers: Vec::new(),
+            body: None,
+            timeout_ms: None,
+            max_size: None,
+            body_sink: None,
+        }
+    }
+}
+
+fn default_method() -> String {
+    "GET".to_string()
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HttpResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl HttpResponse {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Failure of a request, serialized with its `kind` so that the frontend
+/// can tell a service that is down from a network that is.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "snake_case", tag = "kind")]
+pub enum HttpError {
+    /// The request, or its stream, took too long.
+    Timeout {
+        message: String,
+    },
+    /// The service answered with an error status, or redirected too often.
+    Status {
+        status: u16,
+        message: String,
+    },
+    /// The body ended early or could not be decoded.
+    Truncated {
+        message: String,
+    },
+    /// No stream to the service could be opened over Tor.
+    TorConnect {
+        message: String,
+    },
+    /// The URL, or a redirect target, is not an HTTP or HTTPS onion URL.
+    InvalidUrl {
+        message: String,
+    },
+    /// The TLS handshake failed, or the certificate does not match the pins
+    /// of the service.
+    Tls {
+        message: String,
+    },
+    /// The body is larger than the request allows.
+    TooLarge {
+        limit: u64,
+    },
+    Other {
+        message: String,
+    },
+}
+
+impl fmt::Display for HttpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HttpError::Timeout { message }
+            | HttpError::Status { message, .. }
+            | HttpError::Truncated { message }
+            | HttpError::TorConnect { message }
+            | HttpError::InvalidUrl { message }
+            | HttpError::Tls { message }
+            | HttpError::Other { message } => write!(f, "{}", message),
+            HttpError::TooLarge { limit } => {
+                write!(f, "Response exceeds the limit of {} bytes", limit)
+            }
+        }
+    }
+}
+
+impl std::error::Error for HttpError {}
+
+impl HttpError {
+    pub fn invalid_url(message: impl fmt::Display) -> Self {
+        HttpError::InvalidUrl {
+            message: message.to_string(),
+        }
+    }
+
+    fn tls(message: impl fmt::Display) -> Self {
+        HttpError::Tls {
+            message: message.to_string(),
+        }
+    }
+
+    pub fn other(message: impl fmt::Display) -> Self {
+        HttpError::Other {
+            message: message.to_string(),
+        }
+    }
+}
+
+type RequestSender = http1::SendRequest<Full<Bytes>>;
+
+/// Connections are only shared by requests to the same service whose streams
+/// may share circuits. The token changes when the circuits of the scope are
+/// retired, so that their connections are not reused. The flag tells TLS
+/// connections apart.
+type PoolKey = (String, u16, bool, IsolationToken);
+
+struct IdleConnection {
+    sender: RequestSender,
+    idle_since: Instant,
+}
+
+/// Idle keep-alive HTTP/1.1 connections, so that successive requests to a
+/// service skip the stream setup and handshake.
+#[derive(Default)]
+struct ConnectionPool {
+    idle: BTreeMap<PoolKey, Vec<IdleConnection>>,
+}
+
+impl ConnectionPool {
+    /// Takes the most recently used connection that may still be open.
+    fn checkout(&mut self, key: &PoolKey) -> Option<RequestSender> {
+        self.evict_expired();
+        let connections = self.idle.get_mut(key)?;
+        let sender = connections.pop().map(|connection| connection.sender);
+        if connections.is_empty() {
+            self.idle.remove(key);
+        }
+        sender
+    }
+
+    fn checkin(&mut self, key: PoolKey, sender: RequestSender) {
+        if sender.is_closed() {
+            return;
+        }
+
+        let connections = self.idle.entry(key).or_default();
+        connections.push(IdleConnection {
+            sender,
+            idle_since: Instant::now(),
+        });
+        if connections.len() > MAX_IDLE_PER_KEY {
+            connections.remove(0);
+        }
+
+        while self.idle.values().map(Vec::len).sum::<usize>() > MAX_IDLE {
+            let oldest = self
+                .idle
+                .iter()
+                .filter_map(|(key, connections)| {
+                    connections.first().map(|c| (key.clone(), c.idle_since))
+                })
+                .min_by_key(|(_, idle_since)| *idle_since)
+                .map(|(key, _)| key);
+            let Some(oldest) = oldest else { break };
+            if let Some(connections) = self.idle.get_mut(&oldest) {
+                connections.remove(0);
+                if connections.is_empty() {
+                    self.idle.remove(&oldest);
+                }
+            }
+        }
+    }
+
+    /// Drops the idle connections to a host, whatever their port or scope.
+    fn remove_host(&mut self, host: &str) {
+        self.idle
+            .retain(|(key_host, ..), _| !key_host.eq_ignore_ascii_case(host));
+    }
+
+    fn evict_expired(&mut self) {
+        self.idle.retain(|_, connections| {
+            connections.retain(|connection| {
+                connection.idle_since.elapsed() < IDLE_TIMEOUT && !connection.sender.is_closed()
+            });
+            !connections.is_empty()
+        });
+    }
+}
+
+pub struct HttpClient {
+    pool: Mutex<ConnectionPool>,
+    tls_pins: TlsPins,
+    network: NetworkPolicy,
+}
 
 impl HttpClient {
-    pub fn new() -> Self {
-        Self
+    pub fn new(app_handle: AppHandle) -> Self {
+        Self {
+            pool: Mutex::new(ConnectionPool::default()),
+            tls_pins: TlsPins::new(app_handle.clone()),
+            network: NetworkPolicy::new(app_handle),
+        }
+    }
+
+    pub fn tls_pins(&self) -> &TlsPins {
+        &self.tls_pins
     }
 
-    fn validate_onion_url(&self, url: &str) -> Result<()> {
-        let uri = url.parse::<hyper::Uri>()?;
+    /// Replaces the pins of a service, see [`TlsPins::set`]. Its pooled
+    /// connections were checked against the previous pins, so they are
+    /// closed.
+    pub fn set_tls_pins(&self, onion_address: &str, pins: Vec<TlsPin>) -> anyhow::Result<()> {
+        let address = self.tls_pins.set(onion_address, pins)?;
+        if let Ok(mut pool) = self.pool.lock() {
+            pool.remove_host(&address);
+        }
+        Ok(())
+    }
+
+    pub fn network(&self) -> &NetworkPolicy {
+        &self.network
+    }
+
+    fn validate_onion_url(&self, url: &str) -> Result<(), HttpError> {
+        let uri = url.parse::<hyper::Uri>().map_err(HttpError::invalid_url)?;
         let host = uri
             .host()
-            .ok_or_else(|| anyhow::anyhow!("Invalid host in URL"))?;
+            .ok_or_else(|| HttpError::invalid_url("Invalid host in URL"))?;
 
         if !host.ends_with(".onion") {
-            return Err(anyhow::anyhow!("Only .onion domains are allowed"));
+            return Err(HttpError::invalid_url("Only .onion domains are allowed"));
         }
 
         if let Some(scheme) = uri.scheme() {
-            if scheme != &hyper::http::uri::Scheme::HTTP {
-                return Err(anyhow::anyhow!(
-                    "Only HTTP scheme is allowed for .onion domains"
+            if scheme != &Scheme::HTTP && scheme != &Scheme::HTTPS {
+                return Err(HttpError::invalid_url(
+                    "Only HTTP and HTTPS schemes are allowed for .onion domains",
                 ));
             }
         }
@@ -39,76 +325,744 @@ impl HttpClient {
         Ok(())
     }
 
+    /// Fetches an onion URL, failing unless the final response is a success.
+    /// Streams are isolated per onion host unless the caller gives a
+    /// narrower isolation scope.
     pub async fn get(
         &self,
         url: &str,
         tor_client: &TorClientWrapper,
+        isolation: Option<StreamIsolation>,
+        progress_callback: Option<ProgressCallback>,
+    ) -> Result<Vec<u8>, HttpError> {
+        let response = self
+            .send(
+                HttpRequest::get(url),
+                tor_client,
+                isolation,
+                progress_callback,
+            )
+            .await?;
+
+        if !(200..300).contains(&response.status) {
+            return Err(HttpError::Status {
+                status: response.status,
+                message: format!("Request failed with status: {}", response.status),
+            });
+        }
+        Ok(response.body)
+    }
+
+    /// Sends a request to an onion URL, following redirects to other onion
+    /// URLs. Streams are isolated per onion host unless the caller gives a
+    /// narrower isolation scope.
+    pub async fn send(
+        &self,
+        request: HttpRequest,
+        tor_client: &TorClientWrapper,
+        isolation: Option<StreamIsolation>,
         progress_callback: Option<ProgressCallback>,
-    ) -> Result<(Vec<u8>, u16)> {
+    ) -> Result<HttpResponse, HttpError> {
+        let timeout_ms = request.timeout_ms;
+        let redirects = async {
+            let mut request = request;
+            let mut redirects = 0;
+            loop {
+                let response = self
+                    .send_once(
+                        &request,
+                        tor_client,
+                        isolation.clone(),
+                        progress_callback.clone(),
+                    )
+                    .await?;
+
+                let location = match response.status {
+                    301 | 302 | 303 | 307 | 308 => response.header("location"),
+                    _ => None,
+                };
+                let Some(location) = location else {
+                    return Ok(response);
+                };
+                if redirects == MAX_REDIRECTS {
+                    return Err(HttpError::Status {
+                        status: response.status,
+                        message: format!("More than {} redirects", MAX_REDIRECTS),
+                    });
+                }
+                redirects += 1;
+                request = redirected_request(request, response.status, location)?;
+                self.validate_onion_url(&request.url)?;
+            }
+        };
+
+        match timeout_ms {
+            Some(timeout_ms) => tokio::time::timeout(Duration::from_millis(timeout_ms), redirects)
+                .await
+                .map_err(|_| HttpError::Timeout {
+                    message: "Request timed out".to_string(),
+                })?,
+            None => redirects.await,
+        }
+    }
+
+    /// Checks that the service of an onion URL can be reached over the
+    /// circuits of the scope, and returns how long opening a stream took.
+    /// Failing circuits are retired, so that the next request to the service
+    /// builds new ones instead of stalling.
+    pub async fn probe(
+        &self,
+        url: &str,
+        tor_client: &TorClientWrapper,
+        isolation: Option<StreamIsolation>,
+    ) -> Result<Duration, HttpError> {
         self.validate_onion_url(url)?;
 
-        let uri = url.parse::<hyper::Uri>()?;
+        let uri = url.parse::<hyper::Uri>().map_err(HttpError::invalid_url)?;
         let host = uri
             .host()
-            .ok_or_else(|| anyhow::anyhow!("Invalid host in URL"))?;
-        let port = uri.port_u16().unwrap_or(80);
+            .ok_or_else(|| HttpError::invalid_url("Invalid host in URL"))?;
+        let port = uri
+            .port_u16()
+            .unwrap_or(if uri.scheme() == Some(&Scheme::HTTPS) {
+                443
+            } else {
+                80
+            });
+        let isolation = isolation.unwrap_or_else(|| StreamIsolation::Server(host.to_string()));
 
-        self.make_http_tor_request(host, port, url, tor_client, progress_callback)
+        tor_client
+            .probe(host, port, &isolation)
             .await
+            .map_err(|e| HttpError::TorConnect {
+                message: format!("{:#}", e),
+            })
     }
 
+    /// Sends a request without following redirects. Attempts of idempotent
+    /// requests failing before the body is received are retried on fresh
+    /// circuits.
+    async fn send_once(
+        &self,
+        request: &HttpRequest,
+        tor_client: &TorClientWrapper,
+        isolation: Option<StreamIsolation>,
+        progress_callback: Option<ProgressCallback>,
+    ) -> Result<HttpResponse, HttpError> {
+        self.validate_onion_url(&request.url)?;
+
+        let uri = request
+            .url
+            .parse::<hyper::Uri>()
+            .map_err(HttpError::invalid_url)?;
+        let host = uri
+            .host()
+            .ok_or_else(|| HttpError::invalid_url("Invalid host in URL"))?;
+        let tls = uri.scheme() == Some(&Scheme::HTTPS);
+        let port = uri.port_u16().unwrap_or(if tls { 443 } else { 80 });
+        let isolation = isolation.unwrap_or_else(|| StreamIsolation::Server(host.to_string()));
+        let method = hyper::Method::from_bytes(request.method.to_uppercase().as_bytes())
+            .map_err(HttpError::other)?;
+        let max_attempts = if method.is_idempotent() {
+            MAX_ATTEMPTS
+        } else {
+            1
+        };
+
+        let mut attempt = 1;
+        loop {
+            match self
+                .make_http_tor_request(
+                    host,
+                    port,
+                    tls,
+                    &method,
+                    request,
+                    tor_client,
+                    &isolation,
+                    progress_callback.clone(),
+                )
+                .await
+            {
+                Ok(response) => return Ok(response),
+                Err(e) if e.retryable && attempt < max_attempts => {
+                    let delay = retry_delay(attempt);
+                    eprintln!(
+                        "Request to {} failed, retrying on a fresh circuit in {:?}: {}",
+                        host, delay, e.error
+                    );
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                Err(e) => return Err(e.error),
+            }
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
     async fn make_http_tor_request(
         &self,
         host: &str,
         port: u16,
-        original_url: &str,
+        tls: bool,
+        method: &hyper::Method,
+        original_request: &HttpRequest,
         tor_client: &TorClientWrapper,
+        isolation: &StreamIsolation,
         progress_callback: Option<ProgressCallback>,
-    ) -> Result<(Vec<u8>, u16)> {
-        let stream = tor_client.connect(host, port).await?;
-        let stream = TokioIo::new(stream);
+    ) -> Result<HttpResponse, AttemptError> {
+        let report = |kind, message: String| {
+            tor_client.report_circuit_failure(host, port, isolation, kind, message.clone());
+            AttemptError::retryable(match kind {
+                CircuitFailureKind::Stalled => HttpError::Timeout { message },
+                _ => HttpError::TorConnect { message },
+            })
+        };
 
-        let (mut request_sender, connection) = http1::handshake(stream).await?;
+        let key = (
+            host.to_string(),
+            port,
+            tls,
+            tor_client.isolation_token(isolation),
+        );
+        // Connections set up under other pins are not pooled again.
+        let pins = if tls {
+            self.tls_pins.get(host)
+        } else {
+            Vec::new()
+        };
+        let mut pooled = self.checkout(&key).await;
 
-        tokio::spawn(async move {
-            if let Err(e) = connection.await {
-                eprintln!("Connection error: {}", e);
+        let (response, request_sender) = loop {
+            let (mut request_sender, reused) = match pooled.take() {
+                Some(request_sender) => (request_sender, true),
+                None => (
+                    self.open_connection(host, port, tls, tor_client, isolation)
+                        .await?,
+                    false,
+                ),
+            };
+
+            let request = build_request(host, method, original_request)
+                .map_err(|e| AttemptError::fatal(HttpError::other(e)))?;
+            match tokio::time::timeout(READ_TIMEOUT, request_sender.send_request(request)).await {
+                Ok(Ok(response)) => break (response, request_sender),
+                // The service may have closed the idle connection meanwhile,
+                // which says nothing about the circuit.
+                Ok(Err(e)) if reused && (method.is_idempotent() || e.is_canceled()) => continue,
+                Ok(Err(e)) => return Err(report(CircuitFailureKind::ConnectFailed, e.to_string())),
+                Err(_) => {
+                    return Err(report(
+                        CircuitFailureKind::Stalled,
+                        format!("No response from {} within {:?}", host, READ_TIMEOUT),
+                    ))
+                }
             }
-        });
+        };
+        let status = response.status().as_u16();
+        let mut headers: Vec<(String, String)> = response
+            .headers()
+            .iter()
+            .map(|(name, value)| {
+                (
+                    name.to_string(),
+                    String::from_utf8_lossy(value.as_bytes()).into_owned(),
+                )
+            })
+            .collect();
 
-        let request = hyper::Request::builder()
-            .uri(original_url)
-            .method("GET")
-            .header("Host", host)
-            .body(Empty::<Bytes>::new())?;
+        let max_size = original_request.max_size;
+        let content_length = response
+            .headers()
+            .get(hyper::header::CONTENT_LENGTH)
+            .and_then(|length| length.to_str().ok())
+            .and_then(|length| length.parse::<u64>().ok());
+        if let (Some(max_size), Some(content_length)) = (max_size, content_length) {
+            if content_length > max_size {
+                return Err(AttemptError::fatal(HttpError::TooLarge { limit: max_size }));
+            }
+        }
 
-        let response = request_sender.send_request(request).await?;
-        let status = response.status().as_u16();
+        let body_sink = original_request
+            .body_sink
+            .as_ref()
+            .filter(|_| (200..300).contains(&status));
+        if body_sink.is_some() && is_encoded(&headers) {
+            return Err(AttemptError::fatal(HttpError::Truncated {
+                message: format!("Response from {} is encoded and cannot be streamed", host),
+            }));
+        }
 
-        if let Some(callback) = progress_callback {
-            let mut body_bytes = Vec::new();
-            let mut body = response.into_body();
+        if let Some(callback) = &progress_callback {
+            let callback = callback.lock().await;
+            callback(BodyProgress::Started {
+                status,
+                content_length,
+            });
+        }
+
+        let mut body_bytes = Vec::new();
+        let mut received = 0u64;
+        let mut body = response.into_body();
 
-            while let Some(chunk) = body.frame().await {
-                let chunk = chunk?;
-                let data = chunk.into_data().unwrap_or_default();
-                let chunk_len = data.len();
-                body_bytes.extend_from_slice(&data);
+        loop {
+            let chunk = match tokio::time::timeout(READ_TIMEOUT, body.frame()).await {
+                Ok(Some(chunk)) => chunk.map_err(|e| {
+                    AttemptError::fatal(HttpError::Truncated {
+                        message: format!(
+                            "Response from {} broke off after {} bytes: {}",
+                            host, received, e
+                        ),
+                    })
+                })?,
+                Ok(None) => break,
+                Err(_) => {
+                    // Part of the body may already have been reported as
+                    // progress, so the request is not retried.
+                    let message =
+                        format!("Response from {} stalled after {} bytes", host, received);
+                    tor_client.report_circuit_failure(
+                        host,
+                        port,
+                        isolation,
+                        CircuitFailureKind::Stalled,
+                        message.clone(),
+                    );
+                    return Err(AttemptError::fatal(HttpError::Timeout { message }));
+                }
+            };
 
+            let data = chunk.into_data().unwrap_or_default();
+            let length = data.len();
+            received += length as u64;
+            if let Some(max_size) = max_size.filter(|max| received > *max) {
+                return Err(AttemptError::fatal(HttpError::TooLarge { limit: max_size }));
+            }
+            match body_sink {
+                Some(body_sink) => body_sink.send(data).await.map_err(|_| {
+                    AttemptError::fatal(HttpError::other("The receiver of the body went away"))
+                })?,
+                None => body_bytes.extend_from_slice(&data),
+            }
+
+            if let Some(callback) = &progress_callback {
                 let callback = callback.lock().await;
-                callback(chunk_len);
+                callback(BodyProgress::Chunk(length));
+            }
+            self.network.throttle(length).await;
+        }
+
+        if !tls || self.tls_pins.get(host) == pins {
+            if let Ok(mut pool) = self.pool.lock() {
+                pool.checkin(key, request_sender);
+            }
+        }
+
+        let body_bytes =
+            decode_body(&mut headers, body_bytes, max_size).map_err(AttemptError::fatal)?;
+
+        Ok(HttpResponse {
+            status,
+            headers,
+            body: body_bytes,
+        })
+    }
+
+    async fn checkout(&self, key: &PoolKey) -> Option<RequestSender> {
+        loop {
+            let mut request_sender = self.pool.lock().ok()?.checkout(key)?;
+            if let Ok(Ok(())) = tokio::time::timeout(READY_TIMEOUT, request_sender.ready()).await {
+                return Some(request_sender);
             }
+        }
+    }
+
+    async fn open_connection(
+        &self,
+        host: &str,
+        port: u16,
+        tls: bool,
+        tor_client: &TorClientWrapper,
+        isolation: &StreamIsolation,
+    ) -> Result<RequestSender, AttemptError> {
+        // Connection failures are reported by the wrapper itself.
+        let stream = tor_client
+            .connect(host, port, isolation)
+            .await
+            .map_err(|e| {
+                let error = HttpError::TorConnect {
+                    message: format!("{:#}", e),
+                };
+                if tor::is_transient_connect_error(&e) {
+                    AttemptError::retryable(error)
+                } else {
+                    AttemptError::fatal(error)
+                }
+            })?;
 
-            Ok((body_bytes, status))
+        let request_sender = if tls {
+            let stream = self.tls_connect(host, stream).await?;
+            handshake(TokioIo::new(stream)).await
         } else {
-            let body_bytes = response.into_body().collect().await?.to_bytes();
-            Ok((body_bytes.to_vec(), status))
+            handshake(TokioIo::new(stream)).await
+        };
+        request_sender.map_err(|e| {
+            let message = e.to_string();
+            tor_client.report_circuit_failure(
+                host,
+                port,
+                isolation,
+                CircuitFailureKind::ConnectFailed,
+                message.clone(),
+            );
+            AttemptError::retryable(HttpError::TorConnect { message })
+        })
+    }
+
+    /// Sets up TLS over the stream. A pinned service is checked against its
+    /// pins only, since its certificate is often self-signed or issued for
+    /// a clearnet name; others are validated against the system roots.
+    async fn tls_connect<S>(&self, host: &str, stream: S) -> Result<TlsStream<S>, AttemptError>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let pins = self.tls_pins.get(host);
+        let connector = native_tls::TlsConnector::builder()
+            .danger_accept_invalid_certs(!pins.is_empty())
+            .danger_accept_invalid_hostnames(!pins.is_empty())
+            .build()
+            .map_err(|e| AttemptError::fatal(HttpError::tls(e)))?;
+        let stream = tokio_native_tls::TlsConnector::from(connector)
+            .connect(host, stream)
+            .await
+            .map_err(|e| AttemptError::fatal(HttpError::tls(e)))?;
+
+        if !pins.is_empty() {
+            let certificate = stream
+                .get_ref()
+                .peer_certificate()
+                .map_err(|e| AttemptError::fatal(HttpError::tls(e)))?
+                .ok_or_else(|| {
+                    AttemptError::fatal(HttpError::tls("No certificate from the service"))
+                })?
+                .to_der()
+                .map_err(|e| AttemptError::fatal(HttpError::tls(e)))?;
+            verify_certificate(&pins, &certificate)
+                .map_err(|e| AttemptError::fatal(HttpError::tls(e)))?;
         }
+
+        Ok(stream)
     }
 }
 
-impl Default for HttpClient {
-    fn default() -> Self {
-        Self::new()
+fn retry_delay(attempt: usize) -> Duration {
+    RETRY_DELAY * 2u32.pow(attempt.saturating_sub(1) as u32)
+}
+
+/// Performs the HTTP/1.1 handshake and drives the connection in the
+/// background.
+async fn handshake<T>(io: TokioIo<T>) -> hyper::Result<RequestSender>
+where
+    T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (request_sender, connection) = http1::handshake(io).await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            eprintln!("Connection error: {}", e);
+        }
+    });
+    Ok(request_sender)
+}
+
+fn build_request(
+    host: &str,
+    method: &hyper::Method,
+    original_request: &HttpRequest,
+) -> Result<hyper::Request<Full<Bytes>>, hyper::http::Error> {
+    let mut builder = hyper::Request::builder()
+        .uri(&original_request.url)
+        .method(method.clone())
+        .header("Host", host);
+    for (name, value) in &original_request.headers {
+        if !name.eq_ignore_ascii_case("host") {
+            builder = builder.header(name, value);
+        }
+    }
+    if !original_request
+        .headers
+        .iter()
+        .any(|(name, _)| name.eq_ignore_ascii_case("accept-encoding"))
+    {
+        builder = builder.header(hyper::header::ACCEPT_ENCODING, ACCEPT_ENCODING);
+    }
+
+    builder.body(Full::new(Bytes::from(
+        original_request.body.clone().unwrap_or_default(),
+    )))
+}
+
+/// Builds the request a redirect leads to. 303, and 301 and 302 for other
+/// methods than GET and HEAD, turn the request into a GET without body, as
+/// browsers do.
+fn redirected_request(
+    request: HttpRequest,
+    status: u16,
+    location: &str,
+) -> Result<HttpRequest, HttpError> {
+    let base = request
+        .url
+        .parse::<hyper::Uri>()
+        .map_err(HttpError::invalid_url)?;
+    let url = resolve_location(&request.url, location)?;
+    let target = url.parse::<hyper::Uri>().map_err(HttpError::invalid_url)?;
+
+    let method = request.method.to_uppercase();
+    let becomes_get =
+        status == 303 || (matches!(status, 301 | 302) && method != "GET" && method != "HEAD");
+    let same_host = target.scheme() == base.scheme()
+        && target.host() == base.host()
+        && target.port_u16() == base.port_u16();
+
+    let headers = request
+        .headers
+        .into_iter()
+        .filter(|(name, _)| {
+            let name = name.to_ascii_lowercase();
+            (same_host || !CROSS_HOST_STRIPPED_HEADERS.contains(&name.as_str()))
+                && !(becomes_get && (name == "content-type" || name == "content-length"))
+        })
+        .collect();
+
+    Ok(HttpRequest {
+        url,
+        method: if becomes_get {
+            "GET".to_string()
+        } else {
+            method
+        },
+        headers,
+        body: if becomes_get { None } else { request.body },
+        ..request
+    })
+}
+
+/// Resolves a `Location` header against the URL that was redirected, as
+/// browsers do (RFC 3986 section 5).
+fn resolve_location(base: &str, location: &str) -> Result<String, HttpError> {
+    url::Url::parse(base)
+        .and_then(|base| base.join(location.trim()))
+        .map(String::from)
+        .map_err(HttpError::invalid_url)
+}
+
+fn is_encoded(headers: &[(String, String)]) -> bool {
+    headers.iter().any(|(name, value)| {
+        name.eq_ignore_ascii_case("content-encoding")
+            && !matches!(value.trim().to_ascii_lowercase().as_str(), "" | "identity")
+    })
+}
+
+/// Decodes a compressed body, dropping the headers that describe the
+/// encoded form. The limit applies to the decoded size too.
+fn decode_body(
+    headers: &mut Vec<(String, String)>,
+    body: Vec<u8>,
+    max_size: Option<u64>,
+) -> Result<Vec<u8>, HttpError> {
+    let encoding = headers
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("content-encoding"))
+        .map(|(_, value)| value.trim().to_ascii_lowercase());
+
+    let decoder: Box<dyn Read> = match encoding.as_deref() {
+        None | Some("") | Some("identity") => return Ok(body),
+        Some("gzip") | Some("x-gzip") => Box::new(GzDecoder::new(body.as_slice())),
+        Some("deflate") => Box::new(ZlibDecoder::new(body.as_slice())),
+        Some("br") => Box::new(brotli::Decompressor::new(body.as_slice(), 4096)),
+        Some(encoding) => {
+            return Err(HttpError::other(format!(
+                "Unsupported content encoding {}",
+                encoding
+            )))
+        }
+    };
+
+    let mut decoded = Vec::new();
+    let limit = max_size.map_or(u64::MAX, |max_size| max_size.saturating_add(1));
+    decoder
+        .take(limit)
+        .read_to_end(&mut decoded)
+        .map_err(|e| HttpError::Truncated {
+            message: format!("Failed to decode the response: {}", e),
+        })?;
+    if let Some(max_size) = max_size.filter(|max| decoded.len() as u64 > *max) {
+        return Err(HttpError::TooLarge { limit: max_size });
+    }
+
+    headers.retain(|(name, _)| {
+        !name.eq_ignore_ascii_case("content-encoding")
+            && !name.eq_ignore_ascii_case("content-length")
+    });
+    Ok(decoded)
+}
+
+/// Failure of a single request attempt.
+struct AttemptError {
+    error: HttpError,
+    /// Whether the attempt failed before any of the body was received.
+    retryable: bool,
+}
+
+impl AttemptError {
+    fn retryable(error: HttpError) -> Self {
+        Self {
+            error,
+            retryable: true,
+        }
+    }
+
+    fn fatal(error: HttpError) -> Self {
+        Self {
+            error,
+            retryable: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sender of a connection whose server never answers, kept open until
+    /// the runtime stops.
+    async fn idle_sender() -> RequestSender {
+        let (client, server) = tokio::io::duplex(1024);
+        tokio::spawn(async move {
+            let _server = server;
+            std::future::pending::<()>().await
+        });
+        handshake(TokioIo::new(client)).await.unwrap()
+    }
+
+    fn key(host: &str, port: u16) -> PoolKey {
+        (host.to_string(), port, true, IsolationToken::no_isolation())
+    }
+
+    #[tokio::test]
+    async fn drops_the_connections_of_a_host() {
+        let mut pool = ConnectionPool::default();
+        pool.checkin(key("a.onion", 443), idle_sender().await);
+        pool.checkin(key("a.onion", 8443), idle_sender().await);
+        pool.checkin(key("b.onion", 443), idle_sender().await);
+
+        pool.remove_host("A.onion");
+
+        assert!(pool.checkout(&key("a.onion", 443)).is_none());
+        assert!(pool.checkout(&key("a.onion", 8443)).is_none());
+        assert!(pool.checkout(&key("b.onion", 443)).is_some());
+    }
+
+    #[tokio::test]
+    async fn keeps_a_few_connections_per_key() {
+        let mut pool = ConnectionPool::default();
+        for _ in 0..MAX_IDLE_PER_KEY + 1 {
+            pool.checkin(key("a.onion", 443), idle_sender().await);
+        }
+
+        for _ in 0..MAX_IDLE_PER_KEY {
+            assert!(pool.checkout(&key("a.onion", 443)).is_some());
+        }
+        assert!(pool.checkout(&key("a.onion", 443)).is_none());
+    }
+
+    #[tokio::test]
+    async fn evicts_the_oldest_connection_past_the_limit() {
+        let mut pool = ConnectionPool::default();
+        for port in 0..=MAX_IDLE as u16 {
+            pool.checkin(key("a.onion", port), idle_sender().await);
+        }
+
+        assert!(pool.checkout(&key("a.onion", 0)).is_none());
+        for port in 1..=MAX_IDLE as u16 {
+            assert!(pool.checkout(&key("a.onion", port)).is_some());
+        }
+    }
+
+    fn post(url: &str) -> HttpRequest {
+        HttpRequest {
+            method: "POST".to_string(),
+            headers: vec![
+                ("Authorization".to_string(), "Basic abc".to_string()),
+                ("Content-Type".to_string(), "text/plain".to_string()),
+            ],
+            body: Some(b"body".to_vec()),
+            ..HttpRequest::get(url)
+        }
+    }
+
+    #[test]
+    fn resolves_redirect_locations() {
+        let resolve =
+            |location| resolve_location("http://a.onion/maps/1.pmtiles?v=1", location).unwrap();
+
+        assert_eq!(resolve("http://b.onion/x"), "http://b.onion/x");
+        assert_eq!(resolve("HTTPS://b.onion/x"), "https://b.onion/x");
+        assert_eq!(resolve("//b.onion/x"), "http://b.onion/x");
+        assert_eq!(resolve("/x"), "http://a.onion/x");
+        assert_eq!(resolve("2.pmtiles"), "http://a.onion/maps/2.pmtiles");
+        assert_eq!(
+            resolve("/login?next=http://x"),
+            "http://a.onion/login?next=http://x"
+        );
+        assert_eq!(
+            resolve("login?next=http://x"),
+            "http://a.onion/maps/login?next=http://x"
+        );
+        assert_eq!(resolve("?v=2"), "http://a.onion/maps/1.pmtiles?v=2");
+        assert_eq!(resolve("#tiles"), "http://a.onion/maps/1.pmtiles?v=1#tiles");
+        assert_eq!(resolve("../x"), "http://a.onion/x");
+        assert_eq!(resolve("./2.pmtiles"), "http://a.onion/maps/2.pmtiles");
+        assert_eq!(resolve("/a/./b/../c"), "http://a.onion/a/c");
+        assert_eq!(resolve(""), "http://a.onion/maps/1.pmtiles?v=1");
+    }
+
+    #[test]
+    fn turns_redirected_posts_into_gets() {
+        let request = redirected_request(post("http://a.onion/form"), 303, "/done").unwrap();
+        assert_eq!(request.url, "http://a.onion/done");
+        assert_eq!(request.method, "GET");
+        assert_eq!(request.body, None);
+        assert_eq!(
+            request.headers,
+            vec![("Authorization".to_string(), "Basic abc".to_string())]
+        );
+
+        // 307 and 308 keep the method and the body.
+        let request = redirected_request(post("http://a.onion/form"), 307, "/retry").unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.body.as_deref(), Some(&b"body"[..]));
+        assert_eq!(request.headers.len(), 2);
+    }
+
+    #[test]
+    fn drops_credentials_on_redirects_to_other_hosts() {
+        let request =
+            redirected_request(post("http://a.onion/form"), 307, "http://b.onion/form").unwrap();
+        assert_eq!(
+            request.headers,
+            vec![("Content-Type".to_string(), "text/plain".to_string())]
+        );
+
+        // Another port is another origin.
+        let request =
+            redirected_request(post("http://a.onion/form"), 307, "http://a.onion:8080/").unwrap();
+        assert_eq!(request.headers.len(), 1);
+    }
+
+    #[test]
+    fn backs_off_between_attempts() {
+        assert_eq!(retry_delay(1), RETRY_DELAY);
+        assert_eq!(retry_delay(2), RETRY_DELAY * 2);
+        assert_eq!(retry_delay(3), RETRY_DELAY * 4);
     }
 }
@@ -0,0 +1,441 @@
+//! Encrypted store for the onion keys of the app: the client authorization
+//! keys of restricted discovery services, and the identity key and
+//! authorized clients of the onion service hosted by this device. Arti is
+//! given these keys in memory only, so they never reach its state directory
+//! in plaintext. The vault is sealed with AES-256-GCM under a master key kept
+//! in the OS keystore.
+
+use anyhow::Result;
+use arti_client::{HsClientDescEncKey, HsId};
+use openssl::symm::{decrypt_aead, encrypt_aead, Cipher};
+use safelog::DisplayRedacted;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tor_hscrypto::pk::{HsClientDescEncSecretKey, HsIdKeypair};
+use tor_llcrypto::pk::{curve25519, ed25519};
+
+const VAULT_FILE: &str = "keys.vault";
+// Master key file of the first vault format, removed once the key is in the
+// OS keystore.
+#[cfg(not(target_os = "android"))]
+const LEGACY_MASTER_KEY_FILE: &str = "keys.key";
+#[cfg(not(target_os = "android"))]
+const KEYSTORE_SERVICE: &str = "dev.nipsys.ash";
+#[cfg(not(target_os = "android"))]
+const KEYSTORE_USER: &str = "key-vault";
+// Android has no keystore the app can reach without JNI, so the key stays in
+// the app private storage there, which other apps cannot read.
+#[cfg(target_os = "android")]
+const MASTER_KEY_FILE: &str = "keys.key";
+const VAULT_VERSION: u8 = 2;
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+const ONION_SUFFIX: &str = ".onion";
+
+/// Public half of a client authorization key, to be given to the operator
+/// of the service in the C Tor `descriptor:x25519:<key>` form.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClientAuthKey {
+    pub onion_address: String,
+    pub public_key: String,
+}
+
+impl ClientAuthKey {
+    pub fn new(hsid: &HsId, secret: &HsClientDescEncSecretKey) -> Self {
+        Self {
+            onion_address: hsid.display_unredacted().to_string(),
+            public_key: HsClientDescEncKey::from(secret).to_string(),
+        }
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct VaultContents {
+    /// Base32 x25519 secret keys by onion address.
+    #[serde(default)]
+    client_auth: BTreeMap<String, String>,
+    /// Base32 ed25519 seed of the hosted onion service.
+    #[serde(default)]
+    service_identity: Option<String>,
+    /// Base32 x25519 public keys of the clients allowed to discover the
+    /// hosted onion service, by nickname.
+    #[serde(default)]
+    authorized_clients: BTreeMap<String, String>,
+}
+
+/// Client allowed to discover the onion service hosted by this device.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthorizedClient {
+    pub nickname: String,
+    pub public_key: String,
+}
+
+pub struct KeyVault {
+    directory: Option<PathBuf>,
+    // Serializes the read-modify-write cycles on the vault file.
+    lock: Mutex<()>,
+}
+
+impl KeyVault {
+    pub fn new(directory: Option<PathBuf>) -> Self {
+        Self {
+            directory,
+            lock: Mutex::new(()),
+        }
+    }
+
+    pub fn client_auth_keys(&self) -> Result<Vec<(HsId, HsClientDescEncSecretKey)>> {
+        let _lock = self.lock()?;
+        self.load()?
+            .client_auth
+            .iter()
+            .map(|(address, secret)| Ok((address.parse()?, decode_client_secret(secret)?)))
+            .collect()
+    }
+
+    /// Stores the key for a service, replacing the previous one.
+    pub fn insert_client_auth_key(
+        &self,
+        hsid: &HsId,
+        secret: &HsClientDescEncSecretKey,
+    ) -> Result<ClientAuthKey> {
+        let _lock = self.lock()?;
+        let mut contents = self.load()?;
+        contents.client_auth.insert(
+            hsid.display_unredacted().to_string(),
+            encode_key(secret.as_ref().as_bytes()),
+        );
+        self.save(&contents)?;
+        Ok(ClientAuthKey::new(hsid, secret))
+    }
+
+    /// Returns whether the service had a key.
+    pub fn remove_client_auth_key(&self, hsid: &HsId) -> Result<bool> {
+        let _lock = self.lock()?;
+        let mut contents = self.load()?;
+        let removed = contents
+            .client_auth
+            .remove(&hsid.display_unredacted().to_string())
+            .is_some();
+        if removed {
+            self.save(&contents)?;
+        }
+        Ok(removed)
+    }
+
+    /// Returns the identity key of the hosted onion service, generating it
+    /// the first time.
+    pub fn service_identity(&self) -> Result<HsIdKeypair> {
+        let _lock = self.lock()?;
+        let mut contents = self.load()?;
+
+        let seed = match &contents.service_identity {
+            Some(seed) => decode_key(seed)?,
+            None => {
+                let seed: [u8; 32] = rand::random();
+                contents.service_identity = Some(encode_key(&seed));
+                self.save(&contents)?;
+                seed
+            }
+        };
+
+        let keypair = ed25519::Keypair::from_bytes(&seed);
+        Ok(ed25519::ExpandedKeypair::from(&keypair).into())
+    }
+
+    pub fn authorized_clients(&self) -> Result<Vec<(String, HsClientDescEncKey)>> {
+        let _lock = self.lock()?;
+        self.load()?
+            .authorized_clients
+            .iter()
+            .map(|(nickname, key)| Ok((nickname.clone(), decode_client_public_key(key)?)))
+            .collect()
+    }
+
+    /// Allows a client to discover the hosted service, replacing the key of
+    /// the client with the same nickname.
+    pub fn authorize_client(
+        &self,
+        nickname: &str,
+        public_key: &HsClientDescEncKey,
+    ) -> Result<AuthorizedClient> {
+        let _lock = self.lock()?;
+        let mut contents = self.load()?;
+        contents.authorized_clients.insert(
+            nickname.to_string(),
+            encode_key(public_key.as_ref().as_bytes()),
+        );
+        self.save(&contents)?;
+        Ok(AuthorizedClient {
+            nickname: nickname.to_string(),
+            public_key: public_key.to_string(),
+        })
+    }
+
+    /// Returns whether the client was authorized.
+    pub fn revoke_client(&self, nickname: &str) -> Result<bool> {
+        let _lock = self.lock()?;
+        let mut contents = self.load()?;
+        let removed = contents.authorized_clients.remove(nickname).is_some();
+        if removed {
+            self.save(&contents)?;
+        }
+        Ok(removed)
+    }
+
+    fn lock(&self) -> Result<std::sync::MutexGuard<'_, ()>> {
+        self.lock
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Key vault lock poisoned"))
+    }
+
+    fn directory(&self) -> Result<&Path> {
+        self.directory
+            .as_deref()
+            .ok_or_else(|| anyhow::anyhow!("Failed to get the app data directory"))
+    }
+
+    fn load(&self) -> Result<VaultContents> {
+        let path = self.directory()?.join(VAULT_FILE);
+        let sealed = match std::fs::read(&path) {
+            Ok(sealed) => sealed,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(VaultContents::default())
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        let contents = open(&self.master_key()?, &sealed)?;
+        Ok(serde_json::from_slice(&contents)?)
+    }
+
+    fn save(&self, contents: &VaultContents) -> Result<()> {
+        let directory = self.directory()?;
+        let sealed = seal(&self.master_key()?, &serde_json::to_vec(contents)?)?;
+
+        let temp_path = directory.join(format!("{}.tmp", VAULT_FILE));
+        std::fs::write(&temp_path, sealed)?;
+        std::fs::rename(temp_path, directory.join(VAULT_FILE))?;
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "android"))]
+    fn master_key(&self) -> Result<[u8; 32]> {
+        let entry = keyring::Entry::new(KEYSTORE_SERVICE, KEYSTORE_USER)?;
+        match entry.get_secret() {
+            Ok(key) => {
+                return key
+                    .try_into()
+                    .map_err(|_| anyhow::anyhow!("Key vault master key is corrupted"))
+            }
+            Err(keyring::Error::NoEntry) => {}
+            Err(e) => return Err(e.into()),
+        }
+
+        let key: [u8; 32] = rand::random();
+        entry.set_secret(&key)?;
+        match std::fs::remove_file(self.directory()?.join(LEGACY_MASTER_KEY_FILE)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+        Ok(key)
+    }
+
+    #[cfg(target_os = "android")]
+    fn master_key(&self) -> Result<[u8; 32]> {
+        use std::io::Write;
+
+        let directory = self.directory()?;
+        let path = directory.join(MASTER_KEY_FILE);
+
+        match std::fs::read(&path) {
+            Ok(key) => {
+                return key
+                    .try_into()
+                    .map_err(|_| anyhow::anyhow!("Key vault master key is corrupted"))
+            }
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+            Err(_) => {}
+        }
+
+        std::fs::create_dir_all(directory)?;
+        let key: [u8; 32] = rand::random();
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        options.open(&path)?.write_all(&key)?;
+        Ok(key)
+    }
+}
+
+/// Parses a client authorization key, either as a C Tor `.auth_private` line
+/// (`<address>:descriptor:x25519:<key>`), as `descriptor:x25519:<key>`, or as
+/// the bare base32 key. The address is required unless the line has one.
+pub fn parse_client_auth_key(
+    onion_address: Option<&str>,
+    key: &str,
+) -> Result<(HsId, HsClientDescEncSecretKey)> {
+    let parts: Vec<&str> = key.trim().split(':').collect();
+    let (line_address, secret) = match parts.as_slice() {
+        [address, "descriptor", "x25519", secret] => (Some(*address), *secret),
+        ["descriptor", "x25519", secret] => (None, *secret),
+        [secret] => (None, *secret),
+        _ => return Err(anyhow::anyhow!("Unrecognized client authorization key")),
+    };
+
+    let hsid = match (onion_address, line_address) {
+        (Some(address), Some(line_address)) => {
+            let hsid = parse_onion_address(address)?;
+            if hsid != parse_onion_address(line_address)? {
+                return Err(anyhow::anyhow!(
+                    "The key is for another onion address than {}",
+                    address
+                ));
+            }
+            hsid
+        }
+        (Some(address), None) | (None, Some(address)) => parse_onion_address(address)?,
+        (None, None) => return Err(anyhow::anyhow!("Missing onion address for the key")),
+    };
+
+    Ok((hsid, decode_client_secret(secret)?))
+}
+
+/// Parses an onion address, with or without its `.onion` suffix.
+pub fn parse_onion_address(address: &str) -> Result<HsId> {
+    let address = address.trim().to_ascii_lowercase();
+    let address = if address.ends_with(ONION_SUFFIX) {
+        address
+    } else {
+        format!("{}{}", address, ONION_SUFFIX)
+    };
+    Ok(address.parse()?)
+}
+
+/// Parses the public key of a client of the hosted service, either as
+/// `descriptor:x25519:<key>` or as the bare base32 key.
+pub fn parse_client_public_key(key: &str) -> Result<HsClientDescEncKey> {
+    let key = key.trim();
+    if key.contains(':') {
+        return key
+            .parse()
+            .map_err(|e| anyhow::anyhow!("Invalid client public key: {}", e));
+    }
+    decode_client_public_key(key)
+}
+
+pub fn generate_client_secret() -> HsClientDescEncSecretKey {
+    curve25519::StaticSecret::from(rand::random::<[u8; 32]>()).into()
+}
+
+fn decode_client_secret(secret: &str) -> Result<HsClientDescEncSecretKey> {
+    Ok(curve25519::StaticSecret::from(decode_key(secret)?).into())
+}
+
+fn decode_client_public_key(key: &str) -> Result<HsClientDescEncKey> {
+    Ok(curve25519::PublicKey::from(decode_key(key)?).into())
+}
+
+fn encode_key(key: &[u8; 32]) -> String {
+    data_encoding::BASE32_NOPAD.encode(key)
+}
+
+fn decode_key(key: &str) -> Result<[u8; 32]> {
+    data_encoding::BASE32_NOPAD
+        .decode(key.trim().to_ascii_uppercase().as_bytes())?
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("Invalid key length"))
+}
+
+/// Encrypts with AES-256-GCM, as `version || nonce || ciphertext || tag`.
+/// The version is authenticated along with the contents.
+fn seal(master_key: &[u8; 32], plaintext: &[u8]) -> Result<Vec<u8>> {
+    let nonce: [u8; NONCE_LEN] = rand::random();
+    let mut tag = [0u8; TAG_LEN];
+    let ciphertext = encrypt_aead(
+        Cipher::aes_256_gcm(),
+        master_key,
+        Some(&nonce),
+        &[VAULT_VERSION],
+        plaintext,
+        &mut tag,
+    )?;
+
+    let mut sealed = Vec::with_capacity(1 + NONCE_LEN + ciphertext.len() + TAG_LEN);
+    sealed.push(VAULT_VERSION);
+    sealed.extend_from_slice(&nonce);
+    sealed.extend_from_slice(&ciphertext);
+    sealed.extend_from_slice(&tag);
+    Ok(sealed)
+}
+
+fn open(master_key: &[u8; 32], sealed: &[u8]) -> Result<Vec<u8>> {
+    if sealed.len() < 1 + NONCE_LEN + TAG_LEN {
+        return Err(anyhow::anyhow!("Key vault is truncated"));
+    }
+    if sealed[0] != VAULT_VERSION {
+        return Err(anyhow::anyhow!(
+            "Unsupported key vault version {}",
+            sealed[0]
+        ));
+    }
+
+    let (data, tag) = sealed.split_at(sealed.len() - TAG_LEN);
+    let (nonce, ciphertext) = data[1..].split_at(NONCE_LEN);
+    decrypt_aead(
+        Cipher::aes_256_gcm(),
+        master_key,
+        Some(nonce),
+        &data[..1],
+        ciphertext,
+        tag,
+    )
+    .map_err(|_| anyhow::anyhow!("Key vault is corrupted or was tampered with"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_client_public_keys_with_or_without_prefix() {
+        let public_key = HsClientDescEncKey::from(&generate_client_secret());
+        let line = public_key.to_string();
+        let bare = line.strip_prefix("descriptor:x25519:").unwrap();
+
+        assert_eq!(parse_client_public_key(&line).unwrap(), public_key);
+        assert_eq!(parse_client_public_key(bare).unwrap(), public_key);
+        assert_eq!(
+            parse_client_public_key(&bare.to_ascii_lowercase()).unwrap(),
+            public_key
+        );
+        assert!(parse_client_public_key("descriptor:ed25519:AAAA").is_err());
+    }
+
+    #[test]
+    fn opens_what_it_seals() {
+        let master_key: [u8; 32] = rand::random();
+        let sealed = seal(&master_key, b"contents").unwrap();
+
+        assert_eq!(open(&master_key, &sealed).unwrap(), b"contents");
+        assert!(open(&rand::random(), &sealed).is_err());
+    }
+
+    #[test]
+    fn rejects_tampered_vaults() {
+        let master_key: [u8; 32] = rand::random();
+        let sealed = seal(&master_key, b"contents").unwrap();
+
+        for index in 0..sealed.len() {
+            let mut tampered = sealed.clone();
+            tampered[index] ^= 1;
+            assert!(open(&master_key, &tampered).is_err());
+        }
+        assert!(open(&master_key, &sealed[..sealed.len() - 1]).is_err());
+    }
+}
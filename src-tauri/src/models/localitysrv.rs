@@ -0,0 +1,242 @@
+//! Client of localitysrv's HTTP API, reached over Tor. Searches normally go
+//! through Waku from the webview; this is the way around it when no Waku
+//! peer can be found.
+//!
+//! localitysrv publishes no spec of its HTTP API yet: the paths below follow
+//! the `search_country` and `search_locality` queries localitysrv-waku
+//! relays, and have to be checked against localitysrv before a default URL
+//! is shipped. The tests decode responses shaped after those messages, not
+//! recorded from localitysrv. Until then the fallback is only tried once
+//! the user set a URL.
+
+use anyhow::Result;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::sync::RwLock;
+use tauri::AppHandle;
+
+use crate::models::http::{HttpClient, HttpError, HttpRequest};
+use crate::models::settings::{load_setting, save_setting};
+use crate::models::tor::{StreamIsolation, TorClientWrapper};
+
+const SETTINGS_KEY: &str = "localitysrv_url";
+const TIMEOUT_MS: u64 = 60_000;
+const MAX_RESPONSE_SIZE: u64 = 4 * 1024 * 1024;
+
+// Field names match the protobuf messages localitysrv sends over Waku, see
+// `src/interfaces/localitysrv.ts`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Country {
+    pub country_code: String,
+    pub country_name: String,
+    pub locality_count: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Locality {
+    pub id: String,
+    pub name: String,
+    pub country: String,
+    pub placetype: String,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub min_latitude: f64,
+    pub min_longitude: f64,
+    pub max_latitude: f64,
+    pub max_longitude: f64,
+    pub file_size: u64,
+    pub onion_link: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CountrySearchResponse {
+    #[serde(default)]
+    pub query_id: String,
+    pub countries: Vec<Country>,
+    pub page: u32,
+    pub total: u32,
+    pub total_pages: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LocalitySearchResponse {
+    #[serde(default)]
+    pub query_id: String,
+    pub localities: Vec<Locality>,
+    pub page: u32,
+    pub total: u32,
+    pub total_pages: u32,
+}
+
+/// Search terms and page of the results, counted from 1.
+#[derive(Debug, Clone)]
+pub struct SearchQuery {
+    pub query: String,
+    pub page: u32,
+    pub limit: u32,
+}
+
+/// Base URL of the localitysrv onion service, persisted in the settings
+/// store.
+pub struct LocalitysrvClient {
+    app_handle: AppHandle,
+    url: RwLock<Option<String>>,
+}
+
+impl LocalitysrvClient {
+    pub fn new(app_handle: AppHandle) -> Self {
+        let url = load_setting(&app_handle, SETTINGS_KEY).unwrap_or_else(|e| {
+            eprintln!("Failed to load localitysrv URL: {}", e);
+            None
+        });
+
+        Self {
+            app_handle,
+            url: RwLock::new(url),
+        }
+    }
+
+    pub fn url(&self) -> Option<String> {
+        self.url.read().ok().and_then(|url| url.clone())
+    }
+
+    pub fn set_url(&self, url: Option<String>) -> Result<()> {
+        let url = url
+            .map(|url| url.trim().trim_end_matches('/').to_string())
+            .filter(|url| !url.is_empty());
+        if let Some(url) = &url {
+            let uri = url.parse::<hyper::Uri>()?;
+            if !uri.host().is_some_and(|host| host.ends_with(".onion")) {
+                return Err(anyhow::anyhow!("localitysrv must be an onion service"));
+            }
+        }
+
+        save_setting(&self.app_handle, SETTINGS_KEY, &url)?;
+        if let Ok(mut current) = self.url.write() {
+            *current = url;
+        }
+        Ok(())
+    }
+
+    pub async fn search_countries(
+        &self,
+        http_client: &HttpClient,
+        tor_client: &TorClientWrapper,
+        isolation: Option<StreamIsolation>,
+        query: &SearchQuery,
+    ) -> Result<CountrySearchResponse, HttpError> {
+        let url = self.endpoint("countries", query)?;
+        fetch_json(http_client, tor_client, isolation, &url).await
+    }
+
+    pub async fn search_localities(
+        &self,
+        http_client: &HttpClient,
+        tor_client: &TorClientWrapper,
+        isolation: Option<StreamIsolation>,
+        country_code: &str,
+        query: &SearchQuery,
+    ) -> Result<LocalitySearchResponse, HttpError> {
+        let path = format!(
+            "countries/{}/localities",
+            form_urlencoded::byte_serialize(country_code.as_bytes()).collect::<String>()
+        );
+        let url = self.endpoint(&path, query)?;
+        fetch_json(http_client, tor_client, isolation, &url).await
+    }
+
+    fn endpoint(&self, path: &str, query: &SearchQuery) -> Result<String, HttpError> {
+        let base = self
+            .url()
+            .ok_or_else(|| HttpError::invalid_url("localitysrv URL is not set"))?;
+        Ok(endpoint_url(&base, path, query))
+    }
+}
+
+fn endpoint_url(base: &str, path: &str, query: &SearchQuery) -> String {
+    let params = form_urlencoded::Serializer::new(String::new())
+        .append_pair("query", &query.query)
+        .append_pair("page", &query.page.to_string())
+        .append_pair("limit", &query.limit.to_string())
+        .finish();
+    format!("{}/{}?{}", base, path, params)
+}
+
+async fn fetch_json<T: DeserializeOwned>(
+    http_client: &HttpClient,
+    tor_client: &TorClientWrapper,
+    isolation: Option<StreamIsolation>,
+    url: &str,
+) -> Result<T, HttpError> {
+    let mut request = HttpRequest::get(url);
+    request
+        .headers
+        .push(("Accept".to_string(), "application/json".to_string()));
+    request.timeout_ms = Some(TIMEOUT_MS);
+    request.max_size = Some(MAX_RESPONSE_SIZE);
+
+    let response = http_client
+        .send(request, tor_client, isolation, None)
+        .await?;
+    if !(200..300).contains(&response.status) {
+        return Err(HttpError::Status {
+            status: response.status,
+            message: format!("localitysrv answered with status: {}", response.status),
+        });
+    }
+
+    serde_json::from_slice(&response.body)
+        .map_err(|e| HttpError::other(format!("Invalid localitysrv response: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_search_urls() {
+        let query = SearchQuery {
+            query: "saint étienne".to_string(),
+            page: 2,
+            limit: 20,
+        };
+        assert_eq!(
+            endpoint_url("http://a.onion", "countries/FR/localities", &query),
+            "http://a.onion/countries/FR/localities?query=saint+%C3%A9tienne&page=2&limit=20"
+        );
+    }
+
+    #[test]
+    fn decodes_search_responses() {
+        let countries: CountrySearchResponse = serde_json::from_str(
+            r#"{"countries": [{"country_code": "FR", "country_name": "France",
+                "locality_count": 36}], "total": 1, "page": 1, "total_pages": 1}"#,
+        )
+        .unwrap();
+        assert_eq!(countries.query_id, "");
+        assert_eq!(countries.countries[0].country_code, "FR");
+        assert_eq!(countries.countries[0].locality_count, 36);
+
+        let localities: LocalitySearchResponse = serde_json::from_str(
+            r#"{"query_id": "q1", "localities": [{"id": "101751119", "name": "Paris",
+                "country": "FR", "placetype": "locality", "latitude": 48.85,
+                "longitude": 2.35, "min_latitude": 48.81, "min_longitude": 2.22,
+                "max_latitude": 48.9, "max_longitude": 2.47, "file_size": 52428800,
+                "onion_link": "http://a.onion/maps/101751119.pmtiles"}],
+                "total": 1, "page": 1, "total_pages": 1}"#,
+        )
+        .unwrap();
+        let paris = &localities.localities[0];
+        assert_eq!(localities.query_id, "q1");
+        assert_eq!(paris.id, "101751119");
+        assert_eq!(paris.file_size, 52_428_800);
+        assert_eq!(paris.onion_link, "http://a.onion/maps/101751119.pmtiles");
+    }
+
+    #[test]
+    fn refuses_responses_missing_fields() {
+        let response = r#"{"localities": [{"id": "1", "name": "Paris"}],
+            "total": 1, "page": 1, "total_pages": 1}"#;
+        assert!(serde_json::from_str::<LocalitySearchResponse>(response).is_err());
+    }
+}
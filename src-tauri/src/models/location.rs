@@ -0,0 +1,506 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tauri::async_runtime::JoinHandle;
+use tauri::{AppHandle, Emitter};
+use tokio::sync::broadcast;
+
+use crate::models::features::parse_gpx_points;
+use crate::models::geo::Coordinate;
+use crate::models::privacy::LocationPrivacy;
+
+const FIX_CHANNEL_CAPACITY: usize = 64;
+// Rough conversion from NMEA horizontal dilution of precision to meters.
+const HDOP_TO_METERS: f64 = 5.0;
+const KNOTS_TO_METERS_PER_SECOND: f64 = 0.514_444;
+const MAX_REPLAY_GAP: Duration = Duration::from_secs(30);
+// Pace of recorded points without a time.
+const DEFAULT_REPLAY_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LocationSource {
+    Gps,
+    Manual,
+    Replay,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LocationFix {
+    pub latitude: f64,
+    pub longitude: f64,
+    /// Horizontal accuracy in meters, when known.
+    pub accuracy: Option<f64>,
+    pub altitude: Option<f64>,
+    /// Speed in meters per second.
+    pub speed: Option<f64>,
+    /// Heading in degrees from true north.
+    pub heading: Option<f64>,
+    /// Milliseconds since the Unix epoch.
+    pub timestamp: u64,
+    pub source: LocationSource,
+}
+
+impl LocationFix {
+    pub fn coordinate(&self) -> Coordinate {
+        Coordinate::new(self.latitude, self.longitude)
+    }
+}
+
+pub fn now_millis() -> u64 {
+    millis_since_epoch(SystemTime::now())
+}
+
+pub fn millis_since_epoch(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+pub type FixSender = broadcast::Sender<LocationFix>;
+
+/// A source of position fixes.
+///
+/// Providers push fixes into the sender given to `start` until `stop` is
+/// called or they run out of data.
+pub trait LocationProvider: Send + Sync {
+    fn source(&self) -> LocationSource;
+    fn start(&mut self, fixes: FixSender) -> Result<()>;
+    fn stop(&mut self);
+}
+
+/// Position pinned by hand on the map.
+pub struct ManualProvider {
+    fix: LocationFix,
+}
+
+impl ManualProvider {
+    pub fn new(latitude: f64, longitude: f64, accuracy: Option<f64>) -> Self {
+        Self {
+            fix: LocationFix {
+                latitude,
+                longitude,
+                accuracy,
+                altitude: None,
+                speed: None,
+                heading: None,
+                timestamp: now_millis(),
+                source: LocationSource::Manual,
+            },
+        }
+    }
+}
+
+impl LocationProvider for ManualProvider {
+    fn source(&self) -> LocationSource {
+        LocationSource::Manual
+    }
+
+    fn start(&mut self, fixes: FixSender) -> Result<()> {
+        let _ = fixes.send(self.fix.clone());
+        Ok(())
+    }
+
+    fn stop(&mut self) {}
+}
+
+/// Replays a recorded GPX track or NMEA log, for testing on desktop.
+pub struct ReplayProvider {
+    fixes: Vec<LocationFix>,
+    speed: f64,
+    task: Option<JoinHandle<()>>,
+}
+
+impl ReplayProvider {
+    pub fn from_path(path: &Path, speed: f64) -> Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+        let fixes = if content.trim_start().starts_with('<') {
+            parse_gpx_points(&content)?
+                .into_iter()
+                .map(|point| LocationFix {
+                    latitude: point.coordinate.latitude,
+                    longitude: point.coordinate.longitude,
+                    accuracy: None,
+                    altitude: point.elevation,
+                    speed: None,
+                    heading: None,
+                    timestamp: point.time.unwrap_or_default(),
+                    source: LocationSource::Replay,
+                })
+                .collect()
+        } else {
+            parse_nmea(&content)
+        };
+
+        if fixes.is_empty() {
+            return Err(anyhow::anyhow!("No position found in {}", path.display()));
+        }
+
+        Ok(Self {
+            fixes,
+            speed: if speed > 0.0 { speed } else { 1.0 },
+            task: None,
+        })
+    }
+}
+
+impl LocationProvider for ReplayProvider {
+    fn source(&self) -> LocationSource {
+        LocationSource::Replay
+    }
+
+    fn start(&mut self, fixes: FixSender) -> Result<()> {
+        let recorded = self.fixes.clone();
+        let speed = self.speed;
+
+        self.task = Some(tauri::async_runtime::spawn(async move {
+            let mut previous_timestamp = None;
+            for mut fix in recorded {
+                if let Some(previous) = previous_timestamp {
+                    tokio::time::sleep(replay_gap(previous, fix.timestamp).div_f64(speed)).await;
+                }
+                previous_timestamp = Some(fix.timestamp);
+
+                fix.timestamp = now_millis();
+                let _ = fixes.send(fix);
+            }
+        }));
+
+        Ok(())
+    }
+
+    fn stop(&mut self) {
+        if let Some(task) = self.task.take() {
+            task.abort();
+        }
+    }
+}
+
+/// Time between two recorded fixes, timestamps of 0 meaning the time was
+/// not recorded.
+fn replay_gap(previous: u64, timestamp: u64) -> Duration {
+    if previous == 0 || timestamp == 0 {
+        return DEFAULT_REPLAY_INTERVAL;
+    }
+    Duration::from_millis(timestamp.saturating_sub(previous)).min(MAX_REPLAY_GAP)
+}
+
+impl Drop for ReplayProvider {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Platform GPS through the geolocation plugin.
+#[cfg(any(target_os = "android", target_os = "ios"))]
+pub struct GpsProvider {
+    app_handle: AppHandle,
+    watch_id: Option<u32>,
+}
+
+#[cfg(any(target_os = "android", target_os = "ios"))]
+impl GpsProvider {
+    pub fn new(app_handle: AppHandle) -> Self {
+        Self {
+            app_handle,
+            watch_id: None,
+        }
+    }
+}
+
+#[cfg(any(target_os = "android", target_os = "ios"))]
+impl LocationProvider for GpsProvider {
+    fn source(&self) -> LocationSource {
+        LocationSource::Gps
+    }
+
+    fn start(&mut self, fixes: FixSender) -> Result<()> {
+        use tauri_plugin_geolocation::{GeolocationExt, PositionOptions, WatchEvent};
+
+        let geolocation = self.app_handle.geolocation();
+        geolocation.request_permissions(None)?;
+
+        let options = PositionOptions {
+            enable_high_accuracy: true,
+            timeout: 10_000,
+            maximum_age: 0,
+        };
+        let watch_id = geolocation.watch_position(options, move |event| match event {
+            WatchEvent::Position(position) => {
+                let _ = fixes.send(LocationFix {
+                    latitude: position.coords.latitude,
+                    longitude: position.coords.longitude,
+                    accuracy: Some(position.coords.accuracy),
+                    altitude: position.coords.altitude,
+                    speed: position.coords.speed,
+                    heading: position.coords.heading,
+                    timestamp: position.timestamp,
+                    source: LocationSource::Gps,
+                });
+            }
+            WatchEvent::Error(e) => eprintln!("Location error: {}", e),
+        })?;
+
+        self.watch_id = Some(watch_id);
+        Ok(())
+    }
+
+    fn stop(&mut self) {
+        use tauri_plugin_geolocation::GeolocationExt;
+
+        if let Some(watch_id) = self.watch_id.take() {
+            if let Err(e) = self.app_handle.geolocation().clear_watch(watch_id) {
+                eprintln!("Failed to clear location watch: {}", e);
+            }
+        }
+    }
+}
+
+/// Owns the active provider and forwards its fixes to the webview as
+/// `location-fix` events, after the location privacy settings. Subscribers
+/// in the backend get the exact fixes.
+pub struct LocationService {
+    fixes: FixSender,
+    provider: Mutex<Option<Box<dyn LocationProvider>>>,
+    last_fix: Arc<RwLock<Option<LocationFix>>>,
+}
+
+impl LocationService {
+    pub fn new(app_handle: AppHandle, privacy: Arc<LocationPrivacy>, pmtiles_dir: PathBuf) -> Self {
+        let (fixes, mut receiver) = broadcast::channel::<LocationFix>(FIX_CHANNEL_CAPACITY);
+        let last_fix = Arc::new(RwLock::new(None));
+
+        let last_fix_clone = last_fix.clone();
+        tauri::async_runtime::spawn(async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(fix) => {
+                        // A fix the settings could not be applied to is
+                        // dropped rather than shared exactly.
+                        let fix = match privacy.apply_to_fix(fix, &pmtiles_dir).await {
+                            Ok(fix) => fix,
+                            Err(e) => {
+                                eprintln!("Failed to apply location privacy: {}", e);
+                                continue;
+                            }
+                        };
+
+                        let delay = privacy.settings().delay();
+                        if delay.is_zero() {
+                            publish_fix(&app_handle, &last_fix_clone, fix);
+                        } else {
+                            let app_handle = app_handle.clone();
+                            let last_fix = last_fix_clone.clone();
+                            tauri::async_runtime::spawn(async move {
+                                tokio::time::sleep(delay).await;
+                                publish_fix(&app_handle, &last_fix, fix);
+                            });
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        });
+
+        Self {
+            fixes,
+            provider: Mutex::new(None),
+            last_fix,
+        }
+    }
+
+    pub fn start(&self, mut provider: Box<dyn LocationProvider>) -> Result<()> {
+        self.stop();
+        provider.start(self.fixes.clone())?;
+
+        let mut active = self
+            .provider
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Location provider lock poisoned"))?;
+        *active = Some(provider);
+        Ok(())
+    }
+
+    pub fn stop(&self) {
+        if let Ok(mut active) = self.provider.lock() {
+            if let Some(mut provider) = active.take() {
+                provider.stop();
+            }
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<LocationFix> {
+        self.fixes.subscribe()
+    }
+
+    /// Last fix handed to the webview.
+    pub fn last_fix(&self) -> Option<LocationFix> {
+        self.last_fix.read().ok().and_then(|fix| fix.clone())
+    }
+}
+
+fn publish_fix(app_handle: &AppHandle, last_fix: &RwLock<Option<LocationFix>>, fix: LocationFix) {
+    if let Ok(mut last_fix) = last_fix.write() {
+        *last_fix = Some(fix.clone());
+    }
+    if let Err(e) = app_handle.emit("location-fix", fix) {
+        eprintln!("Failed to emit location fix: {}", e);
+    }
+}
+
+/// Reads fixes from NMEA 0183 `RMC` and `GGA` sentences.
+pub fn parse_nmea(content: &str) -> Vec<LocationFix> {
+    let mut fixes: Vec<LocationFix> = Vec::new();
+    let mut last_time: Option<String> = None;
+    let mut date: Option<chrono::NaiveDate> = None;
+
+    for line in content.lines() {
+        let Some(sentence) = checked_nmea_sentence(line.trim()) else {
+            continue;
+        };
+        let fields: Vec<&str> = sentence.split(',').collect();
+        let Some(kind) = fields.first().filter(|kind| kind.len() == 5) else {
+            continue;
+        };
+
+        match &kind[2..] {
+            "RMC" if fields.len() >= 10 && fields[2] == "A" => {
+                date = chrono::NaiveDate::parse_from_str(fields[9], "%d%m%y")
+                    .ok()
+                    .or(date);
+                let Some(coordinate) = nmea_coordinate(&fields[3..7]) else {
+                    continue;
+                };
+
+                let fix = upsert_nmea_fix(&mut fixes, &mut last_time, fields[1], date, coordinate);
+                fix.speed = fields[7]
+                    .parse::<f64>()
+                    .ok()
+                    .map(|knots| knots * KNOTS_TO_METERS_PER_SECOND);
+                fix.heading = fields[8].parse().ok();
+            }
+            "GGA" if fields.len() >= 10 && fields[6] != "0" => {
+                let Some(coordinate) = nmea_coordinate(&fields[2..6]) else {
+                    continue;
+                };
+
+                let fix = upsert_nmea_fix(&mut fixes, &mut last_time, fields[1], date, coordinate);
+                fix.accuracy = fields[8]
+                    .parse::<f64>()
+                    .ok()
+                    .map(|hdop| hdop * HDOP_TO_METERS);
+                fix.altitude = fields[9].parse().ok();
+            }
+            _ => {}
+        }
+    }
+
+    fixes
+}
+
+fn checked_nmea_sentence(line: &str) -> Option<&str> {
+    let body = line.strip_prefix('$')?;
+    match body.split_once('*') {
+        Some((sentence, checksum)) => {
+            let expected = u8::from_str_radix(checksum.get(..2)?, 16).ok()?;
+            let actual = sentence.bytes().fold(0u8, |acc, b| acc ^ b);
+            (expected == actual).then_some(sentence)
+        }
+        None => Some(body),
+    }
+}
+
+fn nmea_coordinate(fields: &[&str]) -> Option<Coordinate> {
+    let degrees = |value: &str, degree_digits: usize, hemisphere: &str, negative: &str| {
+        let whole = value.get(..degree_digits)?.parse::<f64>().ok()?;
+        let minutes = value.get(degree_digits..)?.parse::<f64>().ok()?;
+        let degrees = whole + minutes / 60.0;
+        Some(if hemisphere == negative {
+            -degrees
+        } else {
+            degrees
+        })
+    };
+
+    Some(Coordinate::new(
+        degrees(fields[0], 2, fields[1], "S")?,
+        degrees(fields[2], 3, fields[3], "W")?,
+    ))
+}
+
+/// Returns the fix for the sentence time, creating it when the time changes
+/// so that `RMC` and `GGA` sentences of the same epoch are merged.
+fn upsert_nmea_fix<'a>(
+    fixes: &'a mut Vec<LocationFix>,
+    last_time: &mut Option<String>,
+    time: &str,
+    date: Option<chrono::NaiveDate>,
+    coordinate: Coordinate,
+) -> &'a mut LocationFix {
+    if last_time.as_deref() != Some(time) || fixes.is_empty() {
+        let timestamp = chrono::NaiveTime::parse_from_str(time, "%H%M%S%.f")
+            .ok()
+            .map(|time| {
+                date.unwrap_or_default()
+                    .and_time(time)
+                    .and_utc()
+                    .timestamp_millis()
+                    .max(0) as u64
+            })
+            .unwrap_or_default();
+
+        fixes.push(LocationFix {
+            latitude: coordinate.latitude,
+            longitude: coordinate.longitude,
+            accuracy: None,
+            altitude: None,
+            speed: None,
+            heading: None,
+            timestamp,
+            source: LocationSource::Replay,
+        });
+        *last_time = Some(time.to_string());
+    }
+
+    let fix = fixes.last_mut().expect("fix was just pushed");
+    fix.latitude = coordinate.latitude;
+    fix.longitude = coordinate.longitude;
+    fix
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merges_nmea_sentences_of_the_same_epoch() {
+        let fixes = parse_nmea(
+            "$GPRMC,123519,A,4807.038,N,01131.000,E,022.4,084.4,230394,003.1,W\n\
+             $GPGGA,123519,4807.038,N,01131.000,E,1,08,0.9,545.4,M,46.9,M,,\n\
+             $GPGGA,123520,4807.040,N,01131.000,E,1,08,0.9,545.4,M,46.9,M,,*00\n\
+             $GPGGA,123521,4807.040,S,01131.000,W,1,08,0.9,545.4,M,46.9,M,,",
+        );
+
+        assert_eq!(fixes.len(), 2);
+        assert!((fixes[0].latitude - 48.1173).abs() < 1e-4);
+        assert!((fixes[0].longitude - 11.516_666).abs() < 1e-4);
+        assert_eq!(fixes[0].altitude, Some(545.4));
+        assert!((fixes[0].speed.unwrap() - 22.4 * KNOTS_TO_METERS_PER_SECOND).abs() < 1e-9);
+        assert_eq!(fixes[0].heading, Some(84.4));
+        assert!(fixes[1].latitude < 0.0 && fixes[1].longitude < 0.0);
+        assert_eq!(fixes[1].timestamp - fixes[0].timestamp, 2_000);
+    }
+
+    #[test]
+    fn paces_points_without_time() {
+        assert_eq!(replay_gap(0, 0), DEFAULT_REPLAY_INTERVAL);
+        assert_eq!(replay_gap(1_000, 0), DEFAULT_REPLAY_INTERVAL);
+        assert_eq!(replay_gap(1_000, 3_500), Duration::from_millis(2_500));
+        assert_eq!(replay_gap(1_000, 1_000), Duration::ZERO);
+        assert_eq!(replay_gap(1_000, 10_000_000), MAX_REPLAY_GAP);
+    }
+}
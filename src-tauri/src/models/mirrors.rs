@@ -0,0 +1,234 @@
+//! Reliability of the onion services mirroring the map files, so that
+//! downloads start with the mirrors that served them well before.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::RwLock;
+use std::time::Duration;
+use tauri::AppHandle;
+
+use crate::models::location::now_millis;
+use crate::models::settings::{load_setting, save_setting};
+
+const SETTINGS_KEY: &str = "mirror_stats";
+// A mirror failing again and again is tried last for a while.
+const FAILURE_COOLDOWN: Duration = Duration::from_secs(10 * 60);
+const COOLDOWN_FAILURES: u32 = 2;
+// Weight of the latest download in the average throughput.
+const THROUGHPUT_WEIGHT: f64 = 0.3;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MirrorStats {
+    pub successes: u32,
+    pub failures: u32,
+    pub consecutive_failures: u32,
+    /// Milliseconds since the Unix epoch.
+    pub last_success: Option<u64>,
+    /// Milliseconds since the Unix epoch.
+    pub last_failure: Option<u64>,
+    pub last_error: Option<String>,
+    /// Moving average of the download throughput.
+    pub bytes_per_second: Option<f64>,
+}
+
+impl MirrorStats {
+    /// Share of successful downloads, 0.5 for a mirror never tried.
+    fn reliability(&self) -> f64 {
+        f64::from(self.successes + 1) / f64::from(self.successes + self.failures + 2)
+    }
+
+    fn cooling_down(&self, now: u64) -> bool {
+        self.consecutive_failures >= COOLDOWN_FAILURES
+            && self.last_failure.is_some_and(|last_failure| {
+                now.saturating_sub(last_failure) < FAILURE_COOLDOWN.as_millis() as u64
+            })
+    }
+}
+
+/// Stats by onion host, persisted in the settings store. Mirrors are told
+/// apart by host, since one service serves all the files of a mirror.
+pub struct MirrorRegistry {
+    app_handle: AppHandle,
+    stats: RwLock<BTreeMap<String, MirrorStats>>,
+}
+
+impl MirrorRegistry {
+    pub fn new(app_handle: AppHandle) -> Self {
+        let stats = load_setting(&app_handle, SETTINGS_KEY)
+            .unwrap_or_else(|e| {
+                eprintln!("Failed to load mirror stats: {}", e);
+                None
+            })
+            .unwrap_or_default();
+
+        Self {
+            app_handle,
+            stats: RwLock::new(stats),
+        }
+    }
+
+    pub fn stats(&self) -> BTreeMap<String, MirrorStats> {
+        self.stats
+            .read()
+            .map(|stats| stats.clone())
+            .unwrap_or_default()
+    }
+
+    /// Orders the URLs from the most to the least reliable mirror, see
+    /// [`rank_urls`].
+    pub fn rank(&self, urls: &[String]) -> Vec<String> {
+        rank_urls(&self.stats(), urls, now_millis())
+    }
+
+    pub fn record_success(&self, url: &str, bytes: u64, elapsed: Duration) {
+        self.update(url, |stats| {
+            stats.successes += 1;
+            stats.consecutive_failures = 0;
+            stats.last_success = Some(now_millis());
+
+            let seconds = elapsed.as_secs_f64();
+            if seconds > 0.0 {
+                let throughput = bytes as f64 / seconds;
+                stats.bytes_per_second = Some(match stats.bytes_per_second {
+                    Some(average) => average + THROUGHPUT_WEIGHT * (throughput - average),
+                    None => throughput,
+                });
+            }
+        });
+    }
+
+    pub fn record_failure(&self, url: &str, error: &str) {
+        self.update(url, |stats| {
+            stats.failures += 1;
+            stats.consecutive_failures += 1;
+            stats.last_failure = Some(now_millis());
+            stats.last_error = Some(error.to_string());
+        });
+    }
+
+    fn update(&self, url: &str, update: impl FnOnce(&mut MirrorStats)) {
+        let Ok(mut stats) = self.stats.write() else {
+            return;
+        };
+        update(stats.entry(mirror_key(url)).or_default());
+
+        if let Err(e) = save_setting(&self.app_handle, SETTINGS_KEY, &*stats) {
+            eprintln!("Failed to save mirror stats: {}", e);
+        }
+    }
+}
+
+/// Orders the URLs from the most to the least reliable mirror, keeping the
+/// given order between equally reliable ones. Mirrors that failed repeatedly
+/// and recently come last.
+fn rank_urls(stats: &BTreeMap<String, MirrorStats>, urls: &[String], now: u64) -> Vec<String> {
+    let mut ranked: Vec<(bool, f64, &String)> = urls
+        .iter()
+        .map(|url| {
+            let stats = stats.get(&mirror_key(url)).cloned().unwrap_or_default();
+            (stats.cooling_down(now), stats.reliability(), url)
+        })
+        .collect();
+    ranked.sort_by(|a, b| a.0.cmp(&b.0).then(b.1.total_cmp(&a.1)));
+    ranked.into_iter().map(|(_, _, url)| url.clone()).collect()
+}
+
+fn mirror_key(url: &str) -> String {
+    url.parse::<hyper::Uri>()
+        .ok()
+        .and_then(|uri| uri.host().map(str::to_ascii_lowercase))
+        .unwrap_or_else(|| url.to_string())
+}
+
+/// Checks a downloaded file against its expected hex encoded SHA-256. The
+/// file is hashed off the async runtime.
+pub async fn verify_sha256(expected: &str, path: &Path) -> Result<()> {
+    let expected = data_encoding::HEXLOWER_PERMISSIVE.decode(expected.trim().as_bytes())?;
+    let path = path.to_path_buf();
+    let actual = tokio::task::spawn_blocking(move || -> Result<_> {
+        let mut hasher = Sha256::new();
+        std::io::copy(&mut std::fs::File::open(path)?, &mut hasher)?;
+        Ok(hasher.finalize())
+    })
+    .await??;
+    if actual.as_slice() != expected.as_slice() {
+        return Err(anyhow::anyhow!(
+            "SHA-256 mismatch, got {}",
+            data_encoding::HEXLOWER.encode(&actual)
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 1_000_000_000;
+
+    fn urls(hosts: &[&str]) -> Vec<String> {
+        hosts
+            .iter()
+            .map(|host| format!("http://{}.onion/1.pmtiles", host))
+            .collect()
+    }
+
+    fn stats(successes: u32, failures: u32, consecutive_failures: u32) -> MirrorStats {
+        MirrorStats {
+            successes,
+            failures,
+            consecutive_failures,
+            last_failure: (failures > 0).then_some(NOW - 1000),
+            ..MirrorStats::default()
+        }
+    }
+
+    #[test]
+    fn ranks_reliable_mirrors_first() {
+        let stats = BTreeMap::from([
+            ("a.onion".to_string(), stats(1, 3, 0)),
+            ("c.onion".to_string(), stats(5, 0, 0)),
+        ]);
+
+        // The untried mirror b sits between the unreliable a and the
+        // reliable c, and equal mirrors keep their order.
+        assert_eq!(
+            rank_urls(&stats, &urls(&["a", "b", "c", "d"]), NOW),
+            urls(&["c", "b", "d", "a"])
+        );
+    }
+
+    #[test]
+    fn ranks_failing_mirrors_last_while_they_cool_down() {
+        let stats = BTreeMap::from([("a.onion".to_string(), stats(50, 2, 2))]);
+
+        assert_eq!(
+            rank_urls(&stats, &urls(&["a", "b"]), NOW),
+            urls(&["b", "a"])
+        );
+        let later = NOW + FAILURE_COOLDOWN.as_millis() as u64;
+        assert_eq!(
+            rank_urls(&stats, &urls(&["b", "a"]), later),
+            urls(&["a", "b"])
+        );
+    }
+
+    #[tokio::test]
+    async fn checks_the_sha256_of_files() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(file.path(), b"test").unwrap();
+
+        let sha256 = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08";
+        assert!(verify_sha256(sha256, file.path()).await.is_ok());
+        assert!(verify_sha256(&sha256.to_uppercase(), file.path())
+            .await
+            .is_ok());
+        assert!(verify_sha256(&sha256.replace('9', "8"), file.path())
+            .await
+            .is_err());
+    }
+}
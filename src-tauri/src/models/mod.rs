@@ -1,6 +1,28 @@
 pub mod app;
+pub mod bridges;
+pub mod download;
+pub mod download_service;
+pub mod features;
+pub mod geo;
+pub mod geofence;
+pub mod group;
 pub mod http;
+pub mod key_vault;
+pub mod localitysrv;
+pub mod location;
 pub mod map;
+pub mod mirrors;
+pub mod network;
+pub mod onion_service;
+pub mod privacy;
+pub mod road_graph;
+pub mod routing;
+pub mod settings;
+pub mod socks;
+pub mod tls_pins;
 pub mod tor;
+pub mod tor_bundle;
+pub mod tor_status;
+pub mod vector_tile;
 
 pub use app::*;
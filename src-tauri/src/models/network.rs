@@ -0,0 +1,153 @@
+//! Limits on the data the app downloads, for users on metered connections.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant};
+use tauri::AppHandle;
+
+use crate::models::settings::{load_setting, save_setting};
+
+const SETTINGS_KEY: &str = "network_settings";
+const DEFAULT_METERED_DOWNLOAD_LIMIT: u64 = 10 * 1024 * 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NetworkSettings {
+    /// Limit of the download rate of all requests together, `None` for no
+    /// limit.
+    #[serde(default)]
+    pub max_bytes_per_second: Option<u64>,
+    /// Downloads larger than `metered_download_limit` must be confirmed.
+    #[serde(default)]
+    pub metered: bool,
+    #[serde(default = "default_metered_download_limit")]
+    pub metered_download_limit: u64,
+}
+
+impl Default for NetworkSettings {
+    fn default() -> Self {
+        Self {
+            max_bytes_per_second: None,
+            metered: false,
+            metered_download_limit: DEFAULT_METERED_DOWNLOAD_LIMIT,
+        }
+    }
+}
+
+fn default_metered_download_limit() -> u64 {
+    DEFAULT_METERED_DOWNLOAD_LIMIT
+}
+
+/// Token bucket holding up to a second of traffic. Readers take tokens for
+/// what they received, and wait for the debt to be paid back.
+struct TokenBucket {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl TokenBucket {
+    /// Takes `bytes` received at `now`, returning how long to wait for the
+    /// bucket to be back out of debt.
+    fn take(&mut self, bytes: usize, rate: f64, now: Instant) -> Option<Duration> {
+        let elapsed = now.duration_since(self.refilled_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate).min(rate) - bytes as f64;
+        self.refilled_at = now;
+        (self.tokens < 0.0).then(|| Duration::from_secs_f64(-self.tokens / rate))
+    }
+}
+
+pub struct NetworkPolicy {
+    app_handle: AppHandle,
+    settings: RwLock<NetworkSettings>,
+    bucket: Mutex<TokenBucket>,
+}
+
+impl NetworkPolicy {
+    pub fn new(app_handle: AppHandle) -> Self {
+        let settings = load_setting(&app_handle, SETTINGS_KEY)
+            .unwrap_or_else(|e| {
+                eprintln!("Failed to load network settings: {}", e);
+                None
+            })
+            .unwrap_or_default();
+
+        Self {
+            app_handle,
+            settings: RwLock::new(settings),
+            bucket: Mutex::new(TokenBucket {
+                tokens: 0.0,
+                refilled_at: Instant::now(),
+            }),
+        }
+    }
+
+    pub fn settings(&self) -> NetworkSettings {
+        self.settings
+            .read()
+            .map(|settings| settings.clone())
+            .unwrap_or_default()
+    }
+
+    pub fn set_settings(&self, settings: NetworkSettings) -> Result<()> {
+        if settings.max_bytes_per_second == Some(0) {
+            return Err(anyhow::anyhow!("Rate limit must be positive"));
+        }
+
+        save_setting(&self.app_handle, SETTINGS_KEY, &settings)?;
+        if let Ok(mut current) = self.settings.write() {
+            *current = settings;
+        }
+        Ok(())
+    }
+
+    /// Size above which a download needs the confirmation of the user,
+    /// `None` unless the connection is metered.
+    pub fn download_limit(&self) -> Option<u64> {
+        let settings = self.settings();
+        settings.metered.then_some(settings.metered_download_limit)
+    }
+
+    /// Accounts for `bytes` received, waiting as long as the rate limit
+    /// requires.
+    pub async fn throttle(&self, bytes: usize) {
+        let Some(rate) = self.settings().max_bytes_per_second else {
+            return;
+        };
+        let wait = match self.bucket.lock() {
+            Ok(mut bucket) => bucket.take(bytes, rate as f64, Instant::now()),
+            Err(_) => None,
+        };
+        if let Some(wait) = wait {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn token_bucket_holds_a_second_of_traffic() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket {
+            tokens: 0.0,
+            refilled_at: start,
+        };
+
+        // Idle time fills the bucket up to a second worth of bytes.
+        let now = start + Duration::from_secs(10);
+        assert_eq!(bucket.take(1000, 1000.0, now), None);
+        // Going over it waits for the debt to be paid back.
+        assert_eq!(
+            bucket.take(500, 1000.0, now),
+            Some(Duration::from_millis(500))
+        );
+        assert_eq!(
+            bucket.take(500, 1000.0, now + Duration::from_millis(500)),
+            Some(Duration::from_millis(500))
+        );
+        assert_eq!(bucket.take(0, 1000.0, now + Duration::from_secs(1)), None);
+    }
+}
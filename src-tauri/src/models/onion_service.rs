@@ -0,0 +1,506 @@
+//! Opt-in onion service letting group members reach this device directly.
+//! It currently serves the downloaded maps, so that peers can fetch them
+//! from each other with `download_map` when no map server is reachable.
+//! Restricted discovery is always on: only the clients authorized with
+//! `authorize_onion_client` can fetch the descriptor and reach the service.
+
+use anyhow::Result;
+use arti_client::config::onion_service::{OnionServiceConfig, OnionServiceConfigBuilder};
+use arti_client::config::Reconfigure;
+use bytes::Bytes;
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use http_body_util::combinators::BoxBody;
+use http_body_util::{BodyExt, Empty, StreamBody};
+use hyper::body::Frame;
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use safelog::DisplayRedacted;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio::task::JoinHandle;
+use tor_cell::relaycell::msg::{Connected, End};
+use tor_hscrypto::pk::{HsId, HsIdKey};
+use tor_hsservice::config::restricted_discovery::HsClientNickname;
+use tor_hsservice::{handle_rend_requests, HsNickname, RendRequest, RunningOnionService};
+use tor_proto::client::stream::IncomingStreamRequest;
+
+use crate::models::tor::TorClientWrapper;
+
+// Nickname of the service in Arti's keystore. Its identity key is kept in
+// the key vault, so that the address is stable per device.
+const SERVICE_NICKNAME: &str = "ash";
+const SERVICE_PORT: u16 = 80;
+const MAPS_PATH_PREFIX: &str = "/maps/";
+const READ_CHUNK_SIZE: usize = 64 * 1024;
+
+type ResponseBody = BoxBody<Bytes, std::io::Error>;
+
+struct RunningService {
+    service: Arc<RunningOnionService>,
+    task: JoinHandle<()>,
+}
+
+#[derive(Default)]
+pub struct OnionServiceHost {
+    running: Mutex<Option<RunningService>>,
+}
+
+impl OnionServiceHost {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Launches the service on the bootstrapped Tor client and returns its
+    /// address, which is the current one if the service is already running.
+    pub fn start(&self, tor_client: &TorClientWrapper, pmtiles_dir: PathBuf) -> Result<String> {
+        if let Some(address) = self.address() {
+            return Ok(address);
+        }
+
+        if !tor_client.is_ready() {
+            return Err(anyhow::anyhow!("Tor client is not enabled"));
+        }
+        let client = tor_client
+            .get_client()
+            .ok_or_else(|| anyhow::anyhow!("Tor client is not initialized"))?;
+
+        let config = service_config(tor_client)?;
+        let identity = tor_client.service_identity()?;
+        let hsid = HsId::from(HsIdKey::from(&identity));
+
+        // Arti's keystore is in memory, so it only has the identity key when
+        // the service already ran on this client, and refuses to replace it.
+        let launched: Result<(_, BoxStream<'static, RendRequest>)> = client
+            .launch_onion_service_with_hsid(config.clone(), identity)
+            .map(|(service, requests)| (service, requests.boxed()))
+            .or_else(|_| {
+                client
+                    .launch_onion_service(config)
+                    .map(|(service, requests)| (service, requests.boxed()))
+            })
+            .map_err(|e: arti_client::Error| anyhow::anyhow!(e));
+        let (service, rend_requests) = launched?;
+        if service.onion_address() != Some(hsid) {
+            return Err(anyhow::anyhow!(
+                "Onion service was launched with another identity"
+            ));
+        }
+
+        let task = tokio::spawn(async move {
+            let mut stream_requests = handle_rend_requests(rend_requests);
+            while let Some(request) = stream_requests.next().await {
+                let accepted = matches!(
+                    request.request(),
+                    IncomingStreamRequest::Begin(begin) if begin.port() == SERVICE_PORT
+                );
+                if !accepted {
+                    if let Err(e) = request.reject(End::new_misc()).await {
+                        eprintln!("Failed to reject onion service stream: {}", e);
+                    }
+                    continue;
+                }
+
+                let pmtiles_dir = pmtiles_dir.clone();
+                tokio::spawn(async move {
+                    let stream = match request.accept(Connected::new_empty()).await {
+                        Ok(stream) => stream,
+                        Err(e) => {
+                            eprintln!("Failed to accept onion service stream: {}", e);
+                            return;
+                        }
+                    };
+
+                    let http_service = service_fn(move |request| {
+                        let pmtiles_dir = pmtiles_dir.clone();
+                        async move { respond(request, &pmtiles_dir).await }
+                    });
+                    if let Err(e) = http1::Builder::new()
+                        .serve_connection(TokioIo::new(stream), http_service)
+                        .await
+                    {
+                        eprintln!("Onion service connection error: {}", e);
+                    }
+                });
+            }
+        });
+
+        let address = onion_address(&service)?;
+        let mut running = self
+            .running
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Onion service lock poisoned"))?;
+        if let Some(previous) = running.replace(RunningService { service, task }) {
+            previous.task.abort();
+        }
+
+        Ok(address)
+    }
+
+    /// Hands the current authorized clients to the running service. Without
+    /// any client left, the service is taken down.
+    pub fn reload_clients(&self, tor_client: &TorClientWrapper) -> Result<()> {
+        let running = self
+            .running
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Onion service lock poisoned"))?;
+        let Some(service) = running.as_ref().map(|r| r.service.clone()) else {
+            return Ok(());
+        };
+        drop(running);
+
+        if tor_client.authorized_clients()?.is_empty() {
+            self.stop();
+            return Ok(());
+        }
+        service.reconfigure(service_config(tor_client)?, Reconfigure::WarnOnFailures)?;
+        Ok(())
+    }
+
+    /// Takes the service down. The identity key is kept in the key vault, so
+    /// the service comes back at the same address.
+    pub fn stop(&self) {
+        if let Some(running) = self.running.lock().ok().and_then(|mut r| r.take()) {
+            running.task.abort();
+        }
+    }
+
+    pub fn address(&self) -> Option<String> {
+        self.running
+            .lock()
+            .ok()
+            .and_then(|running| running.as_ref().map(|r| onion_address(&r.service)))
+            .and_then(Result::ok)
+    }
+}
+
+/// Builds the service configuration with restricted discovery limited to the
+/// authorized clients, refusing to publish a service anyone could reach.
+fn service_config(tor_client: &TorClientWrapper) -> Result<OnionServiceConfig> {
+    let clients = tor_client.authorized_clients()?;
+    if clients.is_empty() {
+        return Err(anyhow::anyhow!(
+            "Authorize at least one client before starting the onion service"
+        ));
+    }
+
+    let mut builder = OnionServiceConfigBuilder::default();
+    builder.nickname(HsNickname::new(SERVICE_NICKNAME.to_string())?);
+    let restricted_discovery = builder.restricted_discovery();
+    restricted_discovery.enabled(true);
+    for (nickname, public_key) in clients {
+        let nickname: HsClientNickname = nickname
+            .parse()
+            .map_err(|e| anyhow::anyhow!("Invalid client nickname {}: {}", nickname, e))?;
+        restricted_discovery
+            .static_keys()
+            .access()
+            .push((nickname, public_key));
+    }
+    Ok(builder.build()?)
+}
+
+fn onion_address(service: &RunningOnionService) -> Result<String> {
+    service
+        .onion_address()
+        .map(|address| address.display_unredacted().to_string())
+        .ok_or_else(|| anyhow::anyhow!("Onion service has no address"))
+}
+
+/// Serves `GET /maps/<locality_id>.pmtiles` from the downloaded maps, whole
+/// or as a single byte range.
+async fn respond<B>(
+    request: Request<B>,
+    pmtiles_dir: &Path,
+) -> Result<Response<ResponseBody>, hyper::http::Error> {
+    let file_name = match (request.method(), map_file_name(request.uri().path())) {
+        (&hyper::Method::GET, Some(file_name)) => file_name,
+        (&hyper::Method::GET, None) => return empty_response(StatusCode::NOT_FOUND),
+        _ => return empty_response(StatusCode::METHOD_NOT_ALLOWED),
+    };
+
+    let mut file = match tokio::fs::File::open(pmtiles_dir.join(file_name)).await {
+        Ok(file) => file,
+        Err(_) => return empty_response(StatusCode::NOT_FOUND),
+    };
+    let metadata = match file.metadata().await {
+        Ok(metadata) => metadata,
+        Err(_) => return empty_response(StatusCode::INTERNAL_SERVER_ERROR),
+    };
+    let length = metadata.len();
+    let etag = entity_tag(&metadata);
+
+    // A range is only served from the file the client started with.
+    let range = match (header(&request, "range"), header(&request, "if-range")) {
+        (Some(_), Some(validator)) if validator != etag => None,
+        (Some(range), _) => parse_range(range, length),
+        (None, _) => None,
+    };
+
+    let mut response = Response::builder()
+        .header(hyper::header::CONTENT_TYPE, "application/vnd.pmtiles")
+        .header(hyper::header::ACCEPT_RANGES, "bytes")
+        .header(hyper::header::ETAG, &etag);
+    let (start, end) = match range {
+        None => (0, length),
+        Some(ByteRange::Unsatisfiable) => {
+            return Response::builder()
+                .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                .header(hyper::header::CONTENT_RANGE, format!("bytes */{}", length))
+                .body(empty_body());
+        }
+        Some(ByteRange::Satisfiable { start, end }) => {
+            if file.seek(std::io::SeekFrom::Start(start)).await.is_err() {
+                return empty_response(StatusCode::INTERNAL_SERVER_ERROR);
+            }
+            response = response.status(StatusCode::PARTIAL_CONTENT).header(
+                hyper::header::CONTENT_RANGE,
+                format!("bytes {}-{}/{}", start, end - 1, length),
+            );
+            (start, end)
+        }
+    };
+
+    let chunks = futures::stream::try_unfold(file.take(end - start), |mut file| async move {
+        let mut buffer = vec![0; READ_CHUNK_SIZE];
+        let read = file.read(&mut buffer).await?;
+        if read == 0 {
+            return Ok(None);
+        }
+        buffer.truncate(read);
+        Ok(Some((Frame::data(Bytes::from(buffer)), file)))
+    });
+
+    response
+        .header(hyper::header::CONTENT_LENGTH, end - start)
+        .body(BodyExt::boxed(StreamBody::new(chunks)))
+}
+
+/// Name of the map file a path asks for, refusing anything but a locality
+/// id, so that no other file of the directory can be reached.
+fn map_file_name(path: &str) -> Option<&str> {
+    path.strip_prefix(MAPS_PATH_PREFIX).filter(|name| {
+        name.strip_suffix(".pmtiles").is_some_and(|locality_id| {
+            !locality_id.is_empty()
+                && locality_id
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        })
+    })
+}
+
+fn header<'a, B>(request: &'a Request<B>, name: &str) -> Option<&'a str> {
+    request
+        .headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(str::trim)
+}
+
+/// Strong validator of a map, changing whenever the file is replaced.
+fn entity_tag(metadata: &std::fs::Metadata) -> String {
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|modified| modified.duration_since(std::time::UNIX_EPOCH).ok())
+        .unwrap_or_default();
+    format!("\"{:x}-{:x}\"", metadata.len(), modified.as_nanos())
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum ByteRange {
+    /// Bytes from `start` included to `end` excluded.
+    Satisfiable {
+        start: u64,
+        end: u64,
+    },
+    Unsatisfiable,
+}
+
+/// Parses a `Range` header of a single byte range. Other units and several
+/// ranges are ignored, and get the whole file.
+fn parse_range(value: &str, length: u64) -> Option<ByteRange> {
+    let (first, last) = value.strip_prefix("bytes=")?.trim().split_once('-')?;
+    let (first, last) = (first.trim(), last.trim());
+    if last.contains(',') {
+        return None;
+    }
+
+    let (start, end) = match (first.is_empty(), last.is_empty()) {
+        // Suffix range: the last `last` bytes.
+        (true, false) => {
+            let suffix: u64 = last.parse().ok()?;
+            if suffix == 0 {
+                return Some(ByteRange::Unsatisfiable);
+            }
+            (length.saturating_sub(suffix), length)
+        }
+        (false, true) => (first.parse().ok()?, length),
+        (false, false) => {
+            let start: u64 = first.parse().ok()?;
+            let last: u64 = last.parse().ok()?;
+            if last < start {
+                return None;
+            }
+            (start, last.saturating_add(1).min(length))
+        }
+        (true, true) => return None,
+    };
+
+    if start >= length {
+        return Some(ByteRange::Unsatisfiable);
+    }
+    Some(ByteRange::Satisfiable { start, end })
+}
+
+fn empty_response(status: StatusCode) -> Result<Response<ResponseBody>, hyper::http::Error> {
+    Response::builder().status(status).body(empty_body())
+}
+
+fn empty_body() -> ResponseBody {
+    Empty::<Bytes>::new()
+        .map_err(|never| match never {})
+        .boxed()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(method: &str, path: &str, headers: &[(&str, &str)]) -> Request<()> {
+        let mut request = Request::builder().method(method).uri(path);
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        request.body(()).unwrap()
+    }
+
+    async fn body(response: Response<ResponseBody>) -> Vec<u8> {
+        response
+            .into_body()
+            .collect()
+            .await
+            .unwrap()
+            .to_bytes()
+            .to_vec()
+    }
+
+    fn maps_dir() -> tempfile::TempDir {
+        let directory = tempfile::tempdir().unwrap();
+        std::fs::write(directory.path().join("paris.pmtiles"), b"0123456789").unwrap();
+        std::fs::write(directory.path().join("lyon.pmtiles.part"), b"partial").unwrap();
+        directory
+    }
+
+    #[test]
+    fn only_accepts_locality_map_names() {
+        assert_eq!(
+            map_file_name("/maps/paris-75_1.pmtiles"),
+            Some("paris-75_1.pmtiles")
+        );
+        assert_eq!(map_file_name("/maps/../secret.pmtiles"), None);
+        assert_eq!(map_file_name("/maps/..%2Fsecret.pmtiles"), None);
+        assert_eq!(map_file_name("/maps/sub/paris.pmtiles"), None);
+        assert_eq!(map_file_name("/maps/lyon.pmtiles.part"), None);
+        assert_eq!(map_file_name("/maps/.pmtiles"), None);
+        assert_eq!(map_file_name("/maps/paris"), None);
+        assert_eq!(map_file_name("/paris.pmtiles"), None);
+    }
+
+    #[test]
+    fn parses_single_byte_ranges() {
+        let range = |start, end| Some(ByteRange::Satisfiable { start, end });
+        assert_eq!(parse_range("bytes=2-5", 10), range(2, 6));
+        assert_eq!(parse_range("bytes=2-", 10), range(2, 10));
+        assert_eq!(parse_range("bytes=-3", 10), range(7, 10));
+        assert_eq!(parse_range("bytes=-30", 10), range(0, 10));
+        assert_eq!(parse_range("bytes=8-20", 10), range(8, 10));
+        assert_eq!(parse_range("bytes=10-", 10), Some(ByteRange::Unsatisfiable));
+        assert_eq!(parse_range("bytes=-0", 10), Some(ByteRange::Unsatisfiable));
+        assert_eq!(parse_range("bytes=5-2", 10), None);
+        assert_eq!(parse_range("bytes=0-1,4-5", 10), None);
+        assert_eq!(parse_range("items=0-1", 10), None);
+        assert_eq!(parse_range("bytes=a-b", 10), None);
+    }
+
+    #[tokio::test]
+    async fn refuses_other_methods_and_paths() {
+        let directory = maps_dir();
+        let response = respond(
+            request("POST", "/maps/paris.pmtiles", &[]),
+            directory.path(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+        let response = respond(
+            request("HEAD", "/maps/paris.pmtiles", &[]),
+            directory.path(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+
+        for path in [
+            "/maps/lyon.pmtiles.part",
+            "/maps/.pmtiles",
+            "/maps/marseille.pmtiles",
+        ] {
+            let response = respond(request("GET", path, &[]), directory.path())
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::NOT_FOUND, "{}", path);
+        }
+    }
+
+    #[tokio::test]
+    async fn serves_whole_maps_and_byte_ranges() {
+        let directory = maps_dir();
+        let path = "/maps/paris.pmtiles";
+
+        let response = respond(request("GET", path, &[]), directory.path())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["accept-ranges"], "bytes");
+        let etag = response.headers()["etag"].to_str().unwrap().to_string();
+        assert_eq!(body(response).await, b"0123456789");
+
+        let response = respond(
+            request("GET", path, &[("range", "bytes=2-5")]),
+            directory.path(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(response.headers()["content-range"], "bytes 2-5/10");
+        assert_eq!(response.headers()["content-length"], "4");
+        assert_eq!(body(response).await, b"2345");
+
+        let headers = [("range", "bytes=-3"), ("if-range", etag.as_str())];
+        let response = respond(request("GET", path, &headers), directory.path())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(body(response).await, b"789");
+
+        let headers = [("range", "bytes=-3"), ("if-range", "\"stale\"")];
+        let response = respond(request("GET", path, &headers), directory.path())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(body(response).await, b"0123456789");
+
+        let response = respond(
+            request("GET", path, &[("range", "bytes=10-")]),
+            directory.path(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(response.headers()["content-range"], "bytes */10");
+    }
+}
@@ -0,0 +1,354 @@
+use anyhow::Result;
+use pmtiles::{AsyncPmTilesReader, TileCoord, TileType};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::f64::consts::TAU;
+use std::path::Path;
+use std::sync::RwLock;
+use std::time::Duration;
+use tauri::AppHandle;
+
+use crate::models::geo::{Coordinate, LocalProjection, EARTH_RADIUS_METERS};
+use crate::models::group::Marker;
+use crate::models::location::LocationFix;
+use crate::models::road_graph::ROAD_LAYERS;
+use crate::models::settings::{load_setting, save_setting};
+use crate::models::vector_tile::{decode_layers, GeometryType, TileAddress};
+
+const SETTINGS_KEY: &str = "location_privacy";
+// Privacy level `l` of geo-indistinguishability, epsilon = l / radius.
+const PRIVACY_LEVEL: f64 = 2.0 * std::f64::consts::LN_2;
+const SNAP_MAX_ZOOM: u8 = 15;
+const SNAP_MAX_DISTANCE_METERS: f64 = 500.0;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum PrivacyMode {
+    #[default]
+    Exact,
+    /// Center of the fixed grid cell containing the position.
+    Grid { cell_size_meters: f64 },
+    /// Planar Laplace noise, `radius_meters` being the protected radius.
+    Noise { radius_meters: f64 },
+    /// Nearest road intersection from the downloaded maps.
+    Snap,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LocationPrivacySettings {
+    pub mode: PrivacyMode,
+    #[serde(default)]
+    pub delay_minutes: u32,
+}
+
+impl LocationPrivacySettings {
+    /// How long positions and markers are held back before being shared.
+    pub fn delay(&self) -> Duration {
+        Duration::from_secs(u64::from(self.delay_minutes) * 60)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Publication {
+    pub group_id: String,
+    pub marker: Marker,
+    /// Milliseconds since the Unix epoch.
+    pub publish_at: u64,
+}
+
+/// Privacy filter applied to every position or marker before it is handed
+/// to the messaging layer.
+pub struct LocationPrivacy {
+    app_handle: AppHandle,
+    settings: RwLock<LocationPrivacySettings>,
+}
+
+impl LocationPrivacy {
+    pub fn new(app_handle: AppHandle) -> Self {
+        let settings = load_setting(&app_handle, SETTINGS_KEY)
+            .unwrap_or_else(|e| {
+                eprintln!("Failed to load location privacy settings: {}", e);
+                None
+            })
+            .unwrap_or_default();
+
+        Self {
+            app_handle,
+            settings: RwLock::new(settings),
+        }
+    }
+
+    pub fn settings(&self) -> LocationPrivacySettings {
+        self.settings
+            .read()
+            .map(|settings| settings.clone())
+            .unwrap_or_default()
+    }
+
+    pub fn set_settings(&self, settings: LocationPrivacySettings) -> Result<()> {
+        match &settings.mode {
+            PrivacyMode::Grid { cell_size_meters } if *cell_size_meters <= 0.0 => {
+                return Err(anyhow::anyhow!("Grid cell size must be positive"));
+            }
+            PrivacyMode::Noise { radius_meters } if *radius_meters <= 0.0 => {
+                return Err(anyhow::anyhow!("Noise radius must be positive"));
+            }
+            _ => {}
+        }
+
+        save_setting(&self.app_handle, SETTINGS_KEY, &settings)?;
+        let mut current = self
+            .settings
+            .write()
+            .map_err(|_| anyhow::anyhow!("Location privacy lock poisoned"))?;
+        *current = settings;
+        Ok(())
+    }
+
+    /// Applies the configured mode to a coordinate. Snapping falls back to a
+    /// grid of the snapping distance when no intersection is close enough.
+    pub async fn apply(&self, coordinate: Coordinate, pmtiles_dir: &Path) -> Result<Coordinate> {
+        Ok(match self.settings().mode {
+            PrivacyMode::Exact => coordinate,
+            PrivacyMode::Grid { cell_size_meters } => {
+                coarsen_to_grid(&coordinate, cell_size_meters)
+            }
+            PrivacyMode::Noise { radius_meters } => {
+                add_planar_noise(&coordinate, radius_meters, &mut rand::rng())
+            }
+            PrivacyMode::Snap => snap_to_intersection(&coordinate, pmtiles_dir)
+                .await?
+                .unwrap_or_else(|| coarsen_to_grid(&coordinate, SNAP_MAX_DISTANCE_METERS)),
+        })
+    }
+
+    /// Applies the configured mode to a position fix, dropping the altitude
+    /// and motion that would give away more than the blurred position.
+    pub async fn apply_to_fix(
+        &self,
+        mut fix: LocationFix,
+        pmtiles_dir: &Path,
+    ) -> Result<LocationFix> {
+        if matches!(self.settings().mode, PrivacyMode::Exact) {
+            return Ok(fix);
+        }
+
+        let coordinate = self.apply(fix.coordinate(), pmtiles_dir).await?;
+        fix.latitude = coordinate.latitude;
+        fix.longitude = coordinate.longitude;
+        fix.accuracy = None;
+        fix.altitude = None;
+        fix.speed = None;
+        fix.heading = None;
+        Ok(fix)
+    }
+}
+
+/// Returns the center of the grid cell containing the coordinate. The grid
+/// is anchored on the equator and the prime meridian so that repeated
+/// reports from the same cell always give the same answer.
+pub fn coarsen_to_grid(coordinate: &Coordinate, cell_size_meters: f64) -> Coordinate {
+    let meters_per_degree = EARTH_RADIUS_METERS.to_radians();
+
+    let latitude_step = cell_size_meters / meters_per_degree;
+    let latitude = ((coordinate.latitude / latitude_step).floor() + 0.5) * latitude_step;
+
+    let longitude_step =
+        cell_size_meters / (meters_per_degree * latitude.to_radians().cos()).max(1.0);
+    let longitude = ((coordinate.longitude / longitude_step).floor() + 0.5) * longitude_step;
+
+    Coordinate::new(latitude.clamp(-90.0, 90.0), longitude.clamp(-180.0, 180.0))
+}
+
+/// Moves the coordinate by planar Laplace noise (geo-indistinguishability).
+pub fn add_planar_noise<R: Rng + ?Sized>(
+    coordinate: &Coordinate,
+    radius_meters: f64,
+    rng: &mut R,
+) -> Coordinate {
+    let epsilon = PRIVACY_LEVEL / radius_meters;
+    let angle = rng.random::<f64>() * TAU;
+    // The distance of the planar Laplace distribution follows Gamma(2, 1/epsilon).
+    let distance = -((1.0 - rng.random::<f64>()).ln() + (1.0 - rng.random::<f64>()).ln()) / epsilon;
+
+    LocalProjection::new(*coordinate).unproject(distance * angle.cos(), distance * angle.sin())
+}
+
+/// Finds the closest road intersection in the downloaded maps covering the
+/// coordinate.
+pub async fn snap_to_intersection(
+    coordinate: &Coordinate,
+    pmtiles_dir: &Path,
+) -> Result<Option<Coordinate>> {
+    let Ok(entries) = std::fs::read_dir(pmtiles_dir) else {
+        return Ok(None);
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some("pmtiles") {
+            continue;
+        }
+
+        let reader = AsyncPmTilesReader::new_with_path(&path).await?;
+        let header = reader.get_header();
+        let covers = header.tile_type == TileType::Mvt
+            && (f64::from(header.min_longitude)..=f64::from(header.max_longitude))
+                .contains(&coordinate.longitude)
+            && (f64::from(header.min_latitude)..=f64::from(header.max_latitude))
+                .contains(&coordinate.latitude);
+        if !covers {
+            continue;
+        }
+
+        let center = TileAddress::containing(coordinate, header.max_zoom.min(SNAP_MAX_ZOOM));
+        let mut intersections = Vec::new();
+        for (dx, dy) in (-1i64..=1).flat_map(|dx| (-1i64..=1).map(move |dy| (dx, dy))) {
+            let (Ok(x), Ok(y)) = (
+                u32::try_from(i64::from(center.x) + dx),
+                u32::try_from(i64::from(center.y) + dy),
+            ) else {
+                continue;
+            };
+            let address = TileAddress { x, y, ..center };
+            let Ok(tile_coord) = TileCoord::new(address.z, address.x, address.y) else {
+                continue;
+            };
+            if let Some(data) = reader.get_tile_decompressed(tile_coord).await? {
+                intersections.extend(road_intersections(&data, &address)?);
+            }
+        }
+
+        let nearest = intersections
+            .into_iter()
+            .map(|candidate| (coordinate.distance_to(&candidate), candidate))
+            .filter(|(distance, _)| *distance <= SNAP_MAX_DISTANCE_METERS)
+            .min_by(|a, b| a.0.total_cmp(&b.0));
+
+        if let Some((_, intersection)) = nearest {
+            return Ok(Some(intersection));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Vertices shared by at least two road features of a tile.
+fn road_intersections(data: &[u8], address: &TileAddress) -> Result<Vec<Coordinate>> {
+    let mut intersections = Vec::new();
+
+    for layer in decode_layers(data)? {
+        if !ROAD_LAYERS.contains(&layer.name.as_str()) {
+            continue;
+        }
+
+        let mut vertices: HashMap<(i32, i32), usize> = HashMap::new();
+        for feature in &layer.features {
+            if feature.geometry_type != GeometryType::LineString {
+                continue;
+            }
+            let mut feature_vertices: Vec<(i32, i32)> =
+                feature.paths.iter().flatten().copied().collect();
+            feature_vertices.sort_unstable();
+            feature_vertices.dedup();
+            for vertex in feature_vertices {
+                *vertices.entry(vertex).or_default() += 1;
+            }
+        }
+
+        let extent = layer.extent as i32;
+        intersections.extend(
+            vertices
+                .into_iter()
+                .filter(|((x, y), count)| {
+                    *count >= 2 && (0..=extent).contains(x) && (0..=extent).contains(y)
+                })
+                .map(|(vertex, _)| address.coordinate_at(vertex, layer.extent)),
+        );
+    }
+
+    Ok(intersections)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::vector_tile::test_tiles;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn coarsens_to_stable_cell_centers() {
+        let cell_size = 250.0;
+        let position = Coordinate::new(48.8566, 2.3522);
+        let center = coarsen_to_grid(&position, cell_size);
+
+        assert!(position.distance_to(&center) <= cell_size);
+        assert_eq!(coarsen_to_grid(&center, cell_size), center);
+        // A few meters away, still in the same cell.
+        let nearby = LocalProjection::new(center).unproject(10.0, -10.0);
+        assert_eq!(coarsen_to_grid(&nearby, cell_size), center);
+        // Across the cell, in the next one.
+        let next = LocalProjection::new(center).unproject(cell_size, 0.0);
+        assert_ne!(coarsen_to_grid(&next, cell_size), center);
+    }
+
+    #[test]
+    fn planar_noise_follows_the_protected_radius() {
+        let radius = 200.0;
+        let position = Coordinate::new(-33.8688, 151.2093);
+        let mut rng = StdRng::seed_from_u64(7);
+
+        let distances: Vec<f64> = (0..2_000)
+            .map(|_| position.distance_to(&add_planar_noise(&position, radius, &mut rng)))
+            .collect();
+        let mean = distances.iter().sum::<f64>() / distances.len() as f64;
+
+        // The mean of Gamma(2, 1/epsilon) is 2 / epsilon.
+        let expected = 2.0 * radius / PRIVACY_LEVEL;
+        assert!((mean - expected).abs() < expected * 0.1, "mean {}", mean);
+        assert!(distances.iter().all(|distance| *distance < radius * 20.0));
+        assert!(distances.iter().filter(|d| **d < 1.0).count() < 10);
+    }
+
+    #[test]
+    fn finds_intersections_of_road_features() {
+        let address = TileAddress::containing(&Coordinate::new(48.8566, 2.3522), 15);
+        let tile = test_tiles::line_layer(
+            "roads",
+            4096,
+            &[
+                vec![(0, 2048), (1024, 2048), (4096, 2048)],
+                vec![(1024, 0), (1024, 2048), (1024, 4096)],
+                // Shares a vertex with itself only.
+                vec![(3000, 100), (3000, 200), (3000, 100)],
+            ],
+        );
+
+        let intersections = road_intersections(&tile, &address).unwrap();
+        assert_eq!(
+            intersections,
+            vec![address.coordinate_at((1024, 2048), 4096)]
+        );
+
+        let buildings = test_tiles::line_layer(
+            "buildings",
+            4096,
+            &[vec![(0, 0), (10, 10)], vec![(10, 10), (20, 0)]],
+        );
+        assert!(road_intersections(&buildings, &address).unwrap().is_empty());
+    }
+
+    #[test]
+    fn delays_by_whole_minutes() {
+        let settings = LocationPrivacySettings {
+            mode: PrivacyMode::Exact,
+            delay_minutes: 15,
+        };
+        assert_eq!(settings.delay(), Duration::from_secs(900));
+        assert!(LocationPrivacySettings::default().delay().is_zero());
+    }
+}
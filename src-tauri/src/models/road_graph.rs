@@ -0,0 +1,234 @@
+//! Road network read from the road layers of the downloaded vector tiles,
+//! so that routes follow streets instead of cutting across blocks.
+
+use anyhow::Result;
+use pmtiles::{AsyncPmTilesReader, TileCoord, TileType};
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::models::geo::Coordinate;
+use crate::models::vector_tile::{decode_layers, GeometryType, TileAddress};
+
+/// Road layers of the Protomaps and OpenMapTiles schemas.
+pub const ROAD_LAYERS: [&str; 2] = ["roads", "transportation"];
+const ROUTING_MAX_ZOOM: u8 = 14;
+// Lower zooms drop minor roads, but keep large areas affordable.
+const MAX_TILES: u64 = 64;
+// Vertices closer than about a centimeter are the same node, which joins
+// roads split at tile boundaries.
+const NODE_PRECISION: f64 = 1e7;
+
+#[derive(Debug, Default)]
+pub struct RoadGraph {
+    nodes: Vec<Coordinate>,
+    /// Neighbours of each node with the length of the road between them.
+    edges: Vec<Vec<(usize, f64)>>,
+    index: HashMap<(i64, i64), usize>,
+}
+
+impl RoadGraph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn node(&self, index: usize) -> Coordinate {
+        self.nodes[index]
+    }
+
+    pub fn neighbors(&self, index: usize) -> &[(usize, f64)] {
+        &self.edges[index]
+    }
+
+    /// Adds a road following the points, connected to the roads sharing
+    /// its vertices.
+    pub fn add_road(&mut self, points: &[Coordinate]) {
+        let mut previous: Option<usize> = None;
+        for point in points {
+            let node = self.node_at(point);
+            if let Some(previous) = previous.filter(|previous| *previous != node) {
+                let length = self.nodes[previous].distance_to(&self.nodes[node]);
+                self.connect(previous, node, length);
+                self.connect(node, previous, length);
+            }
+            previous = Some(node);
+        }
+    }
+
+    /// Adds the roads of a vector tile.
+    pub fn add_tile(&mut self, data: &[u8], address: &TileAddress) -> Result<()> {
+        for layer in decode_layers(data)? {
+            if !ROAD_LAYERS.contains(&layer.name.as_str()) {
+                continue;
+            }
+            for feature in &layer.features {
+                if feature.geometry_type != GeometryType::LineString {
+                    continue;
+                }
+                for path in &feature.paths {
+                    let points: Vec<Coordinate> = path
+                        .iter()
+                        .map(|point| address.coordinate_at(*point, layer.extent))
+                        .collect();
+                    self.add_road(&points);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the node closest to the coordinate and its distance.
+    pub fn nearest_node(&self, coordinate: &Coordinate) -> Option<(usize, f64)> {
+        self.nodes
+            .iter()
+            .enumerate()
+            .map(|(index, node)| (index, coordinate.distance_to(node)))
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+    }
+
+    /// Reads the roads of the downloaded maps within the bounds, at the
+    /// most detailed zoom keeping the number of tiles reasonable.
+    pub async fn load(
+        pmtiles_dir: &Path,
+        south_west: &Coordinate,
+        north_east: &Coordinate,
+    ) -> Result<Self> {
+        let mut graph = Self::new();
+        let Ok(entries) = std::fs::read_dir(pmtiles_dir) else {
+            return Ok(graph);
+        };
+
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("pmtiles") {
+                continue;
+            }
+
+            let reader = AsyncPmTilesReader::new_with_path(&path).await?;
+            let header = reader.get_header();
+            let overlaps = header.tile_type == TileType::Mvt
+                && f64::from(header.min_longitude) <= north_east.longitude
+                && f64::from(header.max_longitude) >= south_west.longitude
+                && f64::from(header.min_latitude) <= north_east.latitude
+                && f64::from(header.max_latitude) >= south_west.latitude;
+            if !overlaps {
+                continue;
+            }
+
+            let mut zoom = header.max_zoom.min(ROUTING_MAX_ZOOM);
+            let (mut min, mut max) = tile_range(south_west, north_east, zoom);
+            while zoom > header.min_zoom
+                && u64::from(max.x - min.x + 1) * u64::from(max.y - min.y + 1) > MAX_TILES
+            {
+                zoom -= 1;
+                (min, max) = tile_range(south_west, north_east, zoom);
+            }
+
+            for x in min.x..=max.x {
+                for y in min.y..=max.y {
+                    let address = TileAddress { z: zoom, x, y };
+                    let Ok(tile_coord) = TileCoord::new(zoom, x, y) else {
+                        continue;
+                    };
+                    if let Some(data) = reader.get_tile_decompressed(tile_coord).await? {
+                        graph.add_tile(&data, &address)?;
+                    }
+                }
+            }
+        }
+
+        Ok(graph)
+    }
+
+    fn node_at(&mut self, coordinate: &Coordinate) -> usize {
+        let key = (
+            (coordinate.latitude * NODE_PRECISION).round() as i64,
+            (coordinate.longitude * NODE_PRECISION).round() as i64,
+        );
+        *self.index.entry(key).or_insert_with(|| {
+            self.nodes.push(*coordinate);
+            self.edges.push(Vec::new());
+            self.nodes.len() - 1
+        })
+    }
+
+    fn connect(&mut self, from: usize, to: usize, length: f64) {
+        if !self.edges[from].iter().any(|(node, _)| *node == to) {
+            self.edges[from].push((to, length));
+        }
+    }
+}
+
+/// Top left and bottom right tiles covering the bounds.
+fn tile_range(
+    south_west: &Coordinate,
+    north_east: &Coordinate,
+    zoom: u8,
+) -> (TileAddress, TileAddress) {
+    let north_west = Coordinate::new(north_east.latitude, south_west.longitude);
+    let south_east = Coordinate::new(south_west.latitude, north_east.longitude);
+    (
+        TileAddress::containing(&north_west, zoom),
+        TileAddress::containing(&south_east, zoom),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::vector_tile::test_tiles;
+
+    #[test]
+    fn joins_roads_sharing_vertices() {
+        let (a, b, c) = (
+            Coordinate::new(48.0, 2.0),
+            Coordinate::new(48.001, 2.0),
+            Coordinate::new(48.001, 2.001),
+        );
+        let mut graph = RoadGraph::new();
+        graph.add_road(&[a, b]);
+        graph.add_road(&[b, c]);
+
+        let (b_node, _) = graph.nearest_node(&b).unwrap();
+        assert_eq!(graph.neighbors(b_node).len(), 2);
+        let (_, length) = graph.neighbors(b_node)[0];
+        assert!((length - a.distance_to(&b)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn joins_roads_across_tile_boundaries() {
+        let west = TileAddress::containing(&Coordinate::new(48.8566, 2.3522), 14);
+        let east = TileAddress {
+            x: west.x + 1,
+            ..west
+        };
+        let mut graph = RoadGraph::new();
+        graph
+            .add_tile(
+                &test_tiles::line_layer("roads", 4096, &[vec![(2048, 100), (4096, 100)]]),
+                &west,
+            )
+            .unwrap();
+        graph
+            .add_tile(
+                &test_tiles::line_layer("roads", 4096, &[vec![(0, 100), (2048, 100)]]),
+                &east,
+            )
+            .unwrap();
+        graph
+            .add_tile(
+                &test_tiles::line_layer("buildings", 4096, &[vec![(0, 0), (10, 10)]]),
+                &east,
+            )
+            .unwrap();
+
+        let boundary = east.coordinate_at((0, 100), 4096);
+        let (node, distance) = graph.nearest_node(&boundary).unwrap();
+        assert!(distance < 0.01);
+        assert_eq!(graph.neighbors(node).len(), 2);
+        assert_eq!(graph.nodes.len(), 3);
+    }
+}
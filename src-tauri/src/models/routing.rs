@@ -0,0 +1,713 @@
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+
+use crate::models::geo::{point_in_ring, Area, Coordinate, LocalProjection};
+use crate::models::group::{Marker, MarkerKind};
+use crate::models::road_graph::RoadGraph;
+
+const DEFAULT_HAZARD_RADIUS_METERS: f64 = 100.0;
+const THREAT_PENALTY: f64 = 10.0;
+// Applied instead of blocking when the start or destination already lies in
+// an impassable zone, so the route leads out of it by the shortest way.
+const ESCAPE_PENALTY: f64 = 50.0;
+const MIN_SEARCH_MARGIN_METERS: f64 = 200.0;
+const MIN_CELL_SIZE_METERS: f64 = 5.0;
+const MAX_GRID_SIDE: usize = 250;
+// Start and destination farther than this from any road are routed over
+// open ground.
+const MAX_ROAD_ACCESS_METERS: f64 = 300.0;
+// Step at which roads are checked against the avoidance zones.
+const ZONE_SAMPLE_METERS: f64 = 10.0;
+// Rounding slack when telling outdated entries of the open set, whose
+// estimate was computed from a higher cost.
+const STALE_TOLERANCE: f64 = 1e-6;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AvoidanceZone {
+    pub area: Area,
+    /// Cost multiplier added per meter travelled inside the zone. `None`
+    /// makes the zone impassable.
+    #[serde(default)]
+    pub penalty: Option<f64>,
+}
+
+impl AvoidanceZone {
+    /// Builds a zone from a marker tagged as a hazard.
+    pub fn from_marker(marker: &Marker) -> Option<Self> {
+        if !marker.kind.is_hazard() {
+            return None;
+        }
+
+        let penalty = match (marker.penalty, marker.kind) {
+            (Some(penalty), _) => Some(penalty.max(0.0)),
+            (None, MarkerKind::Threat) => Some(THREAT_PENALTY),
+            (None, _) => None,
+        };
+
+        Some(Self {
+            area: marker.area(DEFAULT_HAZARD_RADIUS_METERS),
+            penalty,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase",
+    tag = "status",
+    content = "data"
+)]
+pub enum RouteResult {
+    Found {
+        path: Vec<Coordinate>,
+        distance_meters: f64,
+        /// Part of the distance travelled inside penalized zones.
+        penalized_meters: f64,
+        /// Whether the path follows the roads of the downloaded maps, rather
+        /// than crossing open ground where no map covers the area.
+        follows_roads: bool,
+    },
+    NoSafeRoute {},
+}
+
+enum ProjectedArea {
+    Circle { x: f64, y: f64, radius: f64 },
+    Polygon(Vec<(f64, f64)>),
+}
+
+impl ProjectedArea {
+    fn new(area: &Area, projection: &LocalProjection) -> Self {
+        match area {
+            Area::Circle {
+                center,
+                radius_meters,
+            } => {
+                let (x, y) = projection.project(center);
+                Self::Circle {
+                    x,
+                    y,
+                    radius: *radius_meters,
+                }
+            }
+            Area::Polygon { points } => {
+                Self::Polygon(points.iter().map(|p| projection.project(p)).collect())
+            }
+        }
+    }
+
+    fn contains(&self, point: (f64, f64)) -> bool {
+        match self {
+            Self::Circle { x, y, radius } => {
+                (point.0 - x).powi(2) + (point.1 - y).powi(2) <= radius.powi(2)
+            }
+            Self::Polygon(ring) => ring.len() >= 3 && point_in_ring(point, ring),
+        }
+    }
+}
+
+#[derive(PartialEq)]
+struct OpenNode {
+    estimate: f64,
+    index: usize,
+}
+
+impl Eq for OpenNode {}
+
+impl Ord for OpenNode {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .estimate
+            .partial_cmp(&self.estimate)
+            .unwrap_or(Ordering::Equal)
+    }
+}
+
+impl PartialOrd for OpenNode {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Zones projected around the route, with the escape penalty applied to
+/// impassable zones containing the start or destination.
+struct ProjectedZones(Vec<(ProjectedArea, Option<f64>)>);
+
+impl ProjectedZones {
+    fn new(
+        zones: &[AvoidanceZone],
+        projection: &LocalProjection,
+        endpoints: [(f64, f64); 2],
+    ) -> Self {
+        Self(
+            zones
+                .iter()
+                .map(|zone| {
+                    let area = ProjectedArea::new(&zone.area, projection);
+                    let penalty = match zone.penalty {
+                        None if endpoints.iter().any(|point| area.contains(*point)) => {
+                            Some(ESCAPE_PENALTY)
+                        }
+                        penalty => penalty,
+                    };
+                    (area, penalty)
+                })
+                .collect(),
+        )
+    }
+
+    /// Cost multiplier at a point, `None` when it is impassable.
+    fn weight(&self, point: (f64, f64)) -> Option<f64> {
+        self.0
+            .iter()
+            .filter(|(area, _)| area.contains(point))
+            .try_fold(1.0, |weight, (_, penalty)| penalty.map(|p| weight + p))
+    }
+
+    fn penalized(&self, point: (f64, f64)) -> bool {
+        self.0.iter().any(|(area, _)| area.contains(point))
+    }
+
+    /// Cost and penalized length of a straight segment, sampled along its
+    /// length. `None` when it crosses an impassable zone.
+    fn segment(&self, a: (f64, f64), b: (f64, f64)) -> Option<(f64, f64)> {
+        let length = ((b.0 - a.0).powi(2) + (b.1 - a.1).powi(2)).sqrt();
+        let pieces = (length / ZONE_SAMPLE_METERS).ceil().max(1.0);
+        let piece = length / pieces;
+
+        let mut cost = 0.0;
+        let mut penalized = 0.0;
+        for step in 0..pieces as usize {
+            let t = (step as f64 + 0.5) / pieces;
+            let point = (a.0 + (b.0 - a.0) * t, a.1 + (b.1 - a.1) * t);
+            cost += piece * self.weight(point)?;
+            if self.penalized(point) {
+                penalized += piece;
+            }
+        }
+        Some((cost, penalized))
+    }
+}
+
+/// A* router steering around avoidance zones. Routes follow the roads of
+/// the downloaded maps, and cross open ground on a grid only where no map
+/// covers the start or destination.
+pub struct Router {
+    zones: Vec<AvoidanceZone>,
+    roads: Option<RoadGraph>,
+}
+
+impl Router {
+    pub fn new(zones: Vec<AvoidanceZone>) -> Self {
+        Self { zones, roads: None }
+    }
+
+    pub fn with_roads(mut self, roads: RoadGraph) -> Self {
+        self.roads = Some(roads).filter(|roads| !roads.is_empty());
+        self
+    }
+
+    pub fn compute_route(&self, start: Coordinate, end: Coordinate) -> RouteResult {
+        let projection = LocalProjection::new(Coordinate::new(
+            (start.latitude + end.latitude) / 2.0,
+            (start.longitude + end.longitude) / 2.0,
+        ));
+        let start_xy = projection.project(&start);
+        let end_xy = projection.project(&end);
+        let zones = ProjectedZones::new(&self.zones, &projection, [start_xy, end_xy]);
+
+        if let Some(roads) = &self.roads {
+            if let Some(result) = Self::route_on_roads(roads, &zones, &projection, start, end) {
+                return result;
+            }
+        }
+        self.route_over_ground(&zones, &projection, start, end)
+    }
+
+    /// Routes along the roads, `None` when the start or destination is too
+    /// far from them.
+    fn route_on_roads(
+        roads: &RoadGraph,
+        zones: &ProjectedZones,
+        projection: &LocalProjection,
+        start: Coordinate,
+        end: Coordinate,
+    ) -> Option<RouteResult> {
+        let (start_node, start_gap) = roads.nearest_node(&start)?;
+        let (end_node, end_gap) = roads.nearest_node(&end)?;
+        if start_gap > MAX_ROAD_ACCESS_METERS || end_gap > MAX_ROAD_ACCESS_METERS {
+            return None;
+        }
+
+        let start_xy = projection.project(&start);
+        let end_xy = projection.project(&end);
+        let node_xy = |node: usize| projection.project(&roads.node(node));
+        let end_node_xy = node_xy(end_node);
+        let heuristic = |node: usize| {
+            let (x, y) = node_xy(node);
+            ((x - end_node_xy.0).powi(2) + (y - end_node_xy.1).powi(2)).sqrt()
+        };
+
+        let mut costs: HashMap<usize, f64> = HashMap::new();
+        let mut previous: HashMap<usize, usize> = HashMap::new();
+        let mut open = BinaryHeap::new();
+        costs.insert(start_node, 0.0);
+        open.push(OpenNode {
+            estimate: heuristic(start_node),
+            index: start_node,
+        });
+
+        let mut found = false;
+        while let Some(OpenNode { estimate, index }) = open.pop() {
+            if index == end_node {
+                found = true;
+                break;
+            }
+            let cost = costs[&index];
+            if estimate - heuristic(index) > cost + STALE_TOLERANCE {
+                continue;
+            }
+
+            for &(next, _) in roads.neighbors(index) {
+                let Some((step, _)) = zones.segment(node_xy(index), node_xy(next)) else {
+                    continue;
+                };
+                let next_cost = cost + step;
+                if next_cost < costs.get(&next).copied().unwrap_or(f64::INFINITY) {
+                    costs.insert(next, next_cost);
+                    previous.insert(next, index);
+                    open.push(OpenNode {
+                        estimate: next_cost + heuristic(next),
+                        index: next,
+                    });
+                }
+            }
+        }
+        if !found {
+            return Some(RouteResult::NoSafeRoute {});
+        }
+
+        let mut node = end_node;
+        let mut nodes = vec![node];
+        while let Some(&previous_node) = previous.get(&node) {
+            nodes.push(previous_node);
+            node = previous_node;
+        }
+        nodes.reverse();
+
+        let mut points = vec![start_xy];
+        points.extend(nodes.iter().map(|node| node_xy(*node)));
+        points.push(end_xy);
+        points.dedup();
+
+        let mut distance_meters = 0.0;
+        let mut penalized_meters = 0.0;
+        for segment in points.windows(2) {
+            distance_meters += ((segment[1].0 - segment[0].0).powi(2)
+                + (segment[1].1 - segment[0].1).powi(2))
+            .sqrt();
+            // The ways to and from the roads may have to leave a zone.
+            let (_, penalized) = zones.segment(segment[0], segment[1]).unwrap_or((0.0, 0.0));
+            penalized_meters += penalized;
+        }
+
+        let mut path = vec![start];
+        path.extend(nodes.iter().map(|node| roads.node(*node)));
+        path.push(end);
+        path.dedup();
+
+        Some(RouteResult::Found {
+            path,
+            distance_meters,
+            penalized_meters,
+            follows_roads: true,
+        })
+    }
+
+    fn route_over_ground(
+        &self,
+        zones: &ProjectedZones,
+        projection: &LocalProjection,
+        start: Coordinate,
+        end: Coordinate,
+    ) -> RouteResult {
+        let start_xy = projection.project(&start);
+        let end_xy = projection.project(&end);
+
+        // Search window around both points, widened by the zones it touches
+        // so detours around them stay inside the grid.
+        let margin = (start.distance_to(&end) * 0.25).max(MIN_SEARCH_MARGIN_METERS);
+        let mut min = (
+            start_xy.0.min(end_xy.0) - margin,
+            start_xy.1.min(end_xy.1) - margin,
+        );
+        let mut max = (
+            start_xy.0.max(end_xy.0) + margin,
+            start_xy.1.max(end_xy.1) + margin,
+        );
+        for zone in &self.zones {
+            let (center, radius) = zone.area.bounding_circle();
+            let (x, y) = projection.project(&center);
+            if x + radius >= min.0
+                && x - radius <= max.0
+                && y + radius >= min.1
+                && y - radius <= max.1
+            {
+                min = (
+                    min.0.min(x - radius - margin),
+                    min.1.min(y - radius - margin),
+                );
+                max = (
+                    max.0.max(x + radius + margin),
+                    max.1.max(y + radius + margin),
+                );
+            }
+        }
+
+        let cell_size =
+            ((max.0 - min.0).max(max.1 - min.1) / MAX_GRID_SIDE as f64).max(MIN_CELL_SIZE_METERS);
+        let columns = ((max.0 - min.0) / cell_size).ceil() as usize + 1;
+        let rows = ((max.1 - min.1) / cell_size).ceil() as usize + 1;
+
+        let cell_center = |index: usize| {
+            (
+                min.0 + (index % columns) as f64 * cell_size,
+                min.1 + (index / columns) as f64 * cell_size,
+            )
+        };
+        let cell_at = |(x, y): (f64, f64)| {
+            let column = ((x - min.0) / cell_size).round() as usize;
+            let row = ((y - min.1) / cell_size).round() as usize;
+            row.min(rows - 1) * columns + column.min(columns - 1)
+        };
+
+        // `None` marks an impassable cell, otherwise the cost multiplier.
+        let weights: Vec<Option<f64>> = (0..rows * columns)
+            .map(|index| zones.weight(cell_center(index)))
+            .collect();
+
+        let start_cell = cell_at(start_xy);
+        let end_cell = cell_at(end_xy);
+
+        let Some(cells) = Self::search(&weights, columns, rows, start_cell, end_cell, cell_size)
+        else {
+            return RouteResult::NoSafeRoute {};
+        };
+
+        let mut points: Vec<(f64, f64)> = Vec::with_capacity(cells.len());
+        for (position, &cell) in cells.iter().enumerate() {
+            let point = if position == 0 {
+                start_xy
+            } else if position == cells.len() - 1 {
+                end_xy
+            } else {
+                cell_center(cell)
+            };
+            // Drop intermediate points lying on a straight line.
+            if points.len() >= 2 {
+                let (a, b) = (points[points.len() - 2], points[points.len() - 1]);
+                if ((b.0 - a.0) * (point.1 - b.1) - (b.1 - a.1) * (point.0 - b.0)).abs() < 1e-6 {
+                    points.pop();
+                }
+            }
+            points.push(point);
+        }
+        if cells.len() == 1 {
+            points.push(end_xy);
+        }
+
+        let mut distance_meters = 0.0;
+        let mut penalized_meters = 0.0;
+        for segment in points.windows(2) {
+            let length = ((segment[1].0 - segment[0].0).powi(2)
+                + (segment[1].1 - segment[0].1).powi(2))
+            .sqrt();
+            let midpoint = (
+                (segment[0].0 + segment[1].0) / 2.0,
+                (segment[0].1 + segment[1].1) / 2.0,
+            );
+            distance_meters += length;
+            if zones.penalized(midpoint) {
+                penalized_meters += length;
+            }
+        }
+
+        RouteResult::Found {
+            path: points
+                .into_iter()
+                .map(|(x, y)| projection.unproject(x, y))
+                .collect(),
+            distance_meters,
+            penalized_meters,
+            follows_roads: false,
+        }
+    }
+
+    fn search(
+        weights: &[Option<f64>],
+        columns: usize,
+        rows: usize,
+        start: usize,
+        end: usize,
+        cell_size: f64,
+    ) -> Option<Vec<usize>> {
+        let heuristic = |index: usize| {
+            let dx = (index % columns) as f64 - (end % columns) as f64;
+            let dy = (index / columns) as f64 - (end / columns) as f64;
+            (dx * dx + dy * dy).sqrt() * cell_size
+        };
+        let weight = |index: usize| {
+            if index == start || index == end {
+                Some(weights[index].unwrap_or(1.0))
+            } else {
+                weights[index]
+            }
+        };
+
+        let mut costs = vec![f64::INFINITY; weights.len()];
+        let mut previous = vec![usize::MAX; weights.len()];
+        let mut open = BinaryHeap::new();
+        costs[start] = 0.0;
+        open.push(OpenNode {
+            estimate: heuristic(start),
+            index: start,
+        });
+
+        while let Some(OpenNode { estimate, index }) = open.pop() {
+            if index == end {
+                let mut path = vec![end];
+                let mut current = end;
+                while current != start {
+                    current = previous[current];
+                    path.push(current);
+                }
+                path.reverse();
+                return Some(path);
+            }
+            if estimate - heuristic(index) > costs[index] + STALE_TOLERANCE {
+                continue;
+            }
+
+            let current_weight = weight(index).unwrap_or(1.0);
+            let (column, row) = ((index % columns) as isize, (index / columns) as isize);
+            for (dx, dy) in [
+                (-1, -1),
+                (0, -1),
+                (1, -1),
+                (-1, 0),
+                (1, 0),
+                (-1, 1),
+                (0, 1),
+                (1, 1),
+            ] {
+                let (next_column, next_row) = (column + dx, row + dy);
+                if next_column < 0
+                    || next_row < 0
+                    || next_column >= columns as isize
+                    || next_row >= rows as isize
+                {
+                    continue;
+                }
+                let next = next_row as usize * columns + next_column as usize;
+                let Some(next_weight) = weight(next) else {
+                    continue;
+                };
+
+                let step = if dx != 0 && dy != 0 {
+                    cell_size * std::f64::consts::SQRT_2
+                } else {
+                    cell_size
+                };
+                let cost = costs[index] + step * (current_weight + next_weight) / 2.0;
+                if cost < costs[next] {
+                    costs[next] = cost;
+                    previous[next] = index;
+                    open.push(OpenNode {
+                        estimate: cost + heuristic(next),
+                        index: next,
+                    });
+                }
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn projection() -> LocalProjection {
+        LocalProjection::new(Coordinate::new(48.8566, 2.3522))
+    }
+
+    fn at(x: f64, y: f64) -> Coordinate {
+        projection().unproject(x, y)
+    }
+
+    fn closure(x: f64, y: f64, radius_meters: f64) -> AvoidanceZone {
+        AvoidanceZone {
+            area: Area::Circle {
+                center: at(x, y),
+                radius_meters,
+            },
+            penalty: None,
+        }
+    }
+
+    /// A straight road from (0, 0) to (1000, 0) and a detour through
+    /// (0, 500) and (1000, 500).
+    fn roads() -> RoadGraph {
+        let mut roads = RoadGraph::new();
+        roads.add_road(&[at(0.0, 0.0), at(500.0, 0.0), at(1000.0, 0.0)]);
+        roads.add_road(&[
+            at(0.0, 0.0),
+            at(0.0, 500.0),
+            at(1000.0, 500.0),
+            at(1000.0, 0.0),
+        ]);
+        roads
+    }
+
+    fn found(result: RouteResult) -> (Vec<Coordinate>, f64, f64, bool) {
+        match result {
+            RouteResult::Found {
+                path,
+                distance_meters,
+                penalized_meters,
+                follows_roads,
+            } => (path, distance_meters, penalized_meters, follows_roads),
+            RouteResult::NoSafeRoute {} => panic!("expected a route"),
+        }
+    }
+
+    #[test]
+    fn serializes_fields_in_camel_case() {
+        let result = RouteResult::Found {
+            path: Vec::new(),
+            distance_meters: 1.0,
+            penalized_meters: 0.0,
+            follows_roads: true,
+        };
+        assert_eq!(
+            serde_json::to_value(result).unwrap(),
+            serde_json::json!({
+                "status": "found",
+                "data": {
+                    "path": [],
+                    "distanceMeters": 1.0,
+                    "penalizedMeters": 0.0,
+                    "followsRoads": true,
+                },
+            })
+        );
+
+        let zone: AvoidanceZone = serde_json::from_value(serde_json::json!({
+            "area": {
+                "type": "circle",
+                "center": { "latitude": 1.0, "longitude": 2.0 },
+                "radiusMeters": 50.0,
+            },
+        }))
+        .unwrap();
+        assert!(matches!(
+            zone.area,
+            Area::Circle { radius_meters, .. } if radius_meters == 50.0
+        ));
+    }
+
+    #[test]
+    fn follows_the_shortest_road_without_zones() {
+        let router = Router::new(Vec::new()).with_roads(roads());
+        let (path, distance, penalized, follows_roads) =
+            found(router.compute_route(at(0.0, 10.0), at(1000.0, 10.0)));
+
+        assert!(follows_roads);
+        assert!(path.contains(&at(500.0, 0.0)));
+        assert!((distance - 1020.0).abs() < 1.0, "distance {}", distance);
+        assert_eq!(penalized, 0.0);
+    }
+
+    #[test]
+    fn takes_the_detour_around_a_closure() {
+        let router = Router::new(vec![closure(500.0, 0.0, 50.0)]).with_roads(roads());
+        let (path, distance, penalized, _) =
+            found(router.compute_route(at(0.0, 10.0), at(1000.0, 10.0)));
+
+        assert!(path.contains(&at(0.0, 500.0)));
+        assert!(!path.contains(&at(500.0, 0.0)));
+        assert!(distance > 2000.0);
+        assert_eq!(penalized, 0.0);
+    }
+
+    #[test]
+    fn prefers_a_penalized_road_to_a_much_longer_one() {
+        let zone = AvoidanceZone {
+            penalty: Some(0.5),
+            ..closure(500.0, 0.0, 50.0)
+        };
+        let router = Router::new(vec![zone]).with_roads(roads());
+        let (path, _, penalized, _) = found(router.compute_route(at(0.0, 10.0), at(1000.0, 10.0)));
+
+        assert!(path.contains(&at(500.0, 0.0)));
+        assert!((penalized - 100.0).abs() < 15.0, "penalized {}", penalized);
+    }
+
+    #[test]
+    fn reports_when_every_road_is_closed() {
+        let router = Router::new(vec![closure(500.0, 0.0, 50.0), closure(500.0, 500.0, 50.0)])
+            .with_roads(roads());
+
+        assert!(matches!(
+            router.compute_route(at(0.0, 10.0), at(1000.0, 10.0)),
+            RouteResult::NoSafeRoute {}
+        ));
+    }
+
+    #[test]
+    fn crosses_open_ground_far_from_roads() {
+        let router = Router::new(vec![closure(500.0, 5000.0, 100.0)]).with_roads(roads());
+        let (path, distance, _, follows_roads) =
+            found(router.compute_route(at(0.0, 5000.0), at(1000.0, 5000.0)));
+
+        assert!(!follows_roads);
+        assert!(distance > 1000.0);
+        let zone = Area::Circle {
+            center: at(500.0, 5000.0),
+            radius_meters: 100.0,
+        };
+        assert!(path.iter().all(|point| !zone.contains(point)));
+    }
+
+    #[test]
+    fn leads_out_of_a_closure_containing_the_start() {
+        let router = Router::new(vec![closure(0.0, 0.0, 50.0)]).with_roads(roads());
+        let (_, _, penalized, follows_roads) =
+            found(router.compute_route(at(0.0, 10.0), at(1000.0, 10.0)));
+
+        assert!(follows_roads);
+        assert!(penalized > 0.0);
+    }
+
+    #[test]
+    fn builds_zones_from_hazard_markers_only() {
+        let marker = |kind| Marker {
+            latitude: 48.8566,
+            longitude: 2.3522,
+            name: "marker".to_string(),
+            kind,
+            radius: None,
+            penalty: None,
+            outline: None,
+        };
+
+        assert!(AvoidanceZone::from_marker(&marker(MarkerKind::MeetingPoint)).is_none());
+        let closure = AvoidanceZone::from_marker(&marker(MarkerKind::Closure)).unwrap();
+        assert_eq!(closure.penalty, None);
+        let threat = AvoidanceZone::from_marker(&marker(MarkerKind::Threat)).unwrap();
+        assert_eq!(threat.penalty, Some(THREAT_PENALTY));
+    }
+}
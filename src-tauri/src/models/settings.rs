@@ -0,0 +1,23 @@
+use anyhow::Result;
+use serde::{de::DeserializeOwned, Serialize};
+use tauri::AppHandle;
+use tauri_plugin_store::StoreExt;
+
+// Shared with the webview, see `src/stores/jsonStore.ts`.
+const STORE_PATH: &str = "store.json";
+
+pub fn load_setting<T: DeserializeOwned>(app_handle: &AppHandle, key: &str) -> Result<Option<T>> {
+    let store = app_handle.store(STORE_PATH)?;
+    store
+        .get(key)
+        .map(serde_json::from_value)
+        .transpose()
+        .map_err(Into::into)
+}
+
+pub fn save_setting<T: Serialize>(app_handle: &AppHandle, key: &str, value: &T) -> Result<()> {
+    let store = app_handle.store(STORE_PATH)?;
+    store.set(key, serde_json::to_value(value)?);
+    store.save()?;
+    Ok(())
+}
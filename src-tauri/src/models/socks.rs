@@ -0,0 +1,305 @@
+//! Loopback SOCKS5 proxy (RFC 1928, with RFC 1929 authentication) tunneling
+//! through the embedded Tor client, so that other components such as the
+//! Waku node reuse the same bootstrapped instance.
+
+use anyhow::Result;
+use data_encoding::HEXLOWER;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+
+use crate::models::tor::{StreamIsolation, TorClientWrapper};
+
+const SOCKS_VERSION: u8 = 5;
+const AUTH_VERSION: u8 = 1;
+const METHOD_NO_AUTH: u8 = 0x00;
+const METHOD_USER_PASS: u8 = 0x02;
+const METHOD_UNACCEPTABLE: u8 = 0xff;
+const COMMAND_CONNECT: u8 = 0x01;
+const ADDRESS_IPV4: u8 = 0x01;
+const ADDRESS_DOMAIN: u8 = 0x03;
+const ADDRESS_IPV6: u8 = 0x04;
+const REPLY_SUCCEEDED: u8 = 0x00;
+const REPLY_GENERAL_FAILURE: u8 = 0x01;
+const REPLY_COMMAND_NOT_SUPPORTED: u8 = 0x07;
+const REPLY_ADDRESS_NOT_SUPPORTED: u8 = 0x08;
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+// Streams of clients without credentials share this isolation scope.
+const DEFAULT_ISOLATION: &str = "socks";
+// Pause after a failed accept, doubled while it keeps failing, so that
+// running out of file descriptors does not turn into a busy loop.
+const MIN_ACCEPT_BACKOFF: Duration = Duration::from_millis(10);
+const MAX_ACCEPT_BACKOFF: Duration = Duration::from_secs(1);
+
+struct RunningProxy {
+    address: SocketAddr,
+    task: JoinHandle<()>,
+}
+
+/// Optional SOCKS5 listener. Clients authenticating with different
+/// username/password pairs never share circuits.
+#[derive(Default)]
+pub struct SocksProxy {
+    running: Mutex<Option<RunningProxy>>,
+}
+
+impl SocksProxy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts listening on the loopback interface, on a random port when
+    /// `port` is 0. Returns the listening address, which is the current one
+    /// if the proxy is already running.
+    pub async fn start(&self, tor_client: TorClientWrapper, port: u16) -> Result<SocketAddr> {
+        if let Some(address) = self.address() {
+            return Ok(address);
+        }
+
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port)).await?;
+        let address = listener.local_addr()?;
+
+        let task = tokio::spawn(async move {
+            let mut backoff = MIN_ACCEPT_BACKOFF;
+            loop {
+                let (stream, _) = match listener.accept().await {
+                    Ok(connection) => {
+                        backoff = MIN_ACCEPT_BACKOFF;
+                        connection
+                    }
+                    Err(e) => {
+                        eprintln!("Failed to accept SOCKS connection: {}", e);
+                        tokio::time::sleep(backoff).await;
+                        backoff = (backoff * 2).min(MAX_ACCEPT_BACKOFF);
+                        continue;
+                    }
+                };
+
+                let tor_client = tor_client.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = handle_connection(stream, &tor_client).await {
+                        eprintln!("SOCKS connection failed: {}", e);
+                    }
+                });
+            }
+        });
+
+        let mut running = self
+            .running
+            .lock()
+            .map_err(|_| anyhow::anyhow!("SOCKS proxy lock poisoned"))?;
+        if let Some(previous) = running.replace(RunningProxy { address, task }) {
+            previous.task.abort();
+        }
+
+        Ok(address)
+    }
+
+    /// Stops accepting connections. Connections already tunneled are left
+    /// open until either side closes them.
+    pub fn stop(&self) {
+        if let Some(running) = self.running.lock().ok().and_then(|mut r| r.take()) {
+            running.task.abort();
+        }
+    }
+
+    pub fn address(&self) -> Option<SocketAddr> {
+        self.running
+            .lock()
+            .ok()
+            .and_then(|running| running.as_ref().map(|running| running.address))
+    }
+}
+
+async fn handle_connection(mut stream: TcpStream, tor_client: &TorClientWrapper) -> Result<()> {
+    let (host, port, isolation) =
+        match tokio::time::timeout(HANDSHAKE_TIMEOUT, handshake(&mut stream)).await {
+            Ok(Ok(Some(request))) => request,
+            Ok(Ok(None)) => return Ok(()),
+            Ok(Err(e)) => return Err(e),
+            Err(_) => return Err(anyhow::anyhow!("SOCKS handshake timed out")),
+        };
+
+    let mut tor_stream = match tor_client.connect(&host, port, &isolation).await {
+        Ok(tor_stream) => tor_stream,
+        Err(e) => {
+            send_reply(&mut stream, REPLY_GENERAL_FAILURE).await?;
+            return Err(e);
+        }
+    };
+
+    send_reply(&mut stream, REPLY_SUCCEEDED).await?;
+    tokio::io::copy_bidirectional(&mut stream, &mut tor_stream).await?;
+    Ok(())
+}
+
+/// Negotiates authentication and reads the CONNECT request. Returns `None`
+/// when the request was refused and the client has been told so.
+async fn handshake<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: &mut S,
+) -> Result<Option<(String, u16, StreamIsolation)>> {
+    let version = stream.read_u8().await?;
+    if version != SOCKS_VERSION {
+        return Err(anyhow::anyhow!("Unsupported SOCKS version {}", version));
+    }
+
+    let method_count = stream.read_u8().await?;
+    let mut methods = vec![0; method_count as usize];
+    stream.read_exact(&mut methods).await?;
+
+    let isolation = if methods.contains(&METHOD_USER_PASS) {
+        stream.write_all(&[SOCKS_VERSION, METHOD_USER_PASS]).await?;
+        let (username, password) = read_credentials(stream).await?;
+        stream.write_all(&[AUTH_VERSION, REPLY_SUCCEEDED]).await?;
+        credential_isolation(&username, &password)
+    } else if methods.contains(&METHOD_NO_AUTH) {
+        stream.write_all(&[SOCKS_VERSION, METHOD_NO_AUTH]).await?;
+        StreamIsolation::Session(DEFAULT_ISOLATION.to_string())
+    } else {
+        stream
+            .write_all(&[SOCKS_VERSION, METHOD_UNACCEPTABLE])
+            .await?;
+        return Ok(None);
+    };
+
+    let mut header = [0u8; 4];
+    stream.read_exact(&mut header).await?;
+    let [version, command, _, address_type] = header;
+    if version != SOCKS_VERSION {
+        return Err(anyhow::anyhow!("Unsupported SOCKS version {}", version));
+    }
+
+    let host = match address_type {
+        ADDRESS_IPV4 => {
+            let mut octets = [0u8; 4];
+            stream.read_exact(&mut octets).await?;
+            Ipv4Addr::from(octets).to_string()
+        }
+        ADDRESS_IPV6 => {
+            let mut octets = [0u8; 16];
+            stream.read_exact(&mut octets).await?;
+            Ipv6Addr::from(octets).to_string()
+        }
+        ADDRESS_DOMAIN => {
+            let length = stream.read_u8().await?;
+            let mut domain = vec![0; length as usize];
+            stream.read_exact(&mut domain).await?;
+            String::from_utf8(domain)?
+        }
+        _ => {
+            send_reply(stream, REPLY_ADDRESS_NOT_SUPPORTED).await?;
+            return Ok(None);
+        }
+    };
+    let port = stream.read_u16().await?;
+
+    if command != COMMAND_CONNECT {
+        send_reply(stream, REPLY_COMMAND_NOT_SUPPORTED).await?;
+        return Ok(None);
+    }
+
+    Ok(Some((host, port, isolation)))
+}
+
+/// Scope of the streams of a username/password pair. Both are hex encoded,
+/// so that no two pairs give the same scope whatever bytes they contain.
+fn credential_isolation(username: &[u8], password: &[u8]) -> StreamIsolation {
+    StreamIsolation::Session(format!(
+        "{}:{}:{}",
+        DEFAULT_ISOLATION,
+        HEXLOWER.encode(username),
+        HEXLOWER.encode(password)
+    ))
+}
+
+async fn read_credentials<S: AsyncRead + Unpin>(stream: &mut S) -> Result<(Vec<u8>, Vec<u8>)> {
+    let version = stream.read_u8().await?;
+    if version != AUTH_VERSION {
+        return Err(anyhow::anyhow!(
+            "Unsupported SOCKS authentication version {}",
+            version
+        ));
+    }
+
+    let mut fields = Vec::with_capacity(2);
+    for _ in 0..2 {
+        let length = stream.read_u8().await?;
+        let mut field = vec![0; length as usize];
+        stream.read_exact(&mut field).await?;
+        fields.push(field);
+    }
+
+    let password = fields.pop().unwrap_or_default();
+    let username = fields.pop().unwrap_or_default();
+    Ok((username, password))
+}
+
+/// Sends a reply with an unspecified bound address, which clients ignore
+/// for tunneled connections.
+async fn send_reply<S: AsyncWrite + Unpin>(stream: &mut S, reply: u8) -> Result<()> {
+    stream
+        .write_all(&[SOCKS_VERSION, reply, 0, ADDRESS_IPV4, 0, 0, 0, 0, 0, 0])
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn separates_credentials_containing_colons() {
+        assert_ne!(
+            credential_isolation(b"a:b", b"c"),
+            credential_isolation(b"a", b"b:c")
+        );
+        assert_ne!(
+            credential_isolation(&[0xff], b""),
+            credential_isolation(&[0xfe], b"")
+        );
+        assert_ne!(
+            credential_isolation(b"", b""),
+            StreamIsolation::Session(DEFAULT_ISOLATION.to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn reads_authenticated_connect_requests() {
+        let (mut client, mut server) = tokio::io::duplex(256);
+        let handshake = tokio::spawn(async move { handshake(&mut server).await });
+
+        client
+            .write_all(&[SOCKS_VERSION, 1, METHOD_USER_PASS])
+            .await
+            .unwrap();
+        client.write_all(&[AUTH_VERSION, 3]).await.unwrap();
+        client.write_all(b"a:b").await.unwrap();
+        client.write_all(&[1]).await.unwrap();
+        client.write_all(b"c").await.unwrap();
+        client
+            .write_all(&[SOCKS_VERSION, COMMAND_CONNECT, 0, ADDRESS_DOMAIN, 9])
+            .await
+            .unwrap();
+        client.write_all(b"abc.onion").await.unwrap();
+        client.write_all(&443u16.to_be_bytes()).await.unwrap();
+
+        let (host, port, isolation) = handshake.await.unwrap().unwrap().unwrap();
+        assert_eq!((host.as_str(), port), ("abc.onion", 443));
+        assert_eq!(isolation, credential_isolation(b"a:b", b"c"));
+
+        let mut replies = [0u8; 4];
+        client.read_exact(&mut replies).await.unwrap();
+        assert_eq!(
+            replies,
+            [
+                SOCKS_VERSION,
+                METHOD_USER_PASS,
+                AUTH_VERSION,
+                REPLY_SUCCEEDED
+            ]
+        );
+    }
+}
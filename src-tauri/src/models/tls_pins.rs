@@ -0,0 +1,131 @@
+//! Pins of the TLS certificates served by onion services over HTTPS. The
+//! onion address already authenticates the service, so a pinned service is
+//! trusted on its pin alone, whoever issued its certificate.
+
+use anyhow::Result;
+use safelog::DisplayRedacted;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+use tauri::AppHandle;
+
+use crate::models::key_vault::parse_onion_address;
+use crate::models::settings::{load_setting, save_setting};
+
+const SETTINGS_KEY: &str = "onion_tls_pins";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PinKind {
+    /// Hash of the SubjectPublicKeyInfo, which survives renewals keeping
+    /// the same key.
+    Spki,
+    /// Hash of the whole DER certificate.
+    Certificate,
+}
+
+/// SHA-256 pin, base64 encoded as in HPKP. The SPKI pin of a certificate is
+/// given by `openssl x509 -pubkey -noout | openssl pkey -pubin -outform der
+/// | openssl dgst -sha256 -binary | base64`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TlsPin {
+    pub kind: PinKind,
+    pub sha256: String,
+}
+
+impl TlsPin {
+    fn digest(&self) -> Result<[u8; 32]> {
+        data_encoding::BASE64
+            .decode(self.sha256.trim().as_bytes())?
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("A SHA-256 pin is 32 bytes long"))
+    }
+}
+
+/// Pins by onion address, persisted in the settings store.
+pub struct TlsPins {
+    app_handle: AppHandle,
+    pins: RwLock<BTreeMap<String, Vec<TlsPin>>>,
+}
+
+impl TlsPins {
+    pub fn new(app_handle: AppHandle) -> Self {
+        let pins = load_setting(&app_handle, SETTINGS_KEY)
+            .unwrap_or_else(|e| {
+                eprintln!("Failed to load onion TLS pins: {}", e);
+                None
+            })
+            .unwrap_or_default();
+
+        Self {
+            app_handle,
+            pins: RwLock::new(pins),
+        }
+    }
+
+    pub fn all(&self) -> BTreeMap<String, Vec<TlsPin>> {
+        self.pins
+            .read()
+            .map(|pins| pins.clone())
+            .unwrap_or_default()
+    }
+
+    /// Pins of the service at `host`, empty if it is not pinned.
+    pub fn get(&self, host: &str) -> Vec<TlsPin> {
+        self.pins
+            .read()
+            .ok()
+            .and_then(|pins| pins.get(&host.to_ascii_lowercase()).cloned())
+            .unwrap_or_default()
+    }
+
+    /// Replaces the pins of a service. An empty list unpins it, so that its
+    /// certificate is validated against the system roots again. Returns the
+    /// address of the service as the pins are stored under.
+    pub fn set(&self, onion_address: &str, pins: Vec<TlsPin>) -> Result<String> {
+        let address = parse_onion_address(onion_address)?
+            .display_unredacted()
+            .to_string();
+        for pin in &pins {
+            pin.digest()?;
+        }
+
+        let mut all = self
+            .pins
+            .write()
+            .map_err(|_| anyhow::anyhow!("TLS pins lock poisoned"))?;
+        if pins.is_empty() {
+            all.remove(&address);
+        } else {
+            all.insert(address.clone(), pins);
+        }
+        save_setting(&self.app_handle, SETTINGS_KEY, &*all)?;
+        Ok(address)
+    }
+}
+
+/// Checks a DER certificate against the pins, any of which may match.
+pub fn verify_certificate(pins: &[TlsPin], certificate: &[u8]) -> Result<()> {
+    let spki = openssl::x509::X509::from_der(certificate)?
+        .public_key()?
+        .public_key_to_der()?;
+    let certificate_digest: [u8; 32] = Sha256::digest(certificate).into();
+    let spki_digest: [u8; 32] = Sha256::digest(&spki).into();
+
+    for pin in pins {
+        let expected = match pin.kind {
+            PinKind::Spki => spki_digest,
+            PinKind::Certificate => certificate_digest,
+        };
+        if pin.digest()? == expected {
+            return Ok(());
+        }
+    }
+
+    Err(anyhow::anyhow!(
+        "Certificate does not match the pins, its SPKI pin is {}",
+        data_encoding::BASE64.encode(&spki_digest)
+    ))
+}
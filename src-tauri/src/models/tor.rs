@@ -1,11 +1,46 @@
 use anyhow::Result;
-use arti_client::{config::CfgPath, BootstrapBehavior, TorClient, TorClientConfig};
+use arti_client::config::{CfgPath, Reconfigure};
+use arti_client::{
+    BootstrapBehavior, ErrorKind, HasKind, IsolationToken, KeystoreSelector, StreamPrefs,
+    TorClient, TorClientConfig,
+};
 use futures::StreamExt;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
 use tauri::{AppHandle, Emitter, Manager};
-use tokio::sync::OnceCell;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tor_config::ExplicitOrAuto;
+use tor_hscrypto::pk::{HsClientDescEncKey, HsClientDescEncSecretKey, HsIdKeypair};
+use tor_keymgr::config::ArtiKeystoreKind;
 use tor_rtcompat::PreferredRuntime;
 
+use crate::models::bridges::BridgeSettings;
+use crate::models::key_vault::{self, AuthorizedClient, ClientAuthKey, KeyVault};
+use crate::models::tor_bundle::{self, StagedBundle, TorStateBundle};
+use crate::models::tor_status::{GuardInfo, TorStatus};
+
+// Interval at which `tor-status` is emitted between bootstrap events, so that
+// directory freshness and clock skew stay current.
+const STATUS_INTERVAL: Duration = Duration::from_secs(30);
+// Censored networks with bridges can take minutes to bootstrap.
+const BOOTSTRAP_TIMEOUT: Duration = Duration::from_secs(300);
+// Reaching an onion service takes a descriptor fetch and a rendezvous, so
+// this is well above what a clearnet connection would need.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(60);
+// A probe only opens a stream, so a healthy circuit answers much sooner.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(30);
+// How long a restart waits for the previous client to release its state.
+const STATE_RELEASE_TIMEOUT: Duration = Duration::from_secs(10);
+const STATE_RELEASE_POLL_INTERVAL: Duration = Duration::from_millis(200);
+// Scopes whose tokens are kept. Scopes come and go with SOCKS credentials
+// and parallel downloads, the least recently used are forgotten past this.
+const MAX_ISOLATION_SCOPES: usize = 512;
+
 #[derive(Debug, Clone, Serialize)]
 pub struct BootstrapStatus {
     pub progress: u32,
@@ -23,64 +58,630 @@ impl From<arti_client::status::BootstrapStatus> for BootstrapStatus {
     }
 }
 
+/// Scope whose streams may share circuits. Streams of different scopes are
+/// never sent over the same circuit, so that an exit or rendezvous observer
+/// cannot link them.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type", content = "id")]
+pub enum StreamIsolation {
+    Group(String),
+    /// Onion host the streams go to.
+    Server(String),
+    Session(String),
+}
+
+impl StreamIsolation {
+    /// Scope `index` among parallel scopes split from this one, whose streams
+    /// share circuits with neither each other nor other scopes. The first is
+    /// this scope itself, so that its warm circuits are reused.
+    pub fn split(&self, index: usize) -> StreamIsolation {
+        if index == 0 {
+            return self.clone();
+        }
+
+        let base = match self {
+            StreamIsolation::Group(id) => format!("group:{}", id),
+            StreamIsolation::Server(host) => format!("server:{}", host),
+            StreamIsolation::Session(id) => format!("session:{}", id),
+        };
+        StreamIsolation::Session(format!("{}#{}", base, index))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitFailureKind {
+    ConnectTimeout,
+    ConnectFailed,
+    /// The stream stopped delivering data while a response was expected.
+    Stalled,
+}
+
+/// Payload of the `tor-circuit-failure` event. The circuits of the scope
+/// have been retired when it is emitted.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CircuitFailure {
+    pub host: String,
+    pub port: u16,
+    pub isolation: StreamIsolation,
+    pub kind: CircuitFailureKind,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum BootstrapState {
+    Idle,
+    Running,
+    Ready,
+    Failed(String),
+    Cancelled,
+}
+
+impl BootstrapState {
+    fn is_finished(&self) -> bool {
+        !matches!(self, BootstrapState::Running)
+    }
+}
+
+/// Runs a single bootstrap attempt at a time, awaited by every caller.
+struct BootstrapSupervisor {
+    state: watch::Sender<BootstrapState>,
+    task: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl BootstrapSupervisor {
+    fn new() -> Self {
+        Self {
+            state: watch::Sender::new(BootstrapState::Idle),
+            task: Mutex::new(None),
+        }
+    }
+
+    /// Starts `attempt` unless one is running or the client is already
+    /// `ready`, then waits for the attempt in progress. `finished` is given
+    /// the outcome of the attempt before its callers.
+    async fn run<F, E>(
+        self: &Arc<Self>,
+        ready: bool,
+        attempt: F,
+        finished: impl FnOnce(&BootstrapState) + Send + 'static,
+    ) -> Result<()>
+    where
+        F: Future<Output = Result<(), E>> + Send + 'static,
+        E: std::fmt::Display,
+    {
+        let mut state = {
+            let mut task = self
+                .task
+                .lock()
+                .map_err(|_| anyhow::anyhow!("Tor bootstrap lock poisoned"))?;
+
+            let running = task.as_ref().is_some_and(|task| !task.is_finished());
+            if !running && !ready {
+                self.state.send_replace(BootstrapState::Running);
+
+                let supervisor = self.clone();
+                *task = Some(tokio::spawn(async move {
+                    let outcome = match tokio::time::timeout(BOOTSTRAP_TIMEOUT, attempt).await {
+                        Ok(Ok(())) => BootstrapState::Ready,
+                        Ok(Err(e)) => BootstrapState::Failed(e.to_string()),
+                        Err(_) => BootstrapState::Failed("Tor bootstrap timed out".to_string()),
+                    };
+                    finished(&outcome);
+                    supervisor.state.send_replace(outcome);
+                }));
+            } else if !running {
+                self.state.send_replace(BootstrapState::Ready);
+            }
+
+            self.state.subscribe()
+        };
+
+        let outcome = state
+            .wait_for(BootstrapState::is_finished)
+            .await
+            .map_err(|_| anyhow::anyhow!("Tor bootstrap state closed"))?
+            .clone();
+
+        match outcome {
+            BootstrapState::Ready => Ok(()),
+            BootstrapState::Failed(e) => Err(anyhow::anyhow!(e)),
+            _ => Err(anyhow::anyhow!("Tor bootstrap was cancelled")),
+        }
+    }
+
+    /// Stops the attempt in progress, failing its callers.
+    fn cancel(&self) {
+        let task = self.task.lock().ok().and_then(|mut task| task.take());
+        if let Some(task) = task {
+            if !task.is_finished() {
+                task.abort();
+                self.state.send_replace(BootstrapState::Cancelled);
+            }
+        }
+    }
+
+    fn reset(&self) {
+        self.cancel();
+        self.state.send_replace(BootstrapState::Idle);
+    }
+}
+
+/// Puts `task` in the slot, aborting the task it replaces.
+fn replace_task(slot: &Mutex<Option<JoinHandle<()>>>, task: Option<JoinHandle<()>>) {
+    if let Ok(mut slot) = slot.lock() {
+        if let Some(previous) = std::mem::replace(&mut *slot, task) {
+            previous.abort();
+        }
+    }
+}
+
+/// Isolation token of each scope, forgetting the least recently used scopes
+/// past `MAX_ISOLATION_SCOPES`. A forgotten scope gets a new token, and so
+/// new circuits, the next time it is used.
+#[derive(Default)]
+struct IsolationTokens {
+    tokens: HashMap<StreamIsolation, (IsolationToken, u64)>,
+    uses: u64,
+}
+
+impl IsolationTokens {
+    fn get(&mut self, isolation: &StreamIsolation) -> IsolationToken {
+        self.uses += 1;
+        let uses = self.uses;
+        if let Some((token, last_use)) = self.tokens.get_mut(isolation) {
+            *last_use = uses;
+            return *token;
+        }
+
+        let token = IsolationToken::new();
+        self.insert(isolation, token);
+        token
+    }
+
+    fn insert(&mut self, isolation: &StreamIsolation, token: IsolationToken) {
+        self.uses += 1;
+        self.tokens.insert(isolation.clone(), (token, self.uses));
+
+        if self.tokens.len() > MAX_ISOLATION_SCOPES {
+            let least_recent = self
+                .tokens
+                .iter()
+                .min_by_key(|(_, (_, last_use))| *last_use)
+                .map(|(isolation, _)| isolation.clone());
+            if let Some(least_recent) = least_recent {
+                self.tokens.remove(&least_recent);
+            }
+        }
+    }
+
+    fn remove(&mut self, isolation: &StreamIsolation) {
+        self.tokens.remove(isolation);
+    }
+
+    fn clear(&mut self) {
+        self.tokens.clear();
+    }
+}
+
+/// State shared between the wrapper and its background tasks.
+struct TorState {
+    client: RwLock<Option<TorClient<PreferredRuntime>>>,
+    /// Forwards the events of the current client, replaced with it.
+    forwarder: Mutex<Option<JoinHandle<()>>>,
+    bootstrap: Arc<BootstrapSupervisor>,
+    isolation_tokens: Mutex<IsolationTokens>,
+    bridges: RwLock<BridgeSettings>,
+    last_error: RwLock<Option<String>>,
+    /// Guard of the current client, read by the forwarder once it is ready.
+    guard: RwLock<Option<GuardInfo>>,
+    tor_dir: Option<PathBuf>,
+    key_vault: KeyVault,
+}
+
+impl TorState {
+    fn client(&self) -> Option<TorClient<PreferredRuntime>> {
+        self.client.read().ok().and_then(|client| client.clone())
+    }
+
+    fn isolation_token(&self, isolation: &StreamIsolation) -> IsolationToken {
+        match self.isolation_tokens.lock() {
+            Ok(mut tokens) => tokens.get(isolation),
+            Err(_) => IsolationToken::new(),
+        }
+    }
+
+    /// Gives the scope a new token, so that its next streams are opened over
+    /// fresh circuits instead of the ones that failed.
+    fn retire_circuits(&self, isolation: &StreamIsolation) {
+        if let Ok(mut tokens) = self.isolation_tokens.lock() {
+            tokens.insert(isolation, IsolationToken::new());
+        }
+    }
+
+    fn end_isolation(&self, isolation: &StreamIsolation) {
+        if let Ok(mut tokens) = self.isolation_tokens.lock() {
+            tokens.remove(isolation);
+        }
+    }
+
+    fn bridge_settings(&self) -> BridgeSettings {
+        self.bridges
+            .read()
+            .map(|bridges| bridges.clone())
+            .unwrap_or_default()
+    }
+
+    fn set_last_error(&self, error: Option<String>) {
+        if let Ok(mut last_error) = self.last_error.write() {
+            *last_error = error;
+        }
+    }
+
+    fn set_guard(&self, guard: Option<GuardInfo>) {
+        if let Ok(mut current) = self.guard.write() {
+            *current = guard;
+        }
+    }
+
+    fn status(&self) -> TorStatus {
+        TorStatus::collect(
+            self.client().as_ref(),
+            self.guard.read().ok().and_then(|guard| guard.clone()),
+            self.last_error.read().ok().and_then(|error| error.clone()),
+        )
+    }
+}
+
+#[derive(Clone)]
 pub struct TorClientWrapper {
-    client: OnceCell<TorClient<PreferredRuntime>>,
+    state: Arc<TorState>,
     app_handle: AppHandle,
 }
 
 impl TorClientWrapper {
     pub fn new(app_handle: AppHandle) -> Self {
+        let app_data_dir = app_handle.path().app_data_dir().ok();
+        let state = TorState {
+            client: RwLock::new(None),
+            forwarder: Mutex::new(None),
+            bootstrap: Arc::new(BootstrapSupervisor::new()),
+            isolation_tokens: Mutex::new(IsolationTokens::default()),
+            bridges: RwLock::new(BridgeSettings::load(&app_handle)),
+            last_error: RwLock::new(None),
+            guard: RwLock::new(None),
+            tor_dir: app_data_dir
+                .as_ref()
+                .map(|app_data_dir| app_data_dir.join("tor")),
+            key_vault: KeyVault::new(app_data_dir),
+        };
+
         Self {
-            client: OnceCell::new(),
+            state: Arc::new(state),
             app_handle,
         }
     }
 
-    async fn ensure_client(&self) -> Result<()> {
-        if self.client.get().is_none() {
-            let mut builder = TorClientConfig::builder();
-            builder.address_filter().allow_onion_addrs(true);
+    fn build_config(&self) -> Result<TorClientConfig> {
+        let mut builder = TorClientConfig::builder();
+        builder.address_filter().allow_onion_addrs(true);
 
-            if let Ok(app_data_dir) = self.app_handle.path().app_data_dir() {
-                let tor_path = &app_data_dir.join("tor");
-                std::fs::create_dir_all(tor_path)?;
-                if let Some(tor_path) = tor_path.to_str() {
-                    builder
-                        .storage()
-                        .cache_dir(CfgPath::new(tor_path.into()))
-                        .state_dir(CfgPath::new(tor_path.into()));
-                }
+        if let Some(tor_path) = &self.state.tor_dir {
+            std::fs::create_dir_all(tor_path)?;
+            if let Some(tor_path) = tor_path.to_str() {
+                builder
+                    .storage()
+                    .cache_dir(CfgPath::new(tor_path.into()))
+                    .state_dir(CfgPath::new(tor_path.into()));
             }
+        }
+
+        // Arti's keystore only holds onion keys: the client authorization
+        // keys and the keys of the hosted service. Arti cannot pick a keystore
+        // per key type, so the whole keystore is in memory. The long-lived
+        // keys are persisted in the key vault and given back on every start,
+        // while the short-lived service keys (introduction points, descriptor
+        // signing) are generated again when the service is launched.
+        builder
+            .storage()
+            .keystore()
+            .primary()
+            .kind(ExplicitOrAuto::Explicit(ArtiKeystoreKind::Ephemeral));
 
-            let config = builder.build()?;
+        self.bridge_settings().apply(&mut builder)?;
 
-            let tor_client = TorClient::builder()
-                .config(config)
-                .bootstrap_behavior(BootstrapBehavior::OnDemand)
-                .create_unbootstrapped()?;
+        Ok(builder.build()?)
+    }
+
+    fn ensure_client(&self) -> Result<TorClient<PreferredRuntime>> {
+        let mut client = self
+            .state
+            .client
+            .write()
+            .map_err(|_| anyhow::anyhow!("Tor client lock poisoned"))?;
 
-            self.client
-                .set(tor_client)
-                .map_err(|_| anyhow::anyhow!("Tor client already initialized"))?;
+        if let Some(client) = client.as_ref() {
+            return Ok(client.clone());
         }
-        Ok(())
+
+        let config = self.build_config()?;
+
+        let tor_client = TorClient::builder()
+            .config(config)
+            .bootstrap_behavior(BootstrapBehavior::OnDemand)
+            .create_unbootstrapped()?;
+
+        match self.state.key_vault.client_auth_keys() {
+            Ok(keys) => {
+                for (hsid, secret) in keys {
+                    if let Err(e) = tor_client.insert_service_discovery_key(
+                        KeystoreSelector::Primary,
+                        hsid,
+                        secret,
+                    ) {
+                        eprintln!("Failed to add onion client authorization key: {}", e);
+                    }
+                }
+            }
+            Err(e) => eprintln!("Failed to load onion client authorization keys: {}", e),
+        }
+
+        *client = Some(tor_client.clone());
+        self.spawn_forwarder(&tor_client);
+        Ok(tor_client)
     }
 
+    /// Bootstraps the client, joining the attempt in progress if there is
+    /// one. Fails if the attempt times out or is cancelled.
     pub async fn bootstrap(&self) -> Result<()> {
-        self.ensure_client().await?;
-        let client = self
-            .client
-            .get()
-            .ok_or_else(|| anyhow::anyhow!("Tor client not initialized"))?;
+        let client = self.ensure_client()?;
 
-        client
-            .bootstrap()
+        let ready = client.bootstrap_status().ready_for_traffic();
+        let state = self.state.clone();
+        self.state
+            .bootstrap
+            .run(
+                ready,
+                async move { client.bootstrap().await },
+                move |outcome| {
+                    state.set_last_error(match outcome {
+                        BootstrapState::Failed(e) => Some(e.clone()),
+                        _ => None,
+                    });
+                },
+            )
             .await
-            .map_err(|e: arti_client::Error| anyhow::anyhow!(e))
+    }
+
+    /// Stops the bootstrap attempt in progress, failing its callers.
+    pub fn cancel_bootstrap(&self) {
+        self.state.bootstrap.cancel();
+    }
+
+    /// Applies the current settings to the running client, restarting it
+    /// when a changed option cannot be updated in place.
+    pub async fn reconfigure(&self) -> Result<()> {
+        let client = match self.get_client() {
+            Some(client) => client,
+            None => return Ok(()),
+        };
+
+        if let Err(e) = client.reconfigure(&self.build_config()?, Reconfigure::AllOrNothing) {
+            eprintln!("Failed to reconfigure Tor client, restarting it: {}", e);
+            self.restart().await?;
+        }
+
+        Ok(())
+    }
+
+    /// Replaces the client with a fresh one built from the current settings,
+    /// bootstrapping it if there was a client running. Streams opened through
+    /// the previous client keep working until they are closed.
+    pub async fn restart(&self) -> Result<()> {
+        let was_running = self.get_client().is_some();
+        self.shutdown();
+        self.wait_for_state_release().await;
+
+        if was_running {
+            self.bootstrap().await?;
+        }
+
+        Ok(())
+    }
+
+    /// Releases the client. Arti stops its background tasks once the last
+    /// stream opened through it is closed, so in-flight requests complete.
+    pub fn shutdown(&self) {
+        self.state.bootstrap.reset();
+
+        if let Ok(mut client) = self.state.client.write() {
+            client.take();
+            replace_task(&self.state.forwarder, None);
+        }
+
+        if let Ok(mut tokens) = self.state.isolation_tokens.lock() {
+            tokens.clear();
+        }
+
+        self.state.set_last_error(None);
+    }
+
+    /// Waits until no client holds Arti's state lock. Clones of a client
+    /// that was shut down hold it until the streams opened through them are
+    /// closed, and a client started before that only gets read-only state
+    /// until Arti manages to take the lock. Gives up after
+    /// `STATE_RELEASE_TIMEOUT`, leaving it to Arti.
+    async fn wait_for_state_release(&self) {
+        let Some(lock_path) = self
+            .state
+            .tor_dir
+            .as_ref()
+            .map(|tor_dir| tor_dir.join("state").join("state.lock"))
+        else {
+            return;
+        };
+
+        let deadline = Instant::now() + STATE_RELEASE_TIMEOUT;
+        while !state_lock_is_free(&lock_path) {
+            if Instant::now() >= deadline {
+                eprintln!("Previous Tor client still holds its state, starting read-only");
+                return;
+            }
+            tokio::time::sleep(STATE_RELEASE_POLL_INTERVAL).await;
+        }
+    }
+
+    pub fn bridge_settings(&self) -> BridgeSettings {
+        self.state.bridge_settings()
+    }
+
+    /// Validates the bridge settings and applies them to the running client,
+    /// then persists them. Returns the settings as stored.
+    pub async fn set_bridges(&self, settings: BridgeSettings) -> Result<BridgeSettings> {
+        let settings = settings.validate()?;
+        let previous = self.replace_bridges(settings.clone())?;
+
+        if let Err(e) = self.reconfigure().await {
+            self.replace_bridges(previous)?;
+            return Err(e);
+        }
+
+        settings.save(&self.app_handle)?;
+        Ok(settings)
+    }
+
+    fn replace_bridges(&self, settings: BridgeSettings) -> Result<BridgeSettings> {
+        let mut bridges = self
+            .state
+            .bridges
+            .write()
+            .map_err(|_| anyhow::anyhow!("Tor bridges lock poisoned"))?;
+        Ok(std::mem::replace(&mut *bridges, settings))
+    }
+
+    /// Writes the directory cache to `path`, for another device to import.
+    /// The client must be bootstrapped, so that the lifetime of its consensus
+    /// is known.
+    pub fn export_state(&self, path: &Path, include_guards: bool) -> Result<TorStateBundle> {
+        let directory = self
+            .status()
+            .directory
+            .ok_or_else(|| anyhow::anyhow!("No Tor directory to export, bootstrap first"))?;
+        tor_bundle::export_bundle(self.tor_dir()?, path, directory, include_guards)
+    }
+
+    /// Replaces the directory cache with the one of a bundle, if it is newer,
+    /// restarting the client around it. The cache is only replaced once the
+    /// previous client has released it.
+    pub async fn import_state(&self, path: &Path) -> Result<TorStateBundle> {
+        let tor_dir = self.tor_dir()?;
+        let staged = StagedBundle::unpack(tor_dir, path, self.status().directory.as_ref())?;
+        let bundle = staged.bundle.clone();
+
+        let was_running = self.get_client().is_some();
+        self.shutdown();
+        self.wait_for_state_release().await;
+        staged.install(tor_dir)?;
+
+        if was_running {
+            self.bootstrap().await?;
+        }
+
+        Ok(bundle)
+    }
+
+    fn tor_dir(&self) -> Result<&Path> {
+        self.state
+            .tor_dir
+            .as_deref()
+            .ok_or_else(|| anyhow::anyhow!("Failed to get the Tor directory"))
+    }
+
+    pub fn client_auth_keys(&self) -> Result<Vec<ClientAuthKey>> {
+        Ok(self
+            .state
+            .key_vault
+            .client_auth_keys()?
+            .iter()
+            .map(|(hsid, secret)| ClientAuthKey::new(hsid, secret))
+            .collect())
+    }
+
+    /// Generates a client authorization key for a restricted discovery
+    /// service. The returned public key must be added to the service.
+    pub fn generate_client_auth_key(&self, onion_address: &str) -> Result<ClientAuthKey> {
+        let hsid = key_vault::parse_onion_address(onion_address)?;
+        self.set_client_auth_key(hsid, key_vault::generate_client_secret())
+    }
+
+    /// Imports a client authorization key, see
+    /// [`key_vault::parse_client_auth_key`] for the accepted formats.
+    pub fn import_client_auth_key(
+        &self,
+        onion_address: Option<&str>,
+        key: &str,
+    ) -> Result<ClientAuthKey> {
+        let (hsid, secret) = key_vault::parse_client_auth_key(onion_address, key)?;
+        self.set_client_auth_key(hsid, secret)
+    }
+
+    /// Returns whether the service had a key.
+    pub fn remove_client_auth_key(&self, onion_address: &str) -> Result<bool> {
+        let hsid = key_vault::parse_onion_address(onion_address)?;
+        let removed = self.state.key_vault.remove_client_auth_key(&hsid)?;
+
+        if let Some(client) = self.get_client() {
+            client.remove_service_discovery_key(KeystoreSelector::Primary, hsid)?;
+        }
+
+        Ok(removed)
+    }
+
+    /// Stores the key and hands it to the running client, so that the next
+    /// descriptor fetch for the service uses it.
+    fn set_client_auth_key(
+        &self,
+        hsid: arti_client::HsId,
+        secret: HsClientDescEncSecretKey,
+    ) -> Result<ClientAuthKey> {
+        let key = self
+            .state
+            .key_vault
+            .insert_client_auth_key(&hsid, &secret)?;
+
+        if let Some(client) = self.get_client() {
+            client.remove_service_discovery_key(KeystoreSelector::Primary, hsid)?;
+            client.insert_service_discovery_key(KeystoreSelector::Primary, hsid, secret)?;
+        }
+
+        Ok(key)
+    }
+
+    /// Clients allowed to discover the onion service hosted by this device.
+    pub fn authorized_clients(&self) -> Result<Vec<(String, HsClientDescEncKey)>> {
+        self.state.key_vault.authorized_clients()
+    }
+
+    /// Allows a client to discover the hosted onion service, see
+    /// [`key_vault::parse_client_public_key`] for the accepted formats.
+    pub fn authorize_client(&self, nickname: &str, public_key: &str) -> Result<AuthorizedClient> {
+        let public_key = key_vault::parse_client_public_key(public_key)?;
+        self.state.key_vault.authorize_client(nickname, &public_key)
+    }
+
+    /// Returns whether the client was authorized.
+    pub fn revoke_client(&self, nickname: &str) -> Result<bool> {
+        self.state.key_vault.revoke_client(nickname)
+    }
+
+    /// Identity key of the onion service hosted by this device.
+    pub fn service_identity(&self) -> Result<HsIdKeypair> {
+        self.state.key_vault.service_identity()
     }
 
     pub fn is_ready(&self) -> bool {
-        let client = match self.client.get() {
+        let client = match self.get_client() {
             Some(client) => client,
             None => return false,
         };
@@ -89,14 +690,22 @@ impl TorClientWrapper {
         status.ready_for_traffic()
     }
 
-    pub fn get_client(&self) -> Option<&TorClient<PreferredRuntime>> {
-        self.client.get()
+    pub fn status(&self) -> TorStatus {
+        self.state.status()
+    }
+
+    pub fn get_client(&self) -> Option<TorClient<PreferredRuntime>> {
+        self.state.client()
     }
 
+    /// Opens a stream to `host:port` over circuits dedicated to the given
+    /// isolation scope. When the connection fails or times out, the circuits
+    /// of the scope are retired and the failure is reported.
     pub async fn connect(
         &self,
         host: &str,
         port: u16,
+        isolation: &StreamIsolation,
     ) -> Result<impl tokio::io::AsyncRead + tokio::io::AsyncWrite + Send + Unpin> {
         if !self.is_ready() {
             return Err(anyhow::anyhow!("Tor client is not enabled"));
@@ -106,29 +715,367 @@ impl TorClientWrapper {
             .get_client()
             .ok_or_else(|| anyhow::anyhow!("Tor client is not initialized"))?;
 
-        client
-            .connect((host, port))
-            .await
-            .map_err(|e: arti_client::Error| anyhow::anyhow!(e))
+        let mut prefs = StreamPrefs::new();
+        prefs.set_isolation(self.state.isolation_token(isolation));
+
+        let result = tokio::time::timeout(
+            CONNECT_TIMEOUT,
+            client.connect_with_prefs((host, port), &prefs),
+        )
+        .await;
+        match result {
+            Ok(Ok(stream)) => Ok(stream),
+            Ok(Err(e)) => {
+                let e: arti_client::Error = e;
+                // A bad address or missing authorization is not the fault of
+                // the circuits, which are kept.
+                if is_transient_kind(e.kind()) {
+                    self.report_circuit_failure(
+                        host,
+                        port,
+                        isolation,
+                        CircuitFailureKind::ConnectFailed,
+                        e.to_string(),
+                    );
+                }
+                Err(anyhow::anyhow!(e))
+            }
+            Err(elapsed) => {
+                let message = format!("Connecting to {}:{} over Tor timed out", host, port);
+                self.report_circuit_failure(
+                    host,
+                    port,
+                    isolation,
+                    CircuitFailureKind::ConnectTimeout,
+                    message.clone(),
+                );
+                Err(anyhow::Error::new(elapsed).context(message))
+            }
+        }
+    }
+
+    /// Checks that a stream to `host:port` opens over the circuits of the
+    /// scope within `PROBE_TIMEOUT`, and returns the time it took. A failed
+    /// probe retires the circuits like any failed connection, so that the
+    /// next request does not wait on a dead circuit.
+    pub async fn probe(
+        &self,
+        host: &str,
+        port: u16,
+        isolation: &StreamIsolation,
+    ) -> Result<Duration> {
+        let started = Instant::now();
+        match tokio::time::timeout(PROBE_TIMEOUT, self.connect(host, port, isolation)).await {
+            Ok(stream) => stream.map(|_| started.elapsed()),
+            Err(elapsed) => {
+                let message = format!("{}:{} did not answer the probe in time", host, port);
+                self.report_circuit_failure(
+                    host,
+                    port,
+                    isolation,
+                    CircuitFailureKind::ConnectTimeout,
+                    message.clone(),
+                );
+                Err(anyhow::Error::new(elapsed).context(message))
+            }
+        }
     }
 
-    pub async fn listen_bootstrap_events(&self, app: AppHandle) -> Result<()> {
-        self.ensure_client().await?;
+    /// Token the streams of the scope are currently isolated with.
+    pub fn isolation_token(&self, isolation: &StreamIsolation) -> IsolationToken {
+        self.state.isolation_token(isolation)
+    }
 
-        let mut bootstrap_events = self
-            .get_client()
-            .ok_or_else(|| anyhow::anyhow!("Tor client not initialized"))?
-            .bootstrap_events();
-
-        tokio::spawn(async move {
-            while let Some(status) = bootstrap_events.next().await {
-                let bootstrap_status: BootstrapStatus = status.into();
-                if let Err(e) = app.emit("tor-bootstrap-status", bootstrap_status) {
-                    eprintln!("Failed to emit bootstrap status: {}", e);
+    /// Forgets the token of a scope that will not be used again.
+    pub fn end_isolation(&self, isolation: &StreamIsolation) {
+        self.state.end_isolation(isolation);
+    }
+
+    /// Retires the circuits of the scope and emits `tor-circuit-failure`.
+    /// Callers reading from a stream report it when it stalls.
+    pub fn report_circuit_failure(
+        &self,
+        host: &str,
+        port: u16,
+        isolation: &StreamIsolation,
+        kind: CircuitFailureKind,
+        message: String,
+    ) {
+        self.state.retire_circuits(isolation);
+
+        let failure = CircuitFailure {
+            host: host.to_string(),
+            port,
+            isolation: isolation.clone(),
+            kind,
+            message,
+        };
+        if let Err(e) = self.app_handle.emit("tor-circuit-failure", failure) {
+            eprintln!("Failed to emit circuit failure: {}", e);
+        }
+    }
+
+    /// Forwards the bootstrap events of a new client as
+    /// `tor-bootstrap-status`, and the full status as `tor-status` on every
+    /// bootstrap event and periodically, replacing the previous forwarder.
+    /// The guard is read again on every period once the client is ready.
+    fn spawn_forwarder(&self, client: &TorClient<PreferredRuntime>) {
+        let mut bootstrap_events = client.bootstrap_events();
+        let client = client.clone();
+        let state = self.state.clone();
+        let app = self.app_handle.clone();
+        state.set_guard(None);
+
+        let forwarder = tokio::spawn(async move {
+            let mut interval = tokio::time::interval(STATUS_INTERVAL);
+            loop {
+                tokio::select! {
+                    status = bootstrap_events.next() => {
+                        let Some(status) = status else { break };
+                        let bootstrap_status: BootstrapStatus = status.into();
+                        if let Err(e) = app.emit("tor-bootstrap-status", bootstrap_status) {
+                            eprintln!("Failed to emit bootstrap status: {}", e);
+                        }
+                    }
+                    _ = interval.tick() => {
+                        if client.bootstrap_status().ready_for_traffic() {
+                            match tokio::time::timeout(PROBE_TIMEOUT, GuardInfo::probe(&client)).await {
+                                Ok(Ok(guard)) => state.set_guard(Some(guard)),
+                                Ok(Err(e)) => eprintln!("Failed to read the Tor guard: {}", e),
+                                Err(_) => eprintln!("Timed out reading the Tor guard"),
+                            }
+                        }
+                    }
+                }
+
+                if let Err(e) = app.emit("tor-status", state.status()) {
+                    eprintln!("Failed to emit Tor status: {}", e);
                 }
             }
         });
 
-        Ok(())
+        replace_task(&self.state.forwarder, Some(forwarder));
+    }
+}
+
+/// Whether Arti's state lock can be taken, which it cannot while a client
+/// of this or another process holds it.
+fn state_lock_is_free(lock_path: &Path) -> bool {
+    let Ok(file) = std::fs::OpenOptions::new().write(true).open(lock_path) else {
+        // Arti creates the lock file with the first client.
+        return true;
+    };
+    match file.try_lock() {
+        Ok(()) => file.unlock().is_ok(),
+        Err(_) => false,
+    }
+}
+
+/// Whether a failure of [`TorClientWrapper::connect`] may go away on fresh
+/// circuits. Timeouts and circuit failures may, while a client that is not
+/// running, an invalid address or a missing client authorization will not.
+pub fn is_transient_connect_error(error: &anyhow::Error) -> bool {
+    if let Some(e) = error.downcast_ref::<arti_client::Error>() {
+        return is_transient_kind(e.kind());
+    }
+    error
+        .downcast_ref::<tokio::time::error::Elapsed>()
+        .is_some()
+}
+
+fn is_transient_kind(kind: ErrorKind) -> bool {
+    !matches!(
+        kind,
+        ErrorKind::BootstrapRequired
+            | ErrorKind::ArtiShuttingDown
+            | ErrorKind::BadApiUsage
+            | ErrorKind::FeatureDisabled
+            | ErrorKind::NotImplemented
+            | ErrorKind::InvalidStreamTarget
+            | ErrorKind::ForbiddenStreamTarget
+            | ErrorKind::ExitPolicyRejected
+            | ErrorKind::RemoteConnectionRefused
+            | ErrorKind::RemoteHostNotFound
+            | ErrorKind::OnionServiceNotFound
+            | ErrorKind::OnionServiceAddressInvalid
+            | ErrorKind::OnionServiceMissingClientAuth
+            | ErrorKind::OnionServiceWrongClientAuth
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::sync::oneshot;
+
+    #[test]
+    fn forgets_the_least_recently_used_scopes() {
+        let mut tokens = IsolationTokens::default();
+        let group = StreamIsolation::Group("group".to_string());
+        let token = tokens.get(&group);
+
+        for index in 0..MAX_ISOLATION_SCOPES * 2 {
+            tokens.get(&StreamIsolation::Session(index.to_string()));
+            // Kept in use, so never the least recent.
+            assert_eq!(tokens.get(&group), token);
+        }
+        assert_eq!(tokens.tokens.len(), MAX_ISOLATION_SCOPES);
+
+        let first = StreamIsolation::Session("0".to_string());
+        assert!(!tokens.tokens.contains_key(&first));
+
+        tokens.remove(&group);
+        assert_ne!(tokens.get(&group), token);
+    }
+
+    #[tokio::test]
+    async fn runs_a_single_bootstrap_for_concurrent_callers() {
+        let supervisor = Arc::new(BootstrapSupervisor::new());
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let (release, released) = oneshot::channel::<()>();
+        let released = Arc::new(Mutex::new(Some(released)));
+
+        let callers: Vec<_> = (0..4)
+            .map(|_| {
+                let supervisor = supervisor.clone();
+                let attempts = attempts.clone();
+                let released = released.clone();
+                tokio::spawn(async move {
+                    let released = released.lock().unwrap().take();
+                    supervisor
+                        .run(
+                            false,
+                            async move {
+                                attempts.fetch_add(1, Ordering::SeqCst);
+                                if let Some(released) = released {
+                                    let _ = released.await;
+                                }
+                                Ok::<_, anyhow::Error>(())
+                            },
+                            |_| {},
+                        )
+                        .await
+                })
+            })
+            .collect();
+
+        tokio::task::yield_now().await;
+        release.send(()).unwrap();
+        for caller in callers {
+            caller.await.unwrap().unwrap();
+        }
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+
+        // A ready client is not bootstrapped again.
+        supervisor
+            .run(true, async { Err::<(), _>("unexpected attempt") }, |_| {})
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn fails_callers_of_a_cancelled_bootstrap() {
+        let supervisor = Arc::new(BootstrapSupervisor::new());
+        let caller = {
+            let supervisor = supervisor.clone();
+            tokio::spawn(async move {
+                supervisor
+                    .run(false, std::future::pending::<Result<(), String>>(), |_| {})
+                    .await
+            })
+        };
+
+        while !matches!(*supervisor.state.borrow(), BootstrapState::Running) {
+            tokio::task::yield_now().await;
+        }
+        supervisor.cancel();
+
+        let error = caller.await.unwrap().unwrap_err();
+        assert!(error.to_string().contains("cancelled"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn times_out_stuck_bootstraps() {
+        let supervisor = Arc::new(BootstrapSupervisor::new());
+        let (outcome, finished) = oneshot::channel();
+
+        let started = tokio::time::Instant::now();
+        let error = supervisor
+            .run(
+                false,
+                std::future::pending::<Result<(), String>>(),
+                move |state| {
+                    let _ = outcome.send(state.clone());
+                },
+            )
+            .await
+            .unwrap_err();
+
+        assert!(error.to_string().contains("timed out"));
+        assert!(started.elapsed() >= BOOTSTRAP_TIMEOUT);
+        assert!(matches!(finished.await.unwrap(), BootstrapState::Failed(_)));
+    }
+
+    #[tokio::test]
+    async fn replaces_and_aborts_forwarders() {
+        let slot = Mutex::new(None);
+        let (first_guard, first_stopped) = oneshot::channel::<()>();
+        let (second_guard, second_stopped) = oneshot::channel::<()>();
+
+        replace_task(
+            &slot,
+            Some(tokio::spawn(async move {
+                let _guard = first_guard;
+                std::future::pending::<()>().await
+            })),
+        );
+        replace_task(
+            &slot,
+            Some(tokio::spawn(async move {
+                let _guard = second_guard;
+                std::future::pending::<()>().await
+            })),
+        );
+        assert!(first_stopped.await.is_err());
+        assert!(slot.lock().unwrap().is_some());
+
+        replace_task(&slot, None);
+        assert!(second_stopped.await.is_err());
+        assert!(slot.lock().unwrap().is_none());
+    }
+
+    #[test]
+    fn sees_the_state_lock_of_other_clients() {
+        let lock_path = std::env::temp_dir().join(format!("ash-state-lock-{}", std::process::id()));
+        assert!(state_lock_is_free(&lock_path));
+
+        let holder = std::fs::File::create(&lock_path).unwrap();
+        holder.lock().unwrap();
+        assert!(!state_lock_is_free(&lock_path));
+
+        drop(holder);
+        assert!(state_lock_is_free(&lock_path));
+        std::fs::remove_file(&lock_path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn fails_fast_on_errors_fresh_circuits_do_not_fix() {
+        assert!(is_transient_kind(ErrorKind::TorNetworkTimeout));
+        assert!(is_transient_kind(ErrorKind::CircuitCollapse));
+        assert!(is_transient_kind(ErrorKind::OnionServiceConnectionFailed));
+        assert!(!is_transient_kind(ErrorKind::OnionServiceMissingClientAuth));
+        assert!(!is_transient_kind(ErrorKind::OnionServiceAddressInvalid));
+        assert!(!is_transient_kind(ErrorKind::RemoteConnectionRefused));
+
+        let elapsed = tokio::time::timeout(Duration::ZERO, std::future::pending::<()>())
+            .await
+            .unwrap_err();
+        assert!(is_transient_connect_error(
+            &anyhow::Error::new(elapsed).context("Connecting timed out")
+        ));
+        assert!(!is_transient_connect_error(&anyhow::anyhow!(
+            "Tor client is not enabled"
+        )));
     }
 }
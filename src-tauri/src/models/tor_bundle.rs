@@ -0,0 +1,407 @@
+//! Snapshots of Arti's directory cache, and optionally of its guards, that a
+//! device can hand to another one so that it bootstraps without downloading
+//! the whole directory, which takes minutes on slow or censored networks.
+
+use anyhow::Result;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use rusqlite::{Connection, OpenFlags};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use tempfile::TempDir;
+
+use crate::models::location::millis_since_epoch;
+use crate::models::tor_status::DirectoryFreshness;
+
+const BUNDLE_MAGIC: &[u8; 8] = b"ASHTOR\x00\x01";
+const MANIFEST_ENTRY: &str = "manifest.json";
+// Layout of Arti's cache and state directories, which are both the Tor
+// directory of the app.
+const DATABASE_ENTRY: &str = "dir.sqlite3";
+const BLOBS_DIR: &str = "dir_blobs";
+const GUARDS_ENTRY: &str = "state/guards.json";
+const STAGING_PREFIX: &str = "tor-bundle-";
+const MAX_ENTRY_SIZE: u64 = 512 * 1024 * 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TorStateBundle {
+    /// Milliseconds since the Unix epoch.
+    pub created: u64,
+    /// Lifetime of the newest consensus in the bundle.
+    pub directory: DirectoryFreshness,
+    pub includes_guards: bool,
+}
+
+/// Writes the directory cache of `tor_dir` to `path`. `directory` is the
+/// lifetime of the consensus the client uses, as Arti's cache is opaque to
+/// the app. Guards are only included when asked for, since devices sharing
+/// guards can be linked.
+pub fn export_bundle(
+    tor_dir: &Path,
+    path: &Path,
+    directory: DirectoryFreshness,
+    include_guards: bool,
+) -> Result<TorStateBundle> {
+    if !directory.valid {
+        return Err(anyhow::anyhow!(
+            "The Tor directory is expired, bootstrap first"
+        ));
+    }
+    let staging = Staging::create(tor_dir)?;
+
+    // The client may be writing to the database, so a consistent copy is
+    // taken rather than copying the file.
+    let snapshot = staging.path().join(DATABASE_ENTRY);
+    let source = Connection::open_with_flags(
+        tor_dir.join(DATABASE_ENTRY),
+        OpenFlags::SQLITE_OPEN_READ_ONLY,
+    )
+    .map_err(|e| anyhow::anyhow!("No Tor directory to export, bootstrap first: {}", e))?;
+    source.execute(
+        "VACUUM INTO ?1",
+        [snapshot
+            .to_str()
+            .ok_or_else(|| anyhow::anyhow!("Invalid staging path"))?],
+    )?;
+
+    let guards = tor_dir.join(GUARDS_ENTRY);
+    let bundle = TorStateBundle {
+        created: millis_since_epoch(SystemTime::now()),
+        directory,
+        includes_guards: include_guards && guards.is_file(),
+    };
+
+    let mut writer = GzEncoder::new(BufWriter::new(File::create(path)?), Compression::default());
+    writer.write_all(BUNDLE_MAGIC)?;
+    let manifest = serde_json::to_vec(&bundle)?;
+    write_entry(
+        &mut writer,
+        MANIFEST_ENTRY,
+        manifest.len() as u64,
+        &mut manifest.as_slice(),
+    )?;
+    write_file_entry(&mut writer, DATABASE_ENTRY, &snapshot)?;
+    for blob in blob_names(&tor_dir.join(BLOBS_DIR))? {
+        let blob_path = tor_dir.join(BLOBS_DIR).join(&blob);
+        write_file_entry(&mut writer, &format!("{}/{}", BLOBS_DIR, blob), &blob_path)?;
+    }
+    if bundle.includes_guards {
+        write_file_entry(&mut writer, GUARDS_ENTRY, &guards)?;
+    }
+    writer.finish()?.flush()?;
+
+    Ok(bundle)
+}
+
+/// A bundle unpacked next to the Tor directory and checked, waiting to be
+/// installed. The unpacked files are removed when it is dropped.
+pub struct StagedBundle {
+    pub bundle: TorStateBundle,
+    staging: Staging,
+}
+
+impl StagedBundle {
+    /// Unpacks the bundle at `path` and checks that its consensus is still
+    /// valid and newer than `local`, the one the client uses if it runs.
+    pub fn unpack(tor_dir: &Path, path: &Path, local: Option<&DirectoryFreshness>) -> Result<Self> {
+        let staging = Staging::create(tor_dir)?;
+
+        let mut reader = GzDecoder::new(BufReader::new(File::open(path)?));
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != BUNDLE_MAGIC {
+            return Err(anyhow::anyhow!("Not a Tor state bundle"));
+        }
+
+        let mut manifest: Option<TorStateBundle> = None;
+        while let Some(name) = read_entry_name(&mut reader)? {
+            let length = read_u64(&mut reader)?;
+            if length > MAX_ENTRY_SIZE {
+                return Err(anyhow::anyhow!("Bundle entry {} is too large", name));
+            }
+            let mut data = (&mut reader).take(length);
+
+            if name == MANIFEST_ENTRY {
+                let mut json = Vec::new();
+                data.read_to_end(&mut json)?;
+                manifest = Some(serde_json::from_slice(&json)?);
+                continue;
+            }
+            if !is_known_entry(&name) {
+                return Err(anyhow::anyhow!("Unexpected bundle entry {}", name));
+            }
+
+            let entry_path = staging.path().join(&name);
+            if let Some(parent) = entry_path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            let copied = std::io::copy(&mut data, &mut File::create(entry_path)?)?;
+            if copied != length {
+                return Err(anyhow::anyhow!("Bundle is truncated"));
+            }
+        }
+
+        let manifest = manifest.ok_or_else(|| anyhow::anyhow!("Bundle has no manifest"))?;
+        if !staging.path().join(DATABASE_ENTRY).is_file() {
+            return Err(anyhow::anyhow!("Bundle has no Tor directory"));
+        }
+
+        // The lifetime in the manifest is only used to skip useless imports.
+        // Arti checks the signatures of the documents when it loads them,
+        // and downloads a new directory if they do not hold.
+        let now = millis_since_epoch(SystemTime::now());
+        let directory = DirectoryFreshness {
+            fresh: now < manifest.directory.fresh_until,
+            valid: manifest.directory.valid_after <= now && now < manifest.directory.valid_until,
+            ..manifest.directory
+        };
+        if !directory.valid {
+            return Err(anyhow::anyhow!("The consensus in the bundle is expired"));
+        }
+        if local.is_some_and(|local| local.valid && local.valid_after >= directory.valid_after) {
+            return Err(anyhow::anyhow!(
+                "The Tor directory of this device is already as recent"
+            ));
+        }
+
+        let bundle = TorStateBundle {
+            created: manifest.created,
+            directory,
+            includes_guards: staging.path().join(GUARDS_ENTRY).is_file(),
+        };
+        Ok(Self { bundle, staging })
+    }
+
+    /// Replaces the directory cache, and the guards if the bundle has them.
+    /// The client must have been shut down.
+    pub fn install(self, tor_dir: &Path) -> Result<()> {
+        let database = tor_dir.join(DATABASE_ENTRY);
+        for suffix in ["-wal", "-shm", "-journal"] {
+            let path = PathBuf::from(format!("{}{}", database.display(), suffix));
+            if path.exists() {
+                std::fs::remove_file(path)?;
+            }
+        }
+        std::fs::rename(self.staging.path().join(DATABASE_ENTRY), database)?;
+
+        let blobs = tor_dir.join(BLOBS_DIR);
+        if blobs.exists() {
+            std::fs::remove_dir_all(&blobs)?;
+        }
+        let staged_blobs = self.staging.path().join(BLOBS_DIR);
+        if staged_blobs.is_dir() {
+            std::fs::rename(staged_blobs, blobs)?;
+        }
+
+        if self.bundle.includes_guards {
+            let guards = tor_dir.join(GUARDS_ENTRY);
+            if let Some(parent) = guards.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::rename(self.staging.path().join(GUARDS_ENTRY), guards)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Scratch directory of its own next to the Tor directory, so that
+/// installing is a rename on the same file system. It is removed when
+/// dropped.
+struct Staging {
+    directory: TempDir,
+}
+
+impl Staging {
+    fn create(tor_dir: &Path) -> Result<Self> {
+        let parent = tor_dir
+            .parent()
+            .ok_or_else(|| anyhow::anyhow!("Invalid Tor directory"))?;
+        std::fs::create_dir_all(parent)?;
+        let directory = tempfile::Builder::new()
+            .prefix(STAGING_PREFIX)
+            .tempdir_in(parent)?;
+        Ok(Self { directory })
+    }
+
+    fn path(&self) -> &Path {
+        self.directory.path()
+    }
+}
+
+/// Lists the files of Arti's blob directory, which holds the documents too
+/// large for its database.
+fn blob_names(blobs_dir: &Path) -> Result<Vec<String>> {
+    let entries = match std::fs::read_dir(blobs_dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut names = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        if !entry.file_type()?.is_file() {
+            continue;
+        }
+        if let Some(name) = entry.file_name().to_str().filter(|name| is_blob_name(name)) {
+            names.push(name.to_string());
+        }
+    }
+    Ok(names)
+}
+
+fn is_known_entry(name: &str) -> bool {
+    name == DATABASE_ENTRY
+        || name == GUARDS_ENTRY
+        || name
+            .strip_prefix(BLOBS_DIR)
+            .and_then(|name| name.strip_prefix('/'))
+            .is_some_and(is_blob_name)
+}
+
+// Keeps entries from escaping the staging directory.
+fn is_blob_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.')
+}
+
+fn write_file_entry<W: Write>(writer: &mut W, name: &str, path: &Path) -> Result<()> {
+    let mut file = File::open(path)?;
+    let length = file.metadata()?.len();
+    write_entry(writer, name, length, &mut file)
+}
+
+/// Writes an entry as its name length (u16), name, data length (u64) and
+/// data, all big-endian.
+fn write_entry<W: Write, R: Read>(
+    writer: &mut W,
+    name: &str,
+    length: u64,
+    data: &mut R,
+) -> Result<()> {
+    writer.write_all(&(name.len() as u16).to_be_bytes())?;
+    writer.write_all(name.as_bytes())?;
+    writer.write_all(&length.to_be_bytes())?;
+    let copied = std::io::copy(&mut data.take(length), writer)?;
+    if copied != length {
+        return Err(anyhow::anyhow!("{} changed while being exported", name));
+    }
+    Ok(())
+}
+
+/// Returns `None` at the end of the bundle.
+fn read_entry_name<R: Read>(reader: &mut R) -> Result<Option<String>> {
+    let mut length = [0u8; 2];
+    match reader.read_exact(&mut length) {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    }
+    let mut name = vec![0; u16::from_be_bytes(length) as usize];
+    reader.read_exact(&mut name)?;
+    Ok(Some(String::from_utf8(name)?))
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> Result<u64> {
+    let mut bytes = [0u8; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_be_bytes(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR: u64 = 3_600_000;
+
+    fn directory(valid_after: u64) -> DirectoryFreshness {
+        DirectoryFreshness {
+            valid_after,
+            fresh_until: valid_after + HOUR,
+            valid_until: valid_after + 3 * HOUR,
+            fresh: true,
+            valid: true,
+        }
+    }
+
+    fn tor_dir(root: &Path) -> PathBuf {
+        let tor_dir = root.join("tor");
+        std::fs::create_dir_all(tor_dir.join(BLOBS_DIR)).unwrap();
+        Connection::open(tor_dir.join(DATABASE_ENTRY))
+            .unwrap()
+            .execute_batch("CREATE TABLE Documents (contents TEXT);")
+            .unwrap();
+        std::fs::write(tor_dir.join(BLOBS_DIR).join("con_abc"), b"consensus").unwrap();
+        std::fs::write(tor_dir.join(BLOBS_DIR).join(".hidden"), b"skipped").unwrap();
+        tor_dir
+    }
+
+    #[test]
+    fn installs_an_exported_bundle() {
+        let now = millis_since_epoch(SystemTime::now());
+        let source = tempfile::tempdir().unwrap();
+        let target = tempfile::tempdir().unwrap();
+        let bundle_path = source.path().join("state.ashtor");
+
+        let exported = export_bundle(
+            &tor_dir(source.path()),
+            &bundle_path,
+            directory(now - HOUR),
+            false,
+        )
+        .unwrap();
+        assert!(!exported.includes_guards);
+
+        let target_dir = tor_dir(target.path());
+        let staged = StagedBundle::unpack(&target_dir, &bundle_path, None).unwrap();
+        assert_eq!(staged.bundle.directory.valid_after, now - HOUR);
+        staged.install(&target_dir).unwrap();
+
+        assert_eq!(
+            std::fs::read(target_dir.join(BLOBS_DIR).join("con_abc")).unwrap(),
+            b"consensus"
+        );
+        assert!(!target_dir.join(BLOBS_DIR).join(".hidden").exists());
+        // Only the Tor directory is left, the staging directory is gone.
+        assert_eq!(std::fs::read_dir(target.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn skips_bundles_older_than_the_local_directory() {
+        let now = millis_since_epoch(SystemTime::now());
+        let source = tempfile::tempdir().unwrap();
+        let bundle_path = source.path().join("state.ashtor");
+        let tor_dir = tor_dir(source.path());
+
+        export_bundle(&tor_dir, &bundle_path, directory(now - HOUR), false).unwrap();
+
+        assert!(StagedBundle::unpack(&tor_dir, &bundle_path, Some(&directory(now))).is_err());
+        assert!(
+            StagedBundle::unpack(&tor_dir, &bundle_path, Some(&directory(now - 2 * HOUR))).is_ok()
+        );
+    }
+
+    #[test]
+    fn stages_in_directories_of_their_own() {
+        let root = tempfile::tempdir().unwrap();
+        let tor_dir = root.path().join("tor");
+
+        let first = Staging::create(&tor_dir).unwrap();
+        let second = Staging::create(&tor_dir).unwrap();
+        assert_ne!(first.path(), second.path());
+
+        let first_path = first.path().to_path_buf();
+        drop(first);
+        assert!(!first_path.exists());
+        assert!(second.path().is_dir());
+    }
+}
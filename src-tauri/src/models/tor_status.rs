@@ -0,0 +1,185 @@
+use anyhow::Result;
+use arti_client::status::BlockageKind as ArtiBlockageKind;
+use arti_client::TorClient;
+use serde::{Deserialize, Serialize};
+use std::time::SystemTime;
+use tor_linkspec::{HasAddrs, HasRelayIds};
+use tor_netdir::{NetDirProvider, Timeliness};
+use tor_rtcompat::PreferredRuntime;
+
+use crate::models::location::millis_since_epoch;
+use crate::models::tor::BootstrapStatus;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TorPhase {
+    Stopped,
+    Bootstrapping,
+    Ready,
+    Blocked,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BlockageKind {
+    Offline,
+    Filtering,
+    CantReachTor,
+    ClockSkewed,
+    CantBootstrap,
+    Other,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Blockage {
+    pub kind: BlockageKind,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClockSkew {
+    /// Seconds our clock is ahead of the relays, negative when behind.
+    pub seconds: f64,
+    /// Whether the skew is large enough to prevent bootstrapping.
+    pub noteworthy: bool,
+    pub description: String,
+}
+
+/// Lifetime of the consensus in use, in milliseconds since the Unix epoch.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DirectoryFreshness {
+    pub valid_after: u64,
+    pub fresh_until: u64,
+    pub valid_until: u64,
+    pub fresh: bool,
+    pub valid: bool,
+}
+
+/// Guard the client goes through, as the first hop of its directory
+/// circuit.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GuardInfo {
+    /// Nickname from the consensus, `None` for bridges, which it does not
+    /// list.
+    pub nickname: Option<String>,
+    pub ed25519_id: Option<String>,
+    pub rsa_id: Option<String>,
+    pub addresses: Vec<String>,
+}
+
+impl GuardInfo {
+    /// Reads the guard off the directory circuit, which is launched if the
+    /// client has none open.
+    pub async fn probe(client: &TorClient<PreferredRuntime>) -> Result<Self> {
+        let netdir = client.dirmgr().timely_netdir()?;
+        let tunnel = client
+            .circmgr()
+            .get_or_launch_dir((&*netdir).into())
+            .await?;
+        let guard = tunnel.first_hop();
+
+        Ok(Self {
+            nickname: netdir
+                .by_ids(&guard)
+                .map(|relay| relay.rs().nickname().to_string()),
+            ed25519_id: guard.ed_identity().map(ToString::to_string),
+            rsa_id: guard.rsa_identity().map(ToString::to_string),
+            addresses: guard.addrs().iter().map(ToString::to_string).collect(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TorStatus {
+    pub phase: TorPhase,
+    pub bootstrap: Option<BootstrapStatus>,
+    pub blockage: Option<Blockage>,
+    pub clock_skew: Option<ClockSkew>,
+    pub directory: Option<DirectoryFreshness>,
+    pub guard: Option<GuardInfo>,
+    pub last_error: Option<String>,
+}
+
+impl TorStatus {
+    pub fn collect(
+        client: Option<&TorClient<PreferredRuntime>>,
+        guard: Option<GuardInfo>,
+        last_error: Option<String>,
+    ) -> Self {
+        let client = match client {
+            Some(client) => client,
+            None => {
+                return Self {
+                    phase: TorPhase::Stopped,
+                    bootstrap: None,
+                    blockage: None,
+                    clock_skew: None,
+                    directory: None,
+                    guard: None,
+                    last_error,
+                }
+            }
+        };
+
+        let status = client.bootstrap_status();
+        let blockage = status.blocked().map(|blockage| Blockage {
+            kind: match blockage.kind() {
+                ArtiBlockageKind::Offline => BlockageKind::Offline,
+                ArtiBlockageKind::Filtering => BlockageKind::Filtering,
+                ArtiBlockageKind::CantReachTor => BlockageKind::CantReachTor,
+                ArtiBlockageKind::ClockSkewed => BlockageKind::ClockSkewed,
+                ArtiBlockageKind::CantBootstrap => BlockageKind::CantBootstrap,
+                _ => BlockageKind::Other,
+            },
+            message: blockage.to_string(),
+        });
+
+        let phase = if status.ready_for_traffic() {
+            TorPhase::Ready
+        } else if blockage.is_some() {
+            TorPhase::Blocked
+        } else {
+            TorPhase::Bootstrapping
+        };
+
+        let clock_skew = client
+            .circmgr()
+            .skew_events()
+            .get()
+            .map(|estimate| ClockSkew {
+                seconds: estimate.skew().as_secs_f64(),
+                noteworthy: estimate.noteworthy(),
+                description: estimate.to_string(),
+            });
+
+        let directory = client
+            .dirmgr()
+            .netdir(Timeliness::Unchecked)
+            .ok()
+            .map(|netdir| {
+                let lifetime = netdir.lifetime();
+                let now = SystemTime::now();
+                DirectoryFreshness {
+                    valid_after: millis_since_epoch(lifetime.valid_after()),
+                    fresh_until: millis_since_epoch(lifetime.fresh_until()),
+                    valid_until: millis_since_epoch(lifetime.valid_until()),
+                    fresh: now < lifetime.fresh_until(),
+                    valid: lifetime.valid_at(now),
+                }
+            });
+
+        Self {
+            phase,
+            bootstrap: Some(status.into()),
+            blockage,
+            clock_skew,
+            directory,
+            guard,
+            last_error,
+        }
+    }
+}
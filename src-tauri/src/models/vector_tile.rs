@@ -0,0 +1,346 @@
+//! Minimal Mapbox Vector Tile decoder, only reading layer names and feature
+//! geometries.
+
+use anyhow::Result;
+use std::f64::consts::PI;
+
+use crate::models::geo::Coordinate;
+
+const DEFAULT_EXTENT: u32 = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GeometryType {
+    Unknown,
+    Point,
+    LineString,
+    Polygon,
+}
+
+#[derive(Debug, Clone)]
+pub struct Feature {
+    pub geometry_type: GeometryType,
+    /// Paths in tile coordinates, one per `MoveTo`.
+    pub paths: Vec<Vec<(i32, i32)>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Layer {
+    pub name: String,
+    pub extent: u32,
+    pub features: Vec<Feature>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TileAddress {
+    pub z: u8,
+    pub x: u32,
+    pub y: u32,
+}
+
+impl TileAddress {
+    pub fn containing(coordinate: &Coordinate, z: u8) -> Self {
+        let tiles = f64::from(1u32 << z);
+        let latitude = coordinate
+            .latitude
+            .clamp(-85.051_128, 85.051_128)
+            .to_radians();
+        let x = ((coordinate.longitude + 180.0) / 360.0 * tiles).floor();
+        let y = ((1.0 - latitude.tan().asinh() / PI) / 2.0 * tiles).floor();
+        Self {
+            z,
+            x: x.clamp(0.0, tiles - 1.0) as u32,
+            y: y.clamp(0.0, tiles - 1.0) as u32,
+        }
+    }
+
+    pub fn coordinate_at(&self, point: (i32, i32), extent: u32) -> Coordinate {
+        let tiles = f64::from(1u32 << self.z);
+        let x = (f64::from(self.x) + f64::from(point.0) / f64::from(extent)) / tiles;
+        let y = (f64::from(self.y) + f64::from(point.1) / f64::from(extent)) / tiles;
+        Coordinate::new(
+            (PI * (1.0 - 2.0 * y)).sinh().atan().to_degrees(),
+            x * 360.0 - 180.0,
+        )
+    }
+}
+
+struct ProtoReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> ProtoReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, position: 0 }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.position >= self.data.len()
+    }
+
+    fn varint(&mut self) -> Result<u64> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = *self
+                .data
+                .get(self.position)
+                .ok_or_else(|| anyhow::anyhow!("Truncated varint in vector tile"))?;
+            self.position += 1;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(anyhow::anyhow!("Invalid varint in vector tile"))
+    }
+
+    fn bytes(&mut self) -> Result<&'a [u8]> {
+        let length = self.varint()? as usize;
+        let end = self
+            .position
+            .checked_add(length)
+            .filter(|end| *end <= self.data.len())
+            .ok_or_else(|| anyhow::anyhow!("Truncated field in vector tile"))?;
+        let bytes = &self.data[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    /// Returns the next field number and wire type.
+    fn key(&mut self) -> Result<(u64, u64)> {
+        let key = self.varint()?;
+        Ok((key >> 3, key & 0x7))
+    }
+
+    fn skip(&mut self, wire_type: u64) -> Result<()> {
+        match wire_type {
+            0 => {
+                self.varint()?;
+            }
+            1 => self.position += 8,
+            2 => {
+                self.bytes()?;
+            }
+            5 => self.position += 4,
+            _ => return Err(anyhow::anyhow!("Unsupported wire type {}", wire_type)),
+        }
+        Ok(())
+    }
+
+    fn packed_u32(&mut self) -> Result<Vec<u32>> {
+        let mut packed = ProtoReader::new(self.bytes()?);
+        let mut values = Vec::new();
+        while !packed.is_empty() {
+            values.push(packed.varint()? as u32);
+        }
+        Ok(values)
+    }
+}
+
+pub fn decode_layers(data: &[u8]) -> Result<Vec<Layer>> {
+    let mut reader = ProtoReader::new(data);
+    let mut layers = Vec::new();
+
+    while !reader.is_empty() {
+        match reader.key()? {
+            (3, 2) => layers.push(decode_layer(reader.bytes()?)?),
+            (_, wire_type) => reader.skip(wire_type)?,
+        }
+    }
+
+    Ok(layers)
+}
+
+fn decode_layer(data: &[u8]) -> Result<Layer> {
+    let mut reader = ProtoReader::new(data);
+    let mut layer = Layer {
+        name: String::new(),
+        extent: DEFAULT_EXTENT,
+        features: Vec::new(),
+    };
+
+    while !reader.is_empty() {
+        match reader.key()? {
+            (1, 2) => layer.name = String::from_utf8_lossy(reader.bytes()?).into_owned(),
+            (2, 2) => layer.features.push(decode_feature(reader.bytes()?)?),
+            (5, 0) => layer.extent = reader.varint()? as u32,
+            (_, wire_type) => reader.skip(wire_type)?,
+        }
+    }
+
+    Ok(layer)
+}
+
+fn decode_feature(data: &[u8]) -> Result<Feature> {
+    let mut reader = ProtoReader::new(data);
+    let mut geometry_type = GeometryType::Unknown;
+    let mut commands = Vec::new();
+
+    while !reader.is_empty() {
+        match reader.key()? {
+            (3, 0) => {
+                geometry_type = match reader.varint()? {
+                    1 => GeometryType::Point,
+                    2 => GeometryType::LineString,
+                    3 => GeometryType::Polygon,
+                    _ => GeometryType::Unknown,
+                }
+            }
+            (4, 2) => commands = reader.packed_u32()?,
+            (_, wire_type) => reader.skip(wire_type)?,
+        }
+    }
+
+    Ok(Feature {
+        geometry_type,
+        paths: decode_geometry(&commands),
+    })
+}
+
+fn decode_geometry(commands: &[u32]) -> Vec<Vec<(i32, i32)>> {
+    let zigzag = |value: u32| ((value >> 1) as i32) ^ -((value & 1) as i32);
+
+    let mut paths: Vec<Vec<(i32, i32)>> = Vec::new();
+    let mut cursor = (0i32, 0i32);
+    let mut index = 0;
+
+    while index < commands.len() {
+        let command = commands[index] & 0x7;
+        let count = (commands[index] >> 3) as usize;
+        index += 1;
+
+        match command {
+            // MoveTo and LineTo
+            1 | 2 => {
+                for _ in 0..count {
+                    let (Some(dx), Some(dy)) = (commands.get(index), commands.get(index + 1))
+                    else {
+                        return paths;
+                    };
+                    index += 2;
+                    cursor = (
+                        cursor.0.wrapping_add(zigzag(*dx)),
+                        cursor.1.wrapping_add(zigzag(*dy)),
+                    );
+
+                    if command == 1 || paths.is_empty() {
+                        paths.push(vec![cursor]);
+                    } else if let Some(path) = paths.last_mut() {
+                        path.push(cursor);
+                    }
+                }
+            }
+            // ClosePath
+            7 => {
+                if let Some(path) = paths.last_mut() {
+                    if let Some(first) = path.first().copied() {
+                        path.push(first);
+                    }
+                }
+            }
+            _ => return paths,
+        }
+    }
+
+    paths
+}
+
+/// Encoder of the few tiles the tests need.
+#[cfg(test)]
+pub(crate) mod test_tiles {
+    fn varint(out: &mut Vec<u8>, mut value: u64) {
+        while value >= 0x80 {
+            out.push((value as u8) | 0x80);
+            value >>= 7;
+        }
+        out.push(value as u8);
+    }
+
+    fn field(out: &mut Vec<u8>, number: u64, bytes: &[u8]) {
+        varint(out, (number << 3) | 2);
+        varint(out, bytes.len() as u64);
+        out.extend_from_slice(bytes);
+    }
+
+    /// Encodes a tile with a single layer of line strings.
+    pub fn line_layer(name: &str, extent: u32, lines: &[Vec<(i32, i32)>]) -> Vec<u8> {
+        let zigzag = |value: i32| ((value << 1) ^ (value >> 31)) as u32 as u64;
+
+        let mut layer = Vec::new();
+        // Version 2, a varint field like the type and extent below.
+        varint(&mut layer, 15 << 3);
+        varint(&mut layer, 2);
+        field(&mut layer, 1, name.as_bytes());
+        for line in lines {
+            let mut geometry = Vec::new();
+            let mut cursor = (0, 0);
+            for (position, point) in line.iter().enumerate() {
+                if position == 0 {
+                    varint(&mut geometry, 1 | (1 << 3));
+                } else if position == 1 {
+                    varint(&mut geometry, 2 | (((line.len() - 1) as u64) << 3));
+                }
+                varint(&mut geometry, zigzag(point.0 - cursor.0));
+                varint(&mut geometry, zigzag(point.1 - cursor.1));
+                cursor = *point;
+            }
+
+            let mut feature = Vec::new();
+            varint(&mut feature, 3 << 3);
+            varint(&mut feature, 2);
+            field(&mut feature, 4, &geometry);
+            field(&mut layer, 2, &feature);
+        }
+        varint(&mut layer, 5 << 3);
+        varint(&mut layer, u64::from(extent));
+
+        let mut tile = Vec::new();
+        field(&mut tile, 3, &layer);
+        tile
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_line_strings() {
+        let data = test_tiles::line_layer("roads", 4096, &[vec![(10, 20), (110, 20), (110, -5)]]);
+        let layers = decode_layers(&data).unwrap();
+
+        assert_eq!(layers.len(), 1);
+        assert_eq!(layers[0].name, "roads");
+        assert_eq!(layers[0].extent, 4096);
+        assert_eq!(
+            layers[0].features[0].geometry_type,
+            GeometryType::LineString
+        );
+        assert_eq!(
+            layers[0].features[0].paths,
+            vec![vec![(10, 20), (110, 20), (110, -5)]]
+        );
+    }
+
+    #[test]
+    fn places_tile_points_on_the_map() {
+        let address = TileAddress::containing(&Coordinate::new(48.8566, 2.3522), 14);
+        let corner = address.coordinate_at((0, 0), 4096);
+        let next = TileAddress {
+            x: address.x + 1,
+            ..address
+        };
+
+        assert_eq!(TileAddress::containing(&corner, 14), address);
+        assert_eq!(
+            address.coordinate_at((4096, 0), 4096),
+            next.coordinate_at((0, 0), 4096)
+        );
+    }
+
+    #[test]
+    fn rejects_truncated_tiles() {
+        let data = test_tiles::line_layer("roads", 4096, &[vec![(0, 0), (1, 1)]]);
+        assert!(decode_layers(&data[..data.len() - 3]).is_err());
+    }
+}
@@ -4,6 +4,7 @@ import { useEffect, useRef } from 'react';
 import GroupInviteDialog from './components/GroupInviteDialog.tsx';
 import GroupJoinDialog from './components/GroupJoinDialog.tsx';
 import NewGroupDialog from './components/NewGroupDialog.tsx';
+import NetworkSettingsDialog from './components/network/NetworkSettingsDialog.tsx';
 import NetworkStatusDialog from './components/network/NetworkStatusDialog.tsx';
 import { NetworkStatus } from './interfaces/networkStatus.ts';
 import { $storeSetupDone } from './stores/jsonStore.ts';
@@ -33,6 +34,7 @@ export default function App() {
     return (
         <div className="size-full">
             <NetworkStatusDialog />
+            <NetworkSettingsDialog />
             <NewGroupDialog />
             <GroupInviteDialog />
             <GroupJoinDialog />
@@ -5,11 +5,15 @@ import { useStore } from '@nanostores/react';
 import { layers, namedFlavor } from '@protomaps/basemaps';
 import { convertFileSrc } from '@tauri-apps/api/core';
 import { appDataDir, join } from '@tauri-apps/api/path';
-import type { Marker } from '../interfaces/group';
+import type { Marker, MarkerKind } from '../interfaces/group';
 import type { Locality } from '../interfaces/localitysrv.ts';
-import { sendMarkerMessage } from '../service/chatService';
-import { $storeDeviceId } from '../stores/jsonStore';
+import {
+    listenForPublications,
+    publishMarker,
+} from '../service/chatService';
+import { $storeDeviceId, $storeSelectedGroup } from '../stores/jsonStore';
 import { $isMarkerNameDialogOpened } from '../stores/mainViewStore';
+import { addGroupMarker } from '../stores/markerStore';
 import { createMarkerWithName } from '../utils/mapUtils';
 import { createPMTilesProtocol } from '../utils/pmtiles-protocol.ts';
 import MarkerComponent from './MarkerComponent';
@@ -23,6 +27,7 @@ const MapComponent = ({ locality }: MapComponentProps) => {
     const mapContainer = useRef<HTMLDivElement>(null);
     const map = useRef<maplibregl.Map | null>(null);
     const deviceId = useStore($storeDeviceId);
+    const selectedGroup = useStore($storeSelectedGroup);
     const [pendingMarker, setPendingMarker] = useState<{
         lat: number;
         lng: number;
@@ -61,7 +66,7 @@ const MapComponent = ({ locality }: MapComponentProps) => {
     }, []);
 
     const handleMarkerNameSubmit = useCallback(
-        (name: string) => {
+        (name: string, kind: MarkerKind) => {
             if (!pendingMarker || !deviceId) return;
 
             const { lat, lng } = pendingMarker;
@@ -69,15 +74,32 @@ const MapComponent = ({ locality }: MapComponentProps) => {
                 latitude: lat,
                 longitude: lng,
                 name: name,
+                kind,
             };
 
-            sendMarkerMessage(markerData, deviceId);
+            if (selectedGroup) {
+                publishMarker(selectedGroup.id, markerData).catch((error) =>
+                    console.error('Failed to publish marker:', error),
+                );
+                addGroupMarker(selectedGroup.id, markerData).catch((error) =>
+                    console.error('Failed to sync markers:', error),
+                );
+            }
             addMarkerToMap(lat, lng, name);
             setPendingMarker(null);
         },
-        [pendingMarker, deviceId, addMarkerToMap],
+        [pendingMarker, deviceId, selectedGroup, addMarkerToMap],
     );
 
+    useEffect(() => {
+        if (!deviceId) return;
+
+        const unlisten = listenForPublications(deviceId);
+        return () => {
+            unlisten.then((unlisten) => unlisten());
+        };
+    }, [deviceId]);
+
     useEffect(() => {
         if (!mapContainer.current || !locality) return;
 
@@ -4,7 +4,9 @@ import { effect } from 'nanostores';
 import protobuf from 'protobufjs';
 import { useEffect, useRef } from 'react';
 import type { Marker } from '../interfaces/group';
+import { newMarkerProtoBuilder } from '../service/chatService';
 import { $storeSelectedGroup } from '../stores/jsonStore';
+import { addGroupMarker } from '../stores/markerStore';
 import { $wakuChatChannel } from '../stores/wakuStore';
 import { createMarkerWithName } from '../utils/mapUtils';
 
@@ -35,10 +37,7 @@ const MarkerComponent = ({ map, clearLocalMarkers }: MarkerComponentProps) => {
                     .add(new protobuf.Field('type', 3, 'string'))
                     .add(new protobuf.Field('content', 4, 'bytes'));
 
-                const MarkerProto = new protobuf.Type('Marker')
-                    .add(new protobuf.Field('latitude', 1, 'float'))
-                    .add(new protobuf.Field('longitude', 2, 'float'))
-                    .add(new protobuf.Field('name', 3, 'string'));
+                const MarkerProto = newMarkerProtoBuilder();
 
                 const root = new protobuf.Root();
                 root.add(GroupMessage);
@@ -57,10 +56,21 @@ const MarkerComponent = ({ map, clearLocalMarkers }: MarkerComponentProps) => {
                     latitude: contentData.latitude,
                     longitude: contentData.longitude,
                     name: contentData.name || '',
+                    kind: contentData.kind || undefined,
+                    radius: contentData.radius || undefined,
+                    penalty: contentData.penalty || undefined,
                 };
 
                 console.log('Received marker:', markerData);
 
+                const selectedGroup = $storeSelectedGroup.get();
+                if (selectedGroup) {
+                    addGroupMarker(selectedGroup.id, markerData).catch(
+                        (error) =>
+                            console.error('Failed to sync markers:', error),
+                    );
+                }
+
                 const { marker, popup } = createMarkerWithName(
                     map,
                     markerData.latitude,
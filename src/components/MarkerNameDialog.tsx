@@ -8,12 +8,27 @@ import {
     DialogHeader,
     DialogTitle,
     Input,
+    Select,
+    SelectContent,
+    SelectItem,
+    SelectTrigger,
+    SelectValue,
 } from '@nipsysdev/lsd-react';
 import { useState } from 'react';
+import type { MarkerKind } from '../interfaces/group';
 import { $isMarkerNameDialogOpened } from '../stores/mainViewStore';
 
+const markerKindLabels: Record<MarkerKind, string> = {
+    point: 'Point',
+    meeting_point: 'Meeting point',
+    safe_zone: 'Safe zone',
+    police_line: 'Police line',
+    closure: 'Closure',
+    threat: 'Threat',
+};
+
 interface MarkerNameDialogProps {
-    onMarkerNameSubmit: (markerName: string) => void;
+    onMarkerNameSubmit: (markerName: string, markerKind: MarkerKind) => void;
 }
 
 export default function MarkerNameDialog({
@@ -21,11 +36,13 @@ export default function MarkerNameDialog({
 }: MarkerNameDialogProps) {
     const isDialogOpened = useStore($isMarkerNameDialogOpened);
     const [markerName, setMarkerName] = useState('');
+    const [markerKind, setMarkerKind] = useState<MarkerKind>('point');
 
     function handleSubmit() {
         if (!markerName) return;
-        onMarkerNameSubmit(markerName);
+        onMarkerNameSubmit(markerName, markerKind);
         setMarkerName('');
+        setMarkerKind('point');
         $isMarkerNameDialogOpened.set(false);
     }
 
@@ -50,6 +67,24 @@ export default function MarkerNameDialog({
                     autoFocus
                 />
 
+                <Select
+                    value={markerKind}
+                    onValueChange={(value) => setMarkerKind(value as MarkerKind)}
+                >
+                    <SelectTrigger className="w-full">
+                        <SelectValue placeholder="Marker kind" />
+                    </SelectTrigger>
+                    <SelectContent>
+                        {Object.entries(markerKindLabels).map(
+                            ([kind, label]) => (
+                                <SelectItem key={kind} value={kind}>
+                                    {label}
+                                </SelectItem>
+                            ),
+                        )}
+                    </SelectContent>
+                </Select>
+
                 <DialogFooter>
                     <div className="text-right mt-4">
                         <Button disabled={!markerName} onClick={handleSubmit}>
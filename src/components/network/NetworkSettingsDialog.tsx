@@ -0,0 +1,145 @@
+import { useStore } from '@nanostores/react';
+import {
+    Button,
+    Checkbox,
+    Dialog,
+    DialogContent,
+    DialogDescription,
+    DialogFooter,
+    DialogHeader,
+    DialogTitle,
+    Input,
+    Label,
+    Typography,
+} from '@nipsysdev/lsd-react';
+import { useEffect, useState } from 'react';
+import {
+    $isNetworkSettingsDialogOpened,
+    $localitysrvUrl,
+    $networkSettings,
+    saveLocalitysrvUrl,
+    saveNetworkSettings,
+} from '../../stores/networkStore';
+
+const KB = 1024;
+const MB = 1024 * 1024;
+
+export default function NetworkSettingsDialog() {
+    const isDialogOpened = useStore($isNetworkSettingsDialogOpened);
+    const networkSettings = useStore($networkSettings);
+    const storeLocalitysrvUrl = useStore($localitysrvUrl);
+    const [rateLimit, setRateLimit] = useState('');
+    const [metered, setMetered] = useState(false);
+    const [downloadLimit, setDownloadLimit] = useState('');
+    const [localitysrvUrl, setLocalitysrvUrl] = useState('');
+    const [error, setError] = useState<string | null>(null);
+
+    useEffect(() => {
+        if (!isDialogOpened || !networkSettings) return;
+        setRateLimit(
+            networkSettings.maxBytesPerSecond === null
+                ? ''
+                : `${Math.round(networkSettings.maxBytesPerSecond / KB)}`,
+        );
+        setMetered(networkSettings.metered);
+        setDownloadLimit(
+            `${Math.round(networkSettings.meteredDownloadLimit / MB)}`,
+        );
+        setLocalitysrvUrl(storeLocalitysrvUrl ?? '');
+        setError(null);
+    }, [isDialogOpened, networkSettings, storeLocalitysrvUrl]);
+
+    const rateLimitKb = rateLimit ? Number(rateLimit) : null;
+    const downloadLimitMb = Number(downloadLimit);
+    const isValid =
+        (rateLimitKb === null ||
+            (Number.isInteger(rateLimitKb) && rateLimitKb > 0)) &&
+        Number.isInteger(downloadLimitMb) &&
+        downloadLimitMb >= 0;
+
+    async function handleSave() {
+        if (!isValid) return;
+        try {
+            await saveNetworkSettings({
+                maxBytesPerSecond:
+                    rateLimitKb === null ? null : rateLimitKb * KB,
+                metered,
+                meteredDownloadLimit: downloadLimitMb * MB,
+            });
+            await saveLocalitysrvUrl(localitysrvUrl.trim() || null);
+            $isNetworkSettingsDialogOpened.set(false);
+        } catch (error) {
+            setError(`${error}`);
+        }
+    }
+
+    return (
+        <Dialog
+            open={isDialogOpened}
+            onOpenChange={(open) => $isNetworkSettingsDialogOpened.set(open)}
+        >
+            <DialogContent>
+                <DialogHeader>
+                    <DialogTitle>Network Settings</DialogTitle>
+                    <DialogDescription>
+                        Limit the data downloaded over Tor, and where to
+                        search localities when Waku does not answer
+                    </DialogDescription>
+                </DialogHeader>
+
+                <Input
+                    value={rateLimit}
+                    type="number"
+                    min={1}
+                    label="Rate limit (KB/s)"
+                    placeholder="No limit"
+                    onChange={(e) => setRateLimit(e.target.value)}
+                />
+
+                <div className="flex items-center gap-x-2">
+                    <Checkbox
+                        id="network-metered"
+                        checked={metered}
+                        onCheckedChange={(checked) =>
+                            setMetered(checked === true)
+                        }
+                    />
+                    <Label htmlFor="network-metered">Metered connection</Label>
+                </div>
+
+                <Input
+                    value={downloadLimit}
+                    type="number"
+                    min={0}
+                    label="Confirm downloads larger than (MB)"
+                    disabled={!metered}
+                    onChange={(e) => setDownloadLimit(e.target.value)}
+                />
+
+                <Input
+                    value={localitysrvUrl}
+                    label="localitysrv onion URL"
+                    placeholder="Only search through Waku"
+                    onChange={(e) => setLocalitysrvUrl(e.target.value)}
+                />
+
+                {error && (
+                    <Typography variant="body2" color="secondary">
+                        {error}
+                    </Typography>
+                )}
+
+                <DialogFooter>
+                    <div className="text-right mt-4">
+                        <Button
+                            disabled={!isValid || !networkSettings}
+                            onClick={handleSave}
+                        >
+                            Save
+                        </Button>
+                    </div>
+                </DialogFooter>
+            </DialogContent>
+        </Dialog>
+    );
+}
@@ -16,6 +16,7 @@ import {
     searchLocalities,
 } from '../../service/searchService.ts';
 import { setStoreLocalities } from '../../stores/jsonStore.ts';
+import { $isNetworkSettingsDialogOpened } from '../../stores/networkStore.ts';
 import { $isWakuDialogOpened, $wakuStatus } from '../../stores/wakuStore.ts';
 
 interface LocalitySelectionStepProps {
@@ -75,7 +76,14 @@ export default function LocalitySelectionStep({
                     </Typography>
                 </div>
 
-                <div className="mb-3 flex justify-end">
+                <div className="mb-3 flex justify-end gap-x-2">
+                    <Badge
+                        variant="outlined"
+                        size="sm"
+                        onClick={() => $isNetworkSettingsDialogOpened.set(true)}
+                    >
+                        Settings
+                    </Badge>
                     <Badge
                         variant="outlined"
                         size="sm"
@@ -7,72 +7,95 @@ import {
     CardDescription,
     CardHeader,
     CardTitle,
+    Dialog,
+    DialogContent,
+    DialogDescription,
+    DialogFooter,
+    DialogHeader,
+    DialogTitle,
     Progress,
     Typography,
 } from '@nipsysdev/lsd-react';
-import { Channel, invoke } from '@tauri-apps/api/core';
 import { useCallback, useState } from 'react';
+import type { Locality } from '../../interfaces/localitysrv';
+import {
+    $mapDownloads,
+    DownloadStatus,
+    startMapDownload,
+    waitForMapDownload,
+} from '../../stores/downloadStore';
 import { $storeLocalities } from '../../stores/jsonStore';
+import { $isNetworkSettingsDialogOpened } from '../../stores/networkStore';
+
+interface MeteredConfirmation {
+    locality: Locality;
+    resolve: (confirmed: boolean) => void;
+}
 
 interface MapDownloadStepProps {
     onStepChange: (stepChange: number) => void;
     onSetupComplete?: () => void;
 }
 
-export type DownloadEvent =
-    | {
-          event: 'progress';
-          data: {
-              chunk_length: number;
-          };
-      }
-    | {
-          event: 'finished';
-      };
-
 export default function MapDownloadStep({
     onStepChange,
     onSetupComplete,
 }: MapDownloadStepProps) {
     const storeLocalities = useStore($storeLocalities);
-    const [downloadProgress, setDownloadProgress] = useState<
-        Record<string, number>
-    >({});
-    const [isDownloading, setIsDownloading] = useState(false);
-    const [downloadedCount, setDownloadedCount] = useState(0);
+    const downloads = useStore($mapDownloads);
+    const [isStarting, setIsStarting] = useState(false);
+    const [meteredConfirmation, setMeteredConfirmation] =
+        useState<MeteredConfirmation | null>(null);
+    const isDownloading =
+        isStarting ||
+        Object.values(downloads).some(
+            (download) => download.state === 'running',
+        );
+    const downloadedCount = storeLocalities.filter(
+        (locality) =>
+            downloads[locality.id.toString()]?.state === 'finished',
+    ).length;
+
+    const confirmMeteredDownload = useCallback(
+        (locality: Locality) =>
+            new Promise<boolean>((resolve) =>
+                setMeteredConfirmation({ locality, resolve }),
+            ),
+        [],
+    );
+
+    function closeMeteredConfirmation(confirmed: boolean) {
+        meteredConfirmation?.resolve(confirmed);
+        setMeteredConfirmation(null);
+    }
 
     const downloadMaps = useCallback(async () => {
         if (isDownloading) return;
 
-        setIsDownloading(true);
-        setDownloadedCount(0);
-        setDownloadProgress({});
+        setIsStarting(true);
 
         for (const locality of storeLocalities) {
-            const onEvent = new Channel<DownloadEvent>();
-
-            onEvent.onmessage = (message) => {
-                if (message.event === 'progress') {
-                    setDownloadProgress((prev) => {
-                        const currentProgress =
-                            prev[locality.id.toString()] || 0;
-                        return {
-                            ...prev,
-                            [locality.id.toString()]:
-                                currentProgress + message.data.chunk_length,
-                        };
-                    });
-                } else if (message.event === 'finished') {
-                    setDownloadedCount((prev) => prev + 1);
-                }
-            };
+            const localityId = locality.id.toString();
+            if ($mapDownloads.get()[localityId]?.state === 'finished') {
+                continue;
+            }
 
             try {
-                await invoke('download_map', {
-                    onionLink: locality.onion_link,
-                    localityId: locality.id.toString(),
-                    onEvent,
-                });
+                await startMapDownload(locality, false);
+                let download = await waitForMapDownload(localityId);
+                if (
+                    download.state === 'confirmation_required' &&
+                    (await confirmMeteredDownload(locality))
+                ) {
+                    await startMapDownload(locality, true);
+                    download = await waitForMapDownload(localityId);
+                }
+                if (download.state === 'failed') {
+                    console.error(
+                        `Failed to download map for ${locality.name}:`,
+                        download.error,
+                    );
+                }
             } catch (error) {
                 console.error(
                     `Failed to download map for ${locality.name}:`,
@@ -81,8 +104,8 @@ export default function MapDownloadStep({
             }
         }
 
-        setIsDownloading(false);
-    }, [storeLocalities, isDownloading]);
+        setIsStarting(false);
+    }, [storeLocalities, isDownloading, confirmMeteredDownload]);
 
     function bytesToMB(bytes: number, decimals: number = 2): string {
         const mb = bytes / (1024 * 1024);
@@ -94,6 +117,25 @@ export default function MapDownloadStep({
         return `${kb.toFixed(2)} KB`;
     }
 
+    function formatStatus(status: DownloadStatus): string {
+        if (status.phase !== 'downloading') {
+            return `${status.phase.charAt(0).toUpperCase()}${status.phase.slice(1)}…`;
+        }
+        const rate = `${bytesToKb(status.bytesPerSecond)}/s`;
+        return status.etaSeconds === null
+            ? rate
+            : `${rate}, ${Math.ceil(status.etaSeconds)} s left`;
+    }
+
+    function progressPercent(
+        status: DownloadStatus | null | undefined,
+        fileSize: number,
+    ): number {
+        if (!status) return 0;
+        const total = status.total ?? fileSize;
+        return total > 0 ? (status.received / total) * 100 : 0;
+    }
+
     return (
         <div className="flex flex-col gap-y-10 size-full">
             <div className="flex-auto flex flex-col">
@@ -113,7 +155,16 @@ export default function MapDownloadStep({
                             Downloaded: {downloadedCount}/
                             {storeLocalities.length}
                         </CardDescription>
-                        <CardAction>
+                        <CardAction className="flex gap-x-2">
+                            <Button
+                                size="sm"
+                                variant="outlined"
+                                onClick={() =>
+                                    $isNetworkSettingsDialogOpened.set(true)
+                                }
+                            >
+                                Settings
+                            </Button>
                             <Button
                                 size="sm"
                                 variant="outlined"
@@ -142,6 +193,16 @@ export default function MapDownloadStep({
                                     <Typography variant="body2">
                                         {locality.name}
                                     </Typography>
+                                    {downloads[locality.id.toString()]
+                                        ?.status && (
+                                        <Typography variant="body2">
+                                            {formatStatus(
+                                                downloads[
+                                                    locality.id.toString()
+                                                ].status!,
+                                            )}
+                                        </Typography>
+                                    )}
                                     <Typography variant="body2">
                                         {locality.file_size < 100000
                                             ? bytesToKb(locality.file_size)
@@ -149,15 +210,11 @@ export default function MapDownloadStep({
                                     </Typography>
                                 </div>
                                 <Progress
-                                    value={
-                                        locality.file_size > 0
-                                            ? ((downloadProgress[
-                                                  locality.id.toString()
-                                              ] || 0) /
-                                                  locality.file_size) *
-                                              100
-                                            : 0
-                                    }
+                                    value={progressPercent(
+                                        downloads[locality.id.toString()]
+                                            ?.status,
+                                        locality.file_size,
+                                    )}
                                 />
                             </div>
                         ))}
@@ -165,6 +222,39 @@ export default function MapDownloadStep({
                 </Card>
             </div>
 
+            <Dialog
+                open={meteredConfirmation !== null}
+                onOpenChange={(open) => {
+                    if (!open) closeMeteredConfirmation(false);
+                }}
+            >
+                <DialogContent>
+                    <DialogHeader>
+                        <DialogTitle>Metered connection</DialogTitle>
+                        <DialogDescription>
+                            {meteredConfirmation &&
+                                `The map of ${meteredConfirmation.locality.name} is ${bytesToMB(meteredConfirmation.locality.file_size)}, download it on a metered connection?`}
+                        </DialogDescription>
+                    </DialogHeader>
+
+                    <DialogFooter>
+                        <div className="flex justify-end gap-x-2 mt-4">
+                            <Button
+                                variant="outlined"
+                                onClick={() => closeMeteredConfirmation(false)}
+                            >
+                                Skip
+                            </Button>
+                            <Button
+                                onClick={() => closeMeteredConfirmation(true)}
+                            >
+                                Download
+                            </Button>
+                        </div>
+                    </DialogFooter>
+                </DialogContent>
+            </Dialog>
+
             <div className="flex justify-between">
                 <Button variant="outlined" onClick={() => onStepChange(-1)}>
                     Previous
@@ -11,10 +11,25 @@ export interface GroupChatMsg {
     text: string;
 }
 
+// Mirrors `MarkerKind` in the Rust backend. Police lines, closures and
+// threats are hazards that routing avoids and geofences warn about.
+export type MarkerKind =
+    | 'point'
+    | 'meeting_point'
+    | 'safe_zone'
+    | 'police_line'
+    | 'closure'
+    | 'threat';
+
 export interface Marker {
     latitude: number;
     longitude: number;
     name: string;
+    kind?: MarkerKind;
+    // Radius in meters of the area covered by the marker.
+    radius?: number;
+    // Routing penalty weight, left to the marker kind when unset.
+    penalty?: number;
 }
 
 export interface GroupMessage {
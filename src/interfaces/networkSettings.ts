@@ -0,0 +1,5 @@
+export interface NetworkSettings {
+    maxBytesPerSecond: number | null;
+    metered: boolean;
+    meteredDownloadLimit: number;
+}
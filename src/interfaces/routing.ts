@@ -0,0 +1,31 @@
+// Mirror the routing types of the Rust backend, see `compute_route`.
+
+export interface Coordinate {
+    latitude: number;
+    longitude: number;
+}
+
+export type Area =
+    | { type: 'circle'; center: Coordinate; radiusMeters: number }
+    | { type: 'polygon'; points: Coordinate[] };
+
+export interface AvoidanceZone {
+    area: Area;
+    // Cost multiplier added per meter travelled inside the zone, the zone
+    // being impassable when unset.
+    penalty?: number | null;
+}
+
+export type RouteResult =
+    | {
+          status: 'found';
+          data: {
+              path: Coordinate[];
+              distanceMeters: number;
+              // Part of the distance travelled inside penalized zones.
+              penalizedMeters: number;
+              // Whether the path follows the roads of the downloaded maps.
+              followsRoads: boolean;
+          };
+      }
+    | { status: 'noSafeRoute'; data: Record<string, never> };
@@ -1,3 +1,5 @@
+import { invoke } from '@tauri-apps/api/core';
+import { listen } from '@tauri-apps/api/event';
 import protobuf from 'protobufjs';
 import type { GroupChatMsg, GroupMessage, Marker } from '../interfaces/group';
 import { $wakuChatChannel } from '../stores/wakuStore';
@@ -12,7 +14,10 @@ export const newMarkerProtoBuilder = () =>
     new protobuf.Type('Marker')
         .add(new protobuf.Field('latitude', 1, 'float'))
         .add(new protobuf.Field('longitude', 2, 'float'))
-        .add(new protobuf.Field('name', 3, 'string'));
+        .add(new protobuf.Field('name', 3, 'string'))
+        .add(new protobuf.Field('kind', 4, 'string'))
+        .add(new protobuf.Field('radius', 5, 'float'))
+        .add(new protobuf.Field('penalty', 6, 'float'));
 
 export const newGroupMessageProtoBuilder = () =>
     new protobuf.Type('GroupMessage')
@@ -35,7 +40,28 @@ export async function sendGroupMessage(msg: GroupChatMsg) {
     await sendGroupMessageInternal(groupMessage);
 }
 
-export async function sendMarkerMessage(marker: Marker, senderId: string) {
+export interface Publication {
+    groupId: string;
+    marker: Marker;
+    publishAt: number;
+}
+
+// Markers go through the location privacy settings of the Rust backend,
+// which hands them back in a `location-publish` event once their delay is
+// over. Only markers from that event are sent over Waku.
+export async function publishMarker(groupId: string, marker: Marker) {
+    return invoke<Publication>('publish_marker', { groupId, marker });
+}
+
+export function listenForPublications(senderId: string) {
+    return listen<Publication>('location-publish', (event) =>
+        sendMarkerMessage(event.payload.marker, senderId).catch((error) =>
+            console.error('Failed to send marker:', error),
+        ),
+    );
+}
+
+async function sendMarkerMessage(marker: Marker, senderId: string) {
     const reliableChannel = $wakuChatChannel.get();
     if (!reliableChannel) return;
 
@@ -73,6 +99,9 @@ async function sendGroupMessageInternal(msg: GroupMessage) {
             latitude: marker.latitude,
             longitude: marker.longitude,
             name: marker.name,
+            kind: marker.kind,
+            radius: marker.radius,
+            penalty: marker.penalty,
         });
         contentBytes = markerBuilder.encode(markerProto).finish();
     }
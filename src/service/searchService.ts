@@ -1,3 +1,4 @@
+import { invoke } from '@tauri-apps/api/core';
 import type { IDecodedMessage } from '@waku/sdk';
 import { nanoid } from 'nanoid';
 import {
@@ -6,8 +7,38 @@ import {
     emptyLocalitySearchResponse,
     type LocalitySearchResponse,
 } from '../interfaces/localitysrv';
+import { loadLocalitysrvUrl } from '../stores/networkStore';
 import { $wakuLightNode, $wakuServerChannel } from '../stores/wakuStore';
 
+// localitysrv-waku never answers a request no peer relayed, nor tells when
+// it is down.
+const WAKU_SEARCH_TIMEOUT_MS = 30_000;
+
+function withTimeout<T>(promise: Promise<T>, ms: number): Promise<T> {
+    return new Promise<T>((resolve, reject) => {
+        const timeout = setTimeout(
+            () => reject(new Error(`No answer after ${ms / 1000} s`)),
+            ms,
+        );
+        promise.then(resolve, reject).finally(() => clearTimeout(timeout));
+    });
+}
+
+// Searches localitysrv over Tor when Waku fails. Only tried once the user
+// set the onion URL of localitysrv, since there is no default one.
+async function searchOverTor<T>(
+    command: string,
+    args: Record<string, unknown>,
+): Promise<T | null> {
+    try {
+        if (!(await loadLocalitysrvUrl())) return null;
+        return await invoke<T>(command, args);
+    } catch (error) {
+        console.error('Failed to search over Tor:', error);
+        return null;
+    }
+}
+
 export async function searchCountries(
     query: string,
     page: number = 1,
@@ -16,14 +47,15 @@ export async function searchCountries(
     const node = $wakuLightNode.get();
     const wakuServerChannel = $wakuServerChannel.get();
 
-    if (!node) {
-        console.error('Waku node has not been initialized yet');
-        return emptyCountrySearchResponse;
-    }
+    const searchArgs = { query, page, limit };
 
-    if (!wakuServerChannel) {
-        console.error('Server channel has not been initialized yet');
-        return emptyCountrySearchResponse;
+    if (!node || !wakuServerChannel) {
+        return (
+            (await searchOverTor<CountrySearchResponse>(
+                'search_countries',
+                searchArgs,
+            )) ?? emptyCountrySearchResponse
+        );
     }
 
     try {
@@ -33,7 +65,7 @@ export async function searchCountries(
         const queryId = nanoid();
 
         // Create a promise that will resolve when we receive a response
-        const resp = await new Promise<any>((resolve) => {
+        const search = new Promise<any>((resolve, reject) => {
             // Create a callback function for processing messages
             const callback = (wakuMessage: IDecodedMessage) => {
                 // Check if there is a payload on the message
@@ -79,17 +111,34 @@ export async function searchCountries(
                 .finish();
 
             // Send the search request using Light Push
-            node.lightPush.send(wakuServerChannel.encoder, {
-                payload: payload,
-            });
+            node.lightPush
+                .send(wakuServerChannel.encoder, {
+                    payload: payload,
+                })
+                .then((result) => {
+                    if (!result.successes.length) {
+                        reject(new Error('No Waku peer took the request'));
+                    }
+                }, reject);
 
             console.log('Country search request sent');
         });
-        node.filter.unsubscribe([wakuServerChannel.decoder]);
+
+        let resp: CountrySearchResponse;
+        try {
+            resp = await withTimeout(search, WAKU_SEARCH_TIMEOUT_MS);
+        } finally {
+            node.filter.unsubscribe([wakuServerChannel.decoder]);
+        }
         return resp;
     } catch (error) {
         console.error('Failed to search countries:', error);
-        return emptyCountrySearchResponse;
+        return (
+            (await searchOverTor<CountrySearchResponse>(
+                'search_countries',
+                searchArgs,
+            )) ?? emptyCountrySearchResponse
+        );
     }
 }
 
@@ -102,14 +151,15 @@ export async function searchLocalities(
     const node = $wakuLightNode.get();
     const wakuServerChannel = $wakuServerChannel.get();
 
-    if (!node) {
-        console.error('Waku node has not been initialized yet');
-        return emptyLocalitySearchResponse;
-    }
+    const searchArgs = { countryCode, query, page, limit };
 
-    if (!wakuServerChannel) {
-        console.error('Server channel has not been initialized yet');
-        return emptyLocalitySearchResponse;
+    if (!node || !wakuServerChannel) {
+        return (
+            (await searchOverTor<LocalitySearchResponse>(
+                'search_localities',
+                searchArgs,
+            )) ?? emptyLocalitySearchResponse
+        );
     }
 
     try {
@@ -119,7 +169,7 @@ export async function searchLocalities(
         const queryId = nanoid();
 
         // Create a promise that will resolve when we receive a response
-        const resp = await new Promise<any>((resolve) => {
+        const search = new Promise<any>((resolve, reject) => {
             // Create a callback function for processing messages
             const callback = (wakuMessage: IDecodedMessage) => {
                 // Check if there is a payload on the message
@@ -169,18 +219,35 @@ export async function searchLocalities(
                 .finish();
 
             // Send the search request using Light Push
-            node.lightPush.send(wakuServerChannel.encoder, {
-                payload: payload,
-            });
+            node.lightPush
+                .send(wakuServerChannel.encoder, {
+                    payload: payload,
+                })
+                .then((result) => {
+                    if (!result.successes.length) {
+                        reject(new Error('No Waku peer took the request'));
+                    }
+                }, reject);
 
             console.log('Locality search request sent');
         });
-        node.filter.unsubscribe([wakuServerChannel.decoder]);
+
+        let resp: LocalitySearchResponse;
+        try {
+            resp = await withTimeout(search, WAKU_SEARCH_TIMEOUT_MS);
+        } finally {
+            node.filter.unsubscribe([wakuServerChannel.decoder]);
+        }
 
         console.log('waku localities resp', resp);
         return resp;
     } catch (error) {
         console.error('Failed to search localities:', error);
-        return emptyLocalitySearchResponse;
+        return (
+            (await searchOverTor<LocalitySearchResponse>(
+                'search_localities',
+                searchArgs,
+            )) ?? emptyLocalitySearchResponse
+        );
     }
 }
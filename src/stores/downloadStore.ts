@@ -0,0 +1,146 @@
+import { invoke } from '@tauri-apps/api/core';
+import { listen } from '@tauri-apps/api/event';
+import { atom, onMount } from 'nanostores';
+import { Locality } from '../interfaces/localitysrv';
+
+export interface DownloadStatus {
+    phase: 'connecting' | 'downloading' | 'verifying' | 'finalizing';
+    received: number;
+    total: number | null;
+    bytesPerSecond: number;
+    etaSeconds: number | null;
+}
+
+export type MapDownload = {
+    localityId: string;
+    status: DownloadStatus | null;
+} & (
+    | { state: 'running' }
+    | { state: 'finished' }
+    | { state: 'failed'; error: string }
+    | { state: 'confirmation_required'; limit: number }
+);
+
+export type DownloadEvent =
+    | { event: 'progress'; data: DownloadStatus }
+    | { event: 'mirrorFailed'; data: { url: string; error: string } }
+    | { event: 'confirmationRequired'; data: { limit: number } }
+    | { event: 'failed'; data: { error: string } }
+    | { event: 'finished'; data: Record<string, never> };
+
+export type MapDownloadEvent = DownloadEvent & { localityId: string };
+
+// Downloads run in the Rust backend, which keeps them going while the
+// webview is suspended (but not if the OS suspends the app); this store
+// catches up with them when it comes back.
+export const $mapDownloads = atom<Record<string, MapDownload>>({});
+
+export async function refreshMapDownloads() {
+    const downloads = await invoke<MapDownload[]>('get_map_downloads');
+    $mapDownloads.set(
+        Object.fromEntries(
+            downloads.map((download) => [download.localityId, download]),
+        ),
+    );
+}
+
+export async function startMapDownload(
+    locality: Locality,
+    confirmMetered: boolean,
+) {
+    await invoke<MapDownload>('download_map', {
+        request: {
+            localityId: locality.id.toString(),
+            name: locality.name,
+            onionLink: locality.onion_link,
+            // localitysrv announces neither mirrors nor the hash of the
+            // maps yet, so only the service that answered is tried.
+            fileSize: Number(locality.file_size) || undefined,
+            confirmMetered,
+        },
+    });
+    // The events of the download may have arrived before the command
+    // returned, possibly up to its end, so the state returned when it
+    // started is stale: the backend's list has the latest one.
+    await refreshMapDownloads();
+}
+
+// Resolves once the download of the locality is no longer running.
+export function waitForMapDownload(localityId: string): Promise<MapDownload> {
+    return new Promise((resolve) => {
+        const check = (downloads: Record<string, MapDownload>) => {
+            const download = downloads[localityId];
+            if (download && download.state !== 'running') {
+                unsubscribe();
+                resolve(download);
+                return true;
+            }
+            return false;
+        };
+        const unsubscribe = $mapDownloads.listen(check);
+        check($mapDownloads.get());
+    });
+}
+
+function setDownload(download: MapDownload) {
+    $mapDownloads.set({
+        ...$mapDownloads.get(),
+        [download.localityId]: download,
+    });
+}
+
+function applyEvent(event: MapDownloadEvent) {
+    const current = $mapDownloads.get()[event.localityId];
+    const localityId = event.localityId;
+    const status = current?.status ?? null;
+
+    switch (event.event) {
+        case 'progress':
+            setDownload({ localityId, state: 'running', status: event.data });
+            break;
+        case 'mirrorFailed':
+            console.warn(
+                `Mirror ${event.data.url} failed, trying the next one:`,
+                event.data.error,
+            );
+            break;
+        case 'confirmationRequired':
+            setDownload({
+                localityId,
+                state: 'confirmation_required',
+                limit: event.data.limit,
+                status,
+            });
+            break;
+        case 'failed':
+            setDownload({
+                localityId,
+                state: 'failed',
+                error: event.data.error,
+                status,
+            });
+            break;
+        case 'finished':
+            setDownload({ localityId, state: 'finished', status });
+            break;
+    }
+}
+
+onMount($mapDownloads, () => {
+    const unlisten = listen('map-download', (event: { payload: MapDownloadEvent }) =>
+        applyEvent(event.payload),
+    );
+
+    const onVisibilityChange = () => {
+        if (document.visibilityState === 'visible') {
+            refreshMapDownloads().catch(console.error);
+        }
+    };
+    document.addEventListener('visibilitychange', onVisibilityChange);
+    refreshMapDownloads().catch(console.error);
+
+    return () => {
+        unlisten.then((unlisten) => unlisten());
+        document.removeEventListener('visibilitychange', onVisibilityChange);
+    };
+});
@@ -0,0 +1,13 @@
+import { invoke } from '@tauri-apps/api/core';
+import { atom } from 'nanostores';
+import type { Marker } from '../interfaces/group';
+
+// Markers of each group, sent and received. The Rust backend gets a copy
+// for routing around hazards and for geofencing.
+export const $groupMarkers = atom<Record<string, Marker[]>>({});
+
+export async function addGroupMarker(groupId: string, marker: Marker) {
+    const markers = [...($groupMarkers.get()[groupId] ?? []), marker];
+    $groupMarkers.set({ ...$groupMarkers.get(), [groupId]: markers });
+    await invoke('set_group_markers', { groupId, markers });
+}
@@ -0,0 +1,38 @@
+import { invoke } from '@tauri-apps/api/core';
+import { atom, onMount } from 'nanostores';
+import type { NetworkSettings } from '../interfaces/networkSettings';
+
+export const $isNetworkSettingsDialogOpened = atom(false);
+export const $networkSettings = atom<NetworkSettings | null>(null);
+
+export async function loadNetworkSettings() {
+    $networkSettings.set(
+        await invoke<NetworkSettings>('get_network_settings'),
+    );
+}
+
+export async function saveNetworkSettings(settings: NetworkSettings) {
+    await invoke('set_network_settings', { settings });
+    $networkSettings.set(settings);
+}
+
+onMount($networkSettings, () => {
+    loadNetworkSettings().catch(console.error);
+});
+
+export const $localitysrvUrl = atom<string | null>(null);
+
+export async function loadLocalitysrvUrl() {
+    const url = await invoke<string | null>('get_localitysrv_url');
+    $localitysrvUrl.set(url);
+    return url;
+}
+
+export async function saveLocalitysrvUrl(url: string | null) {
+    await invoke('set_localitysrv_url', { url });
+    return loadLocalitysrvUrl();
+}
+
+onMount($localitysrvUrl, () => {
+    loadLocalitysrvUrl().catch(console.error);
+});
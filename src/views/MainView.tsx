@@ -36,6 +36,7 @@ import {
     $isSheetOpened,
     $mapSelectedLocality,
 } from '../stores/mainViewStore.ts';
+import { $isNetworkSettingsDialogOpened } from '../stores/networkStore.ts';
 import {
     $isWakuDialogOpened,
     $wakuChatChannel,
@@ -77,6 +78,11 @@ export default function MainView() {
         $isGroupJoinDialogOpened.set(true);
     }
 
+    function openNetworkSettingsDialog() {
+        $isSheetOpened.set(false);
+        $isNetworkSettingsDialogOpened.set(true);
+    }
+
     return (
         <Sheet
             open={isSheetOpened}
@@ -204,6 +210,16 @@ export default function MainView() {
                         </ButtonGroup>
                     </div>
                 </div>
+
+                <div className="mt-auto">
+                    <Button
+                        size="sm"
+                        variant="outlined"
+                        onClick={openNetworkSettingsDialog}
+                    >
+                        Network settings
+                    </Button>
+                </div>
             </SheetContent>
         </Sheet>
     );